@@ -0,0 +1,144 @@
+//! The line editor of the REPL: tab completes keywords and global names, and the arrow keys or
+//! Ctrl-R recall earlier lines, those of past sessions included.
+//!
+//! Editing itself is left to rustyline. Lines are still recorded by [`History`], whose file
+//! outlives the session and backs `:history`.
+
+use std::io;
+
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+
+use crate::history::History;
+
+/// What reading a line came to.
+pub enum Input {
+    Line(String),
+    // Ctrl-C, which abandons the entry being typed.
+    Interrupted,
+    // Ctrl-D, or the end of piped input.
+    Closed,
+}
+
+pub struct LineEditor {
+    editor: Editor<LoxHelper, DefaultHistory>,
+    history: History,
+}
+
+impl LineEditor {
+    /// An editor recalling the lines of the history file.
+    pub fn new() -> io::Result<Self> {
+        let mut editor = Editor::new().map_err(into_io_error)?;
+        editor.set_helper(Some(LoxHelper::default()));
+        let history = History::load();
+        for line in history.lines() {
+            editor.add_history_entry(line).map_err(into_io_error)?;
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// Reads a line, completing names from `globals`.
+    pub fn read_line<'a>(
+        &mut self,
+        prompt: &str,
+        globals: impl IntoIterator<Item = &'a str>,
+    ) -> io::Result<Input> {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.globals = globals.into_iter().map(String::from).collect();
+        }
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Input::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::Closed),
+            Err(error) => Err(into_io_error(error)),
+        }
+    }
+
+    /// Records a line for recall, in this session and later ones. Blank lines are skipped.
+    pub fn remember(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        self.editor
+            .add_history_entry(line.trim_end())
+            .map_err(into_io_error)?;
+        self.history.push(line)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+}
+
+fn into_io_error(error: ReadlineError) -> io::Error {
+    match error {
+        ReadlineError::Io(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+/// Hooks [`compiler::complete`] into the editor.
+#[derive(Default)]
+struct LoxHelper {
+    // Names defined in the VM when the line started.
+    globals: Vec<String>,
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let typed = &line[..pos];
+        let candidates = compiler::complete(typed, self.globals.iter().map(String::as_str));
+        let start = typed
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            .len();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(line: &str, pos: usize) -> (usize, Vec<String>) {
+        let helper = LoxHelper {
+            globals: vec!["value".into(), "variance".into()],
+        };
+        let history = DefaultHistory::new();
+        helper.complete(line, pos, &Context::new(&history)).unwrap()
+    }
+
+    #[test]
+    fn completes_the_word_before_the_cursor() {
+        assert_eq!(
+            complete("print va", 8),
+            (6, vec!["value".into(), "var".into(), "variance".into()])
+        );
+        assert_eq!(complete("print va + 1;", 8).0, 6);
+        assert_eq!(complete("whi", 3), (0, vec!["while".into()]));
+    }
+
+    #[test]
+    fn completes_nothing_outside_names() {
+        assert_eq!(complete("print 1", 7).1, Vec::<String>::new());
+        assert_eq!(complete("print (", 7).1, Vec::<String>::new());
+    }
+}
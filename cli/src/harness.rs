@@ -0,0 +1,203 @@
+//! `ruslox test`: runs annotated scripts and checks what they print and report.
+//!
+//! Each script states its expectations in comments. `// expect: <text>` is a line the script
+//! prints, in order. `// error: <text>` is a diagnostic reported on the line of the comment,
+//! where the text is either its code, like `E1005`, or a part of its message. Errors nobody
+//! expected fail the script; warnings only need to match if they are expected.
+//!
+//! Scripts run in a child `ruslox` process, so a crashing script can't take the runner down.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const EXPECT_MARKER: &str = "// expect: ";
+const ERROR_MARKER: &str = "// error: ";
+
+struct Expectations {
+    output: Vec<String>,
+    // Line of the comment and the code or message fragment.
+    errors: Vec<(usize, String)>,
+}
+
+struct Diagnostic {
+    severity: String,
+    code: Option<String>,
+    message: String,
+    line: Option<usize>,
+}
+
+impl Diagnostic {
+    fn matches(&self, line: usize, text: &str) -> bool {
+        self.line == Some(line)
+            && (self.code.as_deref() == Some(text) || self.message.contains(text))
+    }
+}
+
+/// Runs every `.lox` file under `path`, or `path` itself if it is a file, and prints a summary.
+/// The scripts run with `options` besides their own. Returns whether all of them passed.
+pub fn run_tests(path: impl AsRef<Path>, options: &[String]) -> io::Result<bool> {
+    let mut scripts = Vec::new();
+    discover(path.as_ref(), &mut scripts)?;
+    scripts.sort();
+
+    let mut failed = 0;
+    for script in &scripts {
+        let failures = run_test(script, options)?;
+        match failures.is_empty() {
+            true => println!("PASS {}", script.display()),
+            false => {
+                failed += 1;
+                println!("FAIL {}", script.display());
+                for failure in failures {
+                    println!("    {}", failure);
+                }
+            }
+        }
+    }
+    println!();
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    Ok(failed == 0)
+}
+
+fn discover(path: &Path, scripts: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        scripts.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            discover(&path, scripts)?;
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            scripts.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs one script, describing each way it fell short of its expectations.
+fn run_test(script: &Path, options: &[String]) -> io::Result<Vec<String>> {
+    let expectations = expectations(&fs::read_to_string(script)?);
+    let output = Command::new(env::current_exe()?)
+        .args(options)
+        .args(["--color=never", "--message-format=json", "run"])
+        .arg(script)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut diagnostics: Vec<_> = stderr.lines().filter_map(diagnostic).collect();
+
+    let mut failures = Vec::new();
+    let printed: Vec<_> = stdout.lines().collect();
+    for (index, expected) in expectations.output.iter().enumerate() {
+        match printed.get(index) {
+            Some(&actual) if actual == expected => {}
+            Some(actual) => failures.push(format!(
+                "output line {}: expected `{}`, got `{}`",
+                index + 1,
+                expected,
+                actual
+            )),
+            None => failures.push(format!(
+                "output line {}: expected `{}`, got nothing",
+                index + 1,
+                expected
+            )),
+        }
+    }
+    for unexpected in printed.iter().skip(expectations.output.len()) {
+        failures.push(format!("unexpected output `{}`", unexpected));
+    }
+
+    for (line, text) in &expectations.errors {
+        match diagnostics
+            .iter()
+            .position(|diagnostic| diagnostic.matches(*line, text))
+        {
+            Some(index) => {
+                diagnostics.remove(index);
+            }
+            None => failures.push(format!("line {}: expected error `{}`", line, text)),
+        }
+    }
+    for diagnostic in diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == "error")
+    {
+        let location = diagnostic
+            .line
+            .map_or_else(String::new, |line| format!("line {}: ", line));
+        failures.push(format!(
+            "{}unexpected error[{}]: {}",
+            location,
+            diagnostic.code.as_deref().unwrap_or("?"),
+            diagnostic.message
+        ));
+    }
+    Ok(failures)
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations {
+        output: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (index, line) in source.lines().enumerate() {
+        if let Some((_, expected)) = line.split_once(EXPECT_MARKER) {
+            expectations.output.push(expected.trim_end().into());
+        } else if let Some((_, expected)) = line.split_once(ERROR_MARKER) {
+            expectations
+                .errors
+                .push((index + 1, expected.trim().into()));
+        }
+    }
+    expectations
+}
+
+/// Reads back a diagnostic printed with `--message-format=json`. Only the fields the runner
+/// compares are extracted, from the first label for the line.
+fn diagnostic(json: &str) -> Option<Diagnostic> {
+    let labels = json.find(r#""labels":["#)?;
+    Some(Diagnostic {
+        severity: string_field(json, "severity")?,
+        code: string_field(json, "code"),
+        message: string_field(json, "message")?,
+        line: json[labels..]
+            .find(r#""line":"#)
+            .map(|start| &json[labels + start + r#""line":"#.len()..])
+            .and_then(|rest| {
+                let end = rest.find(|c: char| !c.is_ascii_digit())?;
+                rest[..end].parse().ok()
+            }),
+    })
+}
+
+/// The first string value of `key`. Quotes within strings are always escaped, so the key can't
+/// be found inside another value.
+fn string_field(json: &str, key: &str) -> Option<String> {
+    let marker = format!(r#""{}":""#, key);
+    let start = json.find(&marker)? + marker.len();
+
+    let mut value = String::new();
+    let mut characters = json[start..].chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => return Some(value),
+            '\\' => match characters.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = characters.by_ref().take(4).collect();
+                    value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                escaped => value.push(escaped),
+            },
+            character => value.push(character),
+        }
+    }
+    None
+}
@@ -0,0 +1,80 @@
+//! Lines entered in the REPL, kept in a file so they survive the session.
+//!
+//! The file holds one line per entry, oldest first, like the histories of readline-based
+//! shells. Lines are appended as soon as they are entered, so an interrupted session loses
+//! nothing.
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+// Overrides where history is kept. Set it empty to keep no history.
+const HISTORY_VARIABLE: &str = "RUSLOX_HISTORY";
+const HISTORY_FILE: &str = ".ruslox_history";
+// Older lines are dropped from the file when it is loaded.
+const MAX_LINES: usize = 1000;
+
+pub struct History {
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+}
+
+impl History {
+    /// Loads the history file, or starts an empty history if there is none yet.
+    pub fn load() -> Self {
+        let path = history_path();
+        let mut lines: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| content.lines().map(String::from).collect())
+            .unwrap_or_default();
+
+        if lines.len() > MAX_LINES {
+            lines.drain(..lines.len() - MAX_LINES);
+            if let Some(path) = &path {
+                // Failing to trim the file only costs disk space.
+                let _ = fs::write(
+                    path,
+                    lines
+                        .iter()
+                        .map(|line| format!("{}\n", line))
+                        .collect::<String>(),
+                );
+            }
+        }
+        Self { path, lines }
+    }
+
+    /// Records a line, unless it is blank or repeats the previous one.
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end();
+        if line.trim().is_empty() || self.lines.last().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+        self.lines.push(line.into());
+
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", line)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(HISTORY_VARIABLE) {
+        return (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
@@ -0,0 +1,473 @@
+//! `ruslox lsp`: a language server speaking the language server protocol over standard input
+//! and output, for editors to drive.
+//!
+//! It reports the diagnostics of the compiler, lints included, whenever a document is opened or
+//! saved, jumps from a variable to its declaration, and colors tokens as the scanner classifies
+//! them. Documents are kept whole, so editors send their full text on every change.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+    ptr,
+};
+
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use compiler::{CompileOutput, LintRules, Token, TokenCategory};
+use shared::{error::ErrorItem, line::LineIndex};
+
+use self::json::Json;
+
+mod json;
+
+// Error codes of JSON-RPC.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// Kinds of semantic tokens, in the order of the legend sent to the editor.
+const TOKEN_TYPES: [&str; 6] = [
+    "keyword", "variable", "string", "number", "operator", "comment",
+];
+
+struct Server {
+    rules: LintRules,
+    // Text of each open document by URI.
+    documents: HashMap<String, String>,
+    shut_down: bool,
+}
+
+/// Serves one editor until it sends `exit`. Returns whether it asked to shut down first, as a
+/// clean exit requires.
+pub fn serve(rules: LintRules) -> io::Result<bool> {
+    let mut server = Server {
+        rules,
+        documents: HashMap::new(),
+        shut_down: false,
+    };
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout().lock();
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                let response = failure(Json::Null, PARSE_ERROR, error);
+                write_message(&mut output, &response)?;
+                continue;
+            }
+        };
+        let method = message.get("method").as_str().unwrap_or_default();
+        if method == "exit" {
+            return Ok(server.shut_down);
+        }
+        let params = message.get("params");
+        let id = message.get("id");
+        let mut notifications = Vec::new();
+        let result = server.handle(method, params, &mut notifications);
+        for notification in notifications {
+            write_message(&mut output, &notification)?;
+        }
+        // Notifications have no id and get no response.
+        if *id != Json::Null {
+            let response = match result {
+                Ok(result) => Json::object([
+                    ("jsonrpc", "2.0".into()),
+                    ("id", id.clone()),
+                    ("result", result),
+                ]),
+                Err((code, message)) => failure(id.clone(), code, message),
+            };
+            write_message(&mut output, &response)?;
+        }
+    }
+    Ok(server.shut_down)
+}
+
+impl Server {
+    /// Handles a request or notification, queueing the notifications it sends back.
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Json,
+        notifications: &mut Vec<Json>,
+    ) -> Result<Json, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        match (method, uri) {
+            ("initialize", _) => Ok(capabilities()),
+            ("shutdown", _) => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.get("textDocument").get("text").as_str();
+                self.documents
+                    .insert(uri.into(), text.unwrap_or_default().into());
+                notifications.push(self.diagnostics(uri));
+                Ok(Json::Null)
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // With full synchronization, the last change holds the whole text.
+                if let Json::Array(changes) = params.get("contentChanges") {
+                    if let Some(text) = changes
+                        .last()
+                        .and_then(|change| change.get("text").as_str())
+                    {
+                        self.documents.insert(uri.into(), text.into());
+                    }
+                }
+                Ok(Json::Null)
+            }
+            ("textDocument/didSave", Some(uri)) => {
+                if let Some(text) = params.get("text").as_str() {
+                    self.documents.insert(uri.into(), text.into());
+                }
+                notifications.push(self.diagnostics(uri));
+                Ok(Json::Null)
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                notifications.push(publish(uri, Vec::new()));
+                Ok(Json::Null)
+            }
+            ("textDocument/definition", Some(uri)) => {
+                let text = self.document(uri)?;
+                let position = params.get("position");
+                let (Some(line), Some(character)) = (
+                    position.get("line").as_usize(),
+                    position.get("character").as_usize(),
+                ) else {
+                    return Err((INVALID_PARAMS, "missing position".into()));
+                };
+                let lines = LineIndex::new(text);
+                let offset = offset(text, &lines, line, character);
+                Ok(match definition(text, offset) {
+                    Some(span) => {
+                        Json::object([("uri", uri.into()), ("range", range(text, &lines, &span))])
+                    }
+                    None => Json::Null,
+                })
+            }
+            ("textDocument/semanticTokens/full", Some(uri)) => {
+                let text = self.document(uri)?;
+                Ok(Json::object([("data", semantic_tokens(text))]))
+            }
+            (_, None) if method.starts_with("textDocument/") => {
+                Err((INVALID_PARAMS, "missing text document".into()))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method `{}`", method))),
+        }
+    }
+
+    fn document(&self, uri: &str) -> Result<&str, (i32, String)> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, format!("`{}` is not open", uri)))
+    }
+
+    /// A `publishDiagnostics` notification with the errors and warnings of a document.
+    fn diagnostics(&self, uri: &str) -> Json {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let items = match compiler::compile_with(0, text, &self.rules) {
+            Ok(CompileOutput { warnings, .. }) => warnings.into_errors().diagnostics().to_vec(),
+            Err(error) => error.diagnostics().to_vec(),
+        };
+        let lines = LineIndex::new(text);
+        let diagnostics = items
+            .iter()
+            .map(|item| diagnostic(text, &lines, item))
+            .collect();
+        publish(uri, diagnostics)
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([(
+        "capabilities",
+        Json::object([
+            (
+                "textDocumentSync",
+                Json::object([
+                    ("openClose", Json::Bool(true)),
+                    // Full text on every change.
+                    ("change", 1.into()),
+                    ("save", Json::object([("includeText", Json::Bool(true))])),
+                ]),
+            ),
+            ("definitionProvider", Json::Bool(true)),
+            (
+                "semanticTokensProvider",
+                Json::object([
+                    (
+                        "legend",
+                        Json::object([
+                            (
+                                "tokenTypes",
+                                Json::Array(TOKEN_TYPES.into_iter().map(Json::from).collect()),
+                            ),
+                            ("tokenModifiers", Json::Array(Vec::new())),
+                        ]),
+                    ),
+                    ("full", Json::Bool(true)),
+                ]),
+            ),
+        ]),
+    )])
+}
+
+fn diagnostic(text: &str, lines: &LineIndex, item: &ErrorItem) -> Json {
+    let severity: usize = match item.severity {
+        Severity::Bug | Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+        Severity::Help => 4,
+    };
+    let span = item
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)
+        .or(item.labels.first())
+        .map_or(0..0, |label| label.range.clone());
+    // The editor shows the range, so the messages of the labels are folded into the text.
+    let mut message = item.message.clone();
+    for label in item.labels.iter().filter(|label| !label.message.is_empty()) {
+        message.push('\n');
+        message.push_str(&label.message);
+    }
+    let code = item.code.as_deref().map_or(Json::Null, Json::from);
+    Json::object([
+        ("range", range(text, lines, &span)),
+        ("severity", severity.into()),
+        ("code", code),
+        ("source", "ruslox".into()),
+        ("message", Json::String(message)),
+    ])
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([
+                ("uri", uri.into()),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn failure(id: Json, code: i32, message: impl Into<String>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code as f64)),
+                ("message", Json::String(message.into())),
+            ]),
+        ),
+    ])
+}
+
+/// Span of the declaration of the variable at `offset`, as the resolver links them. Documents
+/// which don't parse or resolve have no definitions.
+fn definition(text: &str, offset: usize) -> Option<Range<usize>> {
+    let scanned = compiler::scan(0, text).ok()?;
+    let parsed = compiler::parse(0, &scanned).ok()?;
+    let resolutions = compiler::resolve(0, &parsed, &LintRules::default()).ok()?;
+    let name =
+        scanned
+            .tokens
+            .iter()
+            .zip(&scanned.positions)
+            .find_map(|(token, span)| match token {
+                Token::Identifier(name) if span.contains(&offset) || span.end == offset => {
+                    Some(name)
+                }
+                _ => None,
+            })?;
+    let declaration = resolutions.declaration(name)?;
+    scanned
+        .tokens
+        .iter()
+        .position(|token| matches!(token, Token::Identifier(name) if ptr::eq(name, declaration)))
+        .map(|index| scanned.positions[index].clone())
+}
+
+/// The tokens of the text, encoded relative to each other as the protocol asks. Tokens spanning
+/// several lines are split at line breaks.
+fn semantic_tokens(text: &str) -> Json {
+    let lines = LineIndex::new(text);
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for token in compiler::classify(text) {
+        let source = &text[token.span.clone()];
+        let kind = match token.category {
+            TokenCategory::Keyword => "keyword",
+            TokenCategory::Identifier => "variable",
+            TokenCategory::Literal if source.starts_with('"') => "string",
+            TokenCategory::Literal if source.starts_with(|c: char| c.is_ascii_digit()) => "number",
+            // `true`, `false` and `nil`.
+            TokenCategory::Literal => "keyword",
+            TokenCategory::Operator => "operator",
+            TokenCategory::Comment => "comment",
+            TokenCategory::Punctuation | TokenCategory::Error => continue,
+        };
+        let kind = TOKEN_TYPES.iter().position(|&name| name == kind).unwrap();
+        let mut start = token.span.start;
+        for piece in source.split('\n') {
+            let length = utf16_len(piece.trim_end_matches('\r'));
+            let (line, character) = position(text, &lines, start);
+            start += piece.len() + 1;
+            if length == 0 {
+                continue;
+            }
+            let delta_start = match line == previous_line {
+                true => character - previous_start,
+                false => character,
+            };
+            data.extend([line - previous_line, delta_start, length, kind, 0]);
+            (previous_line, previous_start) = (line, character);
+        }
+    }
+    Json::Array(data.into_iter().map(Json::from).collect())
+}
+
+/// The 0-based line and UTF-16 column of a byte offset, as the protocol counts them. An offset
+/// inside a character counts as its start.
+fn position(text: &str, lines: &LineIndex, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let (line, _) = lines.line_column(offset);
+    let start = lines.starts()[line - 1];
+    (line - 1, utf16_len(&text[start..offset]))
+}
+
+/// The byte offset of a 0-based line and UTF-16 column, clamped to the text.
+fn offset(text: &str, lines: &LineIndex, line: usize, character: usize) -> usize {
+    let Some(&start) = lines.starts().get(line) else {
+        return text.len();
+    };
+    let mut units = 0;
+    for (index, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + index;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, lines: &LineIndex, span: &Range<usize>) -> Json {
+    let point = |offset| {
+        let (line, character) = position(text, lines, offset);
+        Json::object([("line", line.into()), ("character", character.into())])
+    };
+    Json::object([("start", point(span.start)), ("end", point(span.end))])
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The body of the next message, or `None` once the editor closes the stream.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(uri: &str, text: &str) -> Server {
+        Server {
+            rules: LintRules::default(),
+            documents: HashMap::from([(uri.into(), text.into())]),
+            shut_down: false,
+        }
+    }
+
+    #[test]
+    fn diagnoses_non_ascii_characters() {
+        let published = server("file:///a.lox", "print é;").diagnostics("file:///a.lox");
+        let Json::Array(diagnostics) = published.get("params").get("diagnostics") else {
+            panic!("no diagnostics in {}", published);
+        };
+        let range = diagnostics[0].get("range");
+        assert_eq!(diagnostics[0].get("code").as_str(), Some("E0002"));
+        assert_eq!(range.get("start").get("character").as_usize(), Some(6));
+        assert_eq!(range.get("end").get("character").as_usize(), Some(7));
+    }
+
+    /// The declaration found for the `n`th occurrence of `name`, as the `n`th one overall.
+    fn declared_at(text: &str, name: &str, n: usize) -> Option<usize> {
+        let offset = text.match_indices(name).nth(n).unwrap().0;
+        let span = definition(text, offset)?;
+        text.match_indices(name)
+            .position(|(start, _)| start == span.start)
+    }
+
+    #[test]
+    fn finds_declarations_in_scope() {
+        let text = "var x = 0; for (var x = 1; x < 2; x = x + 1) print x; { var x; x; } print x;";
+        // Uses within the loop refer to the variable of the loop.
+        for n in 2..6 {
+            assert_eq!(declared_at(text, "x", n), Some(1));
+        }
+        assert_eq!(declared_at(text, "x", 7), Some(6));
+        assert_eq!(declared_at(text, "x", 8), Some(0));
+        assert_eq!(declared_at(text, "x", 0), Some(0));
+    }
+
+    #[test]
+    fn finds_globals_declared_later() {
+        let text = "while (false) print g; var g = 1;";
+        assert_eq!(declared_at(text, "g", 0), Some(1));
+        assert_eq!(declared_at("print h;", "h", 0), None);
+    }
+
+    #[test]
+    fn positions_inside_characters_snap_to_their_start() {
+        for (text, offset, expected) in [("aé", 2, (0, 1)), ("aé\nb", 5, (1, 1))] {
+            assert_eq!(position(text, &LineIndex::new(text), offset), expected);
+        }
+    }
+}
@@ -1,63 +1,946 @@
 use std::{
+    cell::RefCell,
     env, fs,
-    io::{self, Write},
+    io::{self, Read, Write},
     path::Path,
+    process,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use runtime::vm::VirtualMachine;
-use shared::error::SourceFileManager;
+mod editor;
+mod harness;
+mod history;
+mod lsp;
+
+use compiler::{CompileOutput, LintRules, ReplSession, Rule};
+use editor::{Input, LineEditor};
+use runtime::vm::{Instrument, VirtualMachine};
+use shared::{
+    chunk::Chunk,
+    error::{
+        explain, ColorMode, EmitOptions, ErrorItem, InterpretError, MessageFormat,
+        SourceFileManager, Warnings,
+    },
+};
 
 const REPL_SIGN: &str = ">>";
+const CONTINUATION_SIGN: &str = "..";
+// Lists the lines entered so far, in this session and earlier ones.
+const HISTORY_COMMAND: &str = ":history";
+// Lists the objects on the heap and what refers to them.
+const HEAP_COMMAND: &str = ":heap";
+// Reads the script from standard input in place of a file.
+const STDIN_PATH: &str = "-";
+// How often `watch` looks at the modification time of the script.
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Options accepted anywhere before the script, so they don't multiply the commands below.
+struct Options {
+    emit: EmitOptions,
+    rules: LintRules,
+    trace: bool,
+    strict_arithmetic: bool,
+    strict_compat: bool,
+    stack_size: Option<usize>,
+    seed: Option<u64>,
+    // Scripts run before the main one or the REPL, so their globals are available to it.
+    preloads: Vec<String>,
+    // The options changing how scripts compile and run, as given, for `test` to pass on to
+    // the scripts it runs.
+    script_options: Vec<String>,
+}
+
+// Options `test` passes on. Output options are its own, and preloads and tracing would print
+// into the output it checks.
+const SCRIPT_OPTIONS: [&str; 11] = [
+    "-W",
+    "--warn",
+    "-A",
+    "--allow",
+    "--deny-warnings",
+    "--strict",
+    "--error-limit",
+    "--strict-arithmetic",
+    "--strict-compat",
+    "--seed",
+    "--stack-size",
+];
+
+impl Options {
+    fn new() -> Self {
+        Self {
+            emit: EmitOptions::default(),
+            rules: LintRules::default(),
+            trace: false,
+            strict_arithmetic: false,
+            strict_compat: false,
+            stack_size: None,
+            seed: None,
+            preloads: Vec::new(),
+            script_options: Vec::new(),
+        }
+    }
+
+    /// Applies `arg` if it is an option, taking its value from `rest` unless it was given as
+    /// `--name=value`. Returns whether it was an option.
+    fn apply<'a>(
+        &mut self,
+        arg: &'a str,
+        rest: &mut impl Iterator<Item = &'a str>,
+    ) -> Result<bool, String> {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg, None),
+        };
+        let mut taken = None;
+        let mut value = || {
+            let value = inline
+                .or_else(|| rest.next())
+                .ok_or(format!("{} needs a value", name))?;
+            taken = Some(value);
+            Ok::<_, String>(value)
+        };
+        match name {
+            "--message-format" => {
+                self.emit.format = match value()? {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => return Err(format!("unknown message format `{}`", other)),
+                }
+            }
+            "--color" => {
+                self.emit.color = match value()? {
+                    "always" => ColorMode::Always,
+                    "auto" => ColorMode::Auto,
+                    "never" => ColorMode::Never,
+                    other => return Err(format!("unknown color mode `{}`", other)),
+                }
+            }
+            "--deny-warnings" => self.rules.deny_warnings(),
+            "--strict" => self.rules.strict([]),
+            "--error-limit" => {
+                let limit = value()?;
+                match limit.parse() {
+                    Ok(limit) if limit > 0 => self.rules.set_error_limit(limit),
+                    _ => {
+                        return Err(format!(
+                            "error limit must be a positive number, not `{}`",
+                            limit
+                        ))
+                    }
+                }
+            }
+            "-W" | "--warn" | "-A" | "--allow" => {
+                let lint = value()?;
+                let warn = matches!(name, "-W" | "--warn");
+                match (lint, Rule::from_name(lint)) {
+                    ("warnings", _) => {
+                        for rule in Rule::ALL {
+                            self.set_rule(rule, warn);
+                        }
+                    }
+                    (_, Some(rule)) => self.set_rule(rule, warn),
+                    (_, None) => return Err(format!("unknown lint `{}`", lint)),
+                }
+            }
+            "--trace" => self.trace = true,
+            "--strict-arithmetic" => self.strict_arithmetic = true,
+            "--strict-compat" => self.strict_compat = true,
+            "--preload" => self.preloads.push(value()?.into()),
+            "--stack-size" => {
+                let size = value()?;
+                match size.parse() {
+                    Ok(size) if (1..=VirtualMachine::MAX_STACK_SIZE).contains(&size) => {
+                        self.stack_size = Some(size)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "stack size must be between 1 and {}, not `{}`",
+                            VirtualMachine::MAX_STACK_SIZE,
+                            size
+                        ))
+                    }
+                }
+            }
+            "--seed" => {
+                let seed = value()?;
+                match seed.parse::<i64>() {
+                    Ok(seed) => self.seed = Some(seed as u64),
+                    Err(_) => return Err(format!("seed must be a whole number, not `{}`", seed)),
+                }
+            }
+            _ => return Ok(false),
+        }
+        if SCRIPT_OPTIONS.contains(&name) {
+            self.script_options.push(name.into());
+            self.script_options.extend(taken.map(String::from));
+        }
+        Ok(true)
+    }
+
+    fn set_rule(&mut self, rule: Rule, enabled: bool) {
+        match enabled {
+            true => self.rules.enable(rule),
+            false => self.rules.disable(rule),
+        }
+    }
+
+    /// A VM configured by the options.
+    fn virtual_machine(&self) -> VirtualMachine {
+        let mut builder = VirtualMachine::builder()
+            .trace(self.trace)
+            .strict_arithmetic(self.strict_arithmetic)
+            .strict_compat(self.strict_compat);
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        match self.stack_size {
+            Some(size) => builder.stack_size(size).build(),
+            None => builder.build(),
+        }
+    }
+
+    /// Runs the preloaded scripts in the VM, in order. Returns whether all of them succeeded;
+    /// the first failure stops the rest.
+    fn preload(&self, vm: &mut VirtualMachine) -> io::Result<bool> {
+        for path in &self.preloads {
+            if !run_file(vm, path, self)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// What to do, as named by the subcommand. Scripts are `-` for standard input.
+enum Command<'a> {
+    Repl,
+    Run(&'a str, Vec<&'a str>),
+    Eval(&'a str, Vec<&'a str>),
+    Watch(&'a str, Vec<&'a str>),
+    Check(&'a str),
+    Compile(&'a str),
+    Disassemble(&'a str),
+    Ast(&'a str),
+    Format(&'a str),
+    Lint(&'a str),
+    Test(&'a str),
+    Bench(usize, &'a str),
+    Explain(&'a str),
+    Lsp,
+    Help,
+}
+
+/// The canonical name of a subcommand. The flag spellings are kept from before subcommands
+/// existed.
+fn subcommand(arg: &str) -> Option<&'static str> {
+    Some(match arg {
+        "repl" => "repl",
+        "run" => "run",
+        "eval" | "-e" | "--eval" => "eval",
+        "watch" | "--watch" => "watch",
+        "check" | "--check" => "check",
+        "compile" | "--compile" => "compile",
+        "disasm" | "--disassemble" => "disasm",
+        "ast" | "--ast" => "ast",
+        "fmt" => "fmt",
+        "lint" => "lint",
+        "test" => "test",
+        "bench" | "--bench" => "bench",
+        "explain" | "--explain" => "explain",
+        "lsp" => "lsp",
+        "help" | "-h" | "--help" => "help",
+        _ => return None,
+    })
+}
+
+/// Parses the command line. Options may come before or after the subcommand, but everything
+/// after the script of `run`, `eval` and `watch` is left to the script.
+fn parse_args<'a>(args: &[&'a str]) -> Result<(Options, Command<'a>), String> {
+    let mut options = Options::new();
+    let mut name = None;
+    let mut operands = Vec::new();
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        if matches!(name, Some("run" | "eval" | "watch")) && !operands.is_empty() {
+            operands.push(arg);
+            operands.extend(args);
+            break;
+        }
+        if options.apply(arg, &mut args)? {
+            continue;
+        }
+        match (name, subcommand(arg)) {
+            (None, Some(subcommand)) => name = Some(subcommand),
+            // Asking for help after a subcommand, as in `ruslox fmt --help`.
+            (Some(_), Some("help")) if arg.starts_with('-') => name = Some("help"),
+            // A script given without a subcommand is run.
+            (None, None) if arg == STDIN_PATH || !arg.starts_with('-') => {
+                name = Some("run");
+                operands.push(arg);
+            }
+            _ if arg.starts_with('-') && arg != STDIN_PATH => {
+                return Err(format!("unknown option `{}`", arg))
+            }
+            _ => operands.push(arg),
+        }
+    }
+
+    let name = name.unwrap_or("repl");
+    let command = match (name, &operands[..]) {
+        ("repl", []) => Command::Repl,
+        ("run", [path, args @ ..]) => Command::Run(path, args.to_vec()),
+        ("eval", [code, args @ ..]) => Command::Eval(code, args.to_vec()),
+        ("watch", [path, args @ ..]) if *path != STDIN_PATH => Command::Watch(path, args.to_vec()),
+        ("check", [path]) => Command::Check(path),
+        ("compile", [path]) => Command::Compile(path),
+        ("disasm", [path]) => Command::Disassemble(path),
+        ("ast", [path]) => Command::Ast(path),
+        ("fmt", [path]) => Command::Format(path),
+        ("lint", [path]) => Command::Lint(path),
+        ("test", [path]) => Command::Test(path),
+        ("bench", [runs, path]) => match runs.parse() {
+            Ok(runs) if runs > 0 => Command::Bench(runs, path),
+            _ => return Err(format!("`{}` is not a number of runs", runs)),
+        },
+        ("explain", [code]) => Command::Explain(code),
+        ("lsp", []) => Command::Lsp,
+        ("help", _) => Command::Help,
+        (name, _) => return Err(format!("wrong arguments for `{}`", name)),
+    };
+    Ok((options, command))
+}
 
-fn main() -> io::Result<()> {
+fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut vm = VirtualMachine::new();
+    let args: Vec<_> = args.iter().skip(1).map(String::as_str).collect();
+    let (mut options, command) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            eprintln!("Run `ruslox help` for usage.");
+            process::exit(2);
+        }
+    };
+    match execute(command, &mut options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            report_io_error(&error, &options);
+            process::exit(1);
+        }
+    }
+}
 
-    match args.len() {
-        1 => repl(&mut vm)?,
-        2 => run_file(&mut vm, &args[1])?,
-        _ => {
-            eprintln!("Usage: ruslox [script]");
+/// Carries out the command, returning whether it succeeded. Failures are reported on the way,
+/// except for I/O errors, which are left to the caller.
+fn execute(command: Command, options: &mut Options) -> io::Result<bool> {
+    let mut vm = options.virtual_machine();
+    let preloaded = matches!(
+        command,
+        Command::Repl | Command::Run(..) | Command::Eval(..)
+    );
+    if preloaded && !options.preload(&mut vm)? {
+        return Ok(false);
+    }
+    // Natives and preloaded globals are declared, for the commands compiling without a VM.
+    if options.rules.is_strict() {
+        options.rules.strict(vm.global_names());
+    }
+
+    let options = &*options;
+    match command {
+        Command::Repl => repl(&mut vm, options).map(|()| true),
+        Command::Run(path, args) => {
+            vm.set_args(args.iter().map(|arg| arg.to_string()).collect());
+            run_file(&mut vm, path, options)
+        }
+        Command::Eval(code, args) => {
+            vm.set_args(args.iter().map(|arg| arg.to_string()).collect());
+            let mut files = SourceFileManager::new();
+            Ok(run(&mut vm, code, "<eval>", &mut files, None, options))
+        }
+        Command::Watch(path, args) => watch_file(path, &args, options).map(|()| true),
+        Command::Check(path) => check_file(path, options),
+        Command::Compile(path) => compile_file(path, options),
+        Command::Disassemble(path) => disassemble_file(path, options),
+        Command::Ast(path) => dump_file(path, options),
+        Command::Format(path) => format_file(path, options),
+        Command::Lint(path) => lint_file(path, options),
+        Command::Test(path) => harness::run_tests(path, &options.script_options),
+        Command::Bench(runs, path) => bench_file(path, runs, options),
+        Command::Explain(code) => Ok(explain(code)),
+        Command::Lsp => lsp::serve(options.rules.clone()),
+        Command::Help => {
+            help();
+            Ok(true)
         }
     }
-    Ok(())
 }
 
-fn repl(vm: &mut VirtualMachine) -> io::Result<()> {
-    let mut line = String::new();
-    loop {
-        line.clear();
+/// Renders an I/O error like any other diagnostic, so it follows `--message-format`.
+fn report_io_error(error: &io::Error, options: &Options) {
+    let files: SourceFileManager<String, String> = SourceFileManager::new();
+    InterpretError::Simple(ErrorItem::error().with_message(error.to_string()))
+        .emit_as(&files, options.emit);
+}
 
-        print!("{} ", REPL_SIGN);
-        io::stdout().flush()?;
-        io::stdin().read_line(&mut line)?;
+fn help() {
+    let lints: Vec<_> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+    println!("Usage: ruslox [options] [command] [arguments]");
+    println!();
+    println!("Commands:");
+    println!("    [run] <script> [args...]   run a script or a compiled .loxc file");
+    println!("    repl                       start the REPL, the default without arguments");
+    println!("    eval <code> [args...]      run code given on the command line (-e)");
+    println!("    watch <script> [args...]   run the script again whenever it changes");
+    println!("    check <script>             compile without running, failing on errors");
+    println!("    compile <script>           write the bytecode to <script>.loxc");
+    println!("    disasm <script>            print the bytecode without running it");
+    println!("    ast <script>               print the syntax tree as S-expressions");
+    println!("    fmt <script>               print the script in the canonical layout");
+    println!("    lint <script>              report lint warnings");
+    println!("    test <path>                run the annotated scripts under a directory");
+    println!("    bench <runs> <script>      time compilation and execution");
+    println!("    explain <code>             explain an error or warning code");
+    println!("    lsp                        serve editors over the language server protocol");
+    println!("    help                       print this help");
+    println!("Scripts named `-` are read from standard input.");
+    println!();
+    println!("Options:");
+    println!("    --message-format <format>  print diagnostics as `human` text or `json` lines");
+    println!("    --color <when>             color diagnostics: always, auto or never");
+    println!("    -W, --warn <lint>          enable a lint");
+    println!("    -A, --allow <lint>         disable a lint");
+    println!("    --deny-warnings            treat warnings as errors");
+    println!("    --strict                   reject variables declared nowhere before running");
+    println!(
+        "    --error-limit <n>          stop compiling after this many errors ({} by default)",
+        LintRules::DEFAULT_ERROR_LIMIT
+    );
+    println!("    --preload <script>         run a script first, for its globals; repeatable");
+    println!("    --trace                    print every instruction executed with the stack");
+    println!("    --strict-arithmetic        fail on division by zero and NaN results");
+    println!("    --strict-compat            refuse what reference Lox refuses, like \"a\" + 1");
+    println!("    --seed <n>                 start `random` and `randomInt` from this seed");
+    println!(
+        "    --stack-size <slots>       overflow beyond this many values ({} by default, at most {})",
+        VirtualMachine::DEFAULT_STACK_SIZE,
+        VirtualMachine::MAX_STACK_SIZE
+    );
+    println!();
+    println!("Lints: {}, or `warnings` for all of them", lints.join(", "));
+}
+
+/// Prints the explanation of a code, returning whether there is one.
+fn explain(code: &str) -> bool {
+    match explain::explain(code) {
+        Some(explanation) => {
+            println!("{}", explanation);
+            true
+        }
+        None => {
+            let codes: Vec<_> = explain::codes().collect();
+            eprintln!("no explanation for `{}`", code);
+            eprintln!("Known codes: {}", codes.join(", "));
+            false
+        }
+    }
+}
+
+fn repl(vm: &mut VirtualMachine, options: &Options) -> io::Result<()> {
+    let mut session = ReplSession::with_rules(options.rules.clone());
+    // Every entry stays in here, so diagnostics about code from an earlier entry can still show
+    // its source.
+    let mut files = SourceFileManager::new();
+    let mut editor = LineEditor::new()?;
+    let mut entry = String::new();
+    loop {
+        let sign = match entry.is_empty() {
+            true => REPL_SIGN,
+            false => CONTINUATION_SIGN,
+        };
+        let line = match editor.read_line(&format!("{} ", sign), vm.global_names())? {
+            Input::Line(line) => line,
+            Input::Interrupted => {
+                entry.clear();
+                continue;
+            }
+            Input::Closed => return Ok(()),
+        };
 
-        if line.trim().is_empty() {
+        // An empty line ends the session, or forces an incomplete entry through.
+        if line.trim().is_empty() && entry.is_empty() {
             return Ok(());
         }
-        run(vm, &line, "<input>");
+        if line.trim() == HISTORY_COMMAND && entry.is_empty() {
+            for (number, line) in editor.history().lines().iter().enumerate() {
+                println!("{:>5}  {}", number + 1, line);
+            }
+            continue;
+        }
+        if line.trim() == HEAP_COMMAND && entry.is_empty() {
+            print!("{}", vm.heap_dump());
+            continue;
+        }
+        if let Err(error) = editor.remember(&line) {
+            eprintln!("cannot save history: {}", error);
+        }
+        entry.push_str(&line);
+        entry.push('\n');
+        if !line.trim().is_empty() && compiler::is_incomplete(&entry) {
+            continue;
+        }
+        run(
+            vm,
+            &entry,
+            "<input>",
+            &mut files,
+            Some(&mut session),
+            options,
+        );
+        entry.clear();
+    }
+}
+
+/// Reads a file along with the name diagnostics refer to it by. Errors name the file.
+fn read_file(path: impl AsRef<Path>) -> io::Result<(String, Vec<u8>)> {
+    let stdin = path.as_ref() == Path::new(STDIN_PATH);
+    let filename = match stdin {
+        true => "<stdin>".into(),
+        false => path.as_ref().to_string_lossy().into_owned(),
+    };
+    let bytes = match stdin {
+        true => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+        }
+        false => fs::read(path),
+    };
+    match bytes {
+        Ok(bytes) => Ok((filename, bytes)),
+        Err(error) => Err(io::Error::new(
+            error.kind(),
+            format!("cannot read {}: {}", filename, error),
+        )),
+    }
+}
+
+/// Reads a script along with the name diagnostics refer to it by.
+fn read_source(path: impl AsRef<Path>) -> io::Result<(String, String)> {
+    let (filename, bytes) = read_file(path)?;
+    Ok((filename, into_source(bytes)?))
+}
+
+fn into_source(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Runs the script again whenever it is modified, in a fresh VM each time. A file missing for
+/// a moment, as when an editor replaces it on save, is waited for.
+fn watch_file(path: &str, script_args: &[&str], options: &Options) -> io::Result<()> {
+    let mut last_modified = None;
+    loop {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let Ok(modified) = modified {
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                eprintln!("[running {}]", path);
+
+                let mut vm = options.virtual_machine();
+                vm.set_args(script_args.iter().map(|arg| arg.to_string()).collect());
+                let ran = options
+                    .preload(&mut vm)
+                    .and_then(|preloaded| match preloaded {
+                        true => run_file(&mut vm, path, options),
+                        false => Ok(false),
+                    });
+                if let Err(error) = ran {
+                    report_io_error(&error, options);
+                }
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Runs a script, or a chunk compiled from one. Bytecode is recognized by its header, so
+/// startup skips the frontend whatever the file is named.
+fn run_file(
+    vm: &mut VirtualMachine,
+    path: impl AsRef<Path>,
+    options: &Options,
+) -> io::Result<bool> {
+    let (filename, bytes) = read_file(&path)?;
+    if Chunk::is_serialized(&bytes) {
+        return run_compiled(vm, path, filename, &bytes, options);
+    }
+    let mut files = SourceFileManager::new();
+    Ok(run(
+        vm,
+        into_source(bytes)?,
+        filename,
+        &mut files,
+        None,
+        options,
+    ))
+}
+
+fn dump_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(path)?;
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(filename, &source);
+    match compiler::dump_ast(file_id, &source) {
+        Ok(dumped) => print!("{}", dumped),
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Prints the script in the canonical layout.
+fn format_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(path)?;
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(filename, &source);
+    match compiler::format(file_id, &source) {
+        Ok(formatted) => print!("{}", formatted),
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
     }
+    Ok(true)
 }
 
-fn run_file(vm: &mut VirtualMachine, path: impl AsRef<Path>) -> io::Result<()> {
-    let filename = path.as_ref().to_string_lossy().into_owned();
-    let source = fs::read_to_string(path)?;
-    run(vm, source, filename);
-    Ok(())
+/// Reports lint warnings for the script without running it. Warnings only fail it when they
+/// are denied.
+fn lint_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(path)?;
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(filename, &source);
+    match compiler::check(file_id, &source, &options.rules) {
+        Ok(warnings) => warnings.emit_as(&files, options.emit),
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    }
+    Ok(true)
 }
 
-fn run(vm: &mut VirtualMachine, source: impl AsRef<str>, filename: impl AsRef<str>) {
-    // codespan-reporting environments.
+/// Compiles the script without running it, reporting whether it compiled. Unlike `lint`, this
+/// also catches errors only code generation finds, such as too many constants.
+fn check_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(path)?;
+
     let mut files = SourceFileManager::new();
-    let file_id = files.add(filename.as_ref(), source.as_ref());
+    let file_id = files.add(filename, &source);
+    #[cfg(not(feature = "register"))]
+    let compiled = compiler::compile_with(file_id, &source, &options.rules);
+    #[cfg(feature = "register")]
+    let compiled = compiler::compile_register_with(file_id, &source, &options.rules);
 
-    match compiler::compile(file_id, source.as_ref()) {
-        Ok(chunk) => {
-            if let Err(error) = vm.interpret(chunk) {
-                error.emit(&files);
+    match compiled {
+        Ok(CompileOutput { warnings, .. }) => {
+            warnings.emit_as(&files, options.emit);
+            Ok(true)
+        }
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            Ok(false)
+        }
+    }
+}
+
+/// Compiles and runs the script `runs` times, each time in a fresh VM, then reports how long
+/// compilation and execution took. Returns whether every run succeeded.
+fn bench_file(path: impl AsRef<Path>, runs: usize, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(path)?;
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(filename, &source);
+    let mut compile_times = Vec::with_capacity(runs);
+    let mut run_times = Vec::with_capacity(runs);
+    let mut gc_times = Vec::with_capacity(runs);
+    let mut collections = 0;
+    for run in 0..runs {
+        let start = Instant::now();
+        #[cfg(not(feature = "register"))]
+        let compiled = compiler::compile_with(file_id, &source, &options.rules);
+        #[cfg(feature = "register")]
+        let compiled = compiler::compile_register_with(file_id, &source, &options.rules);
+        compile_times.push(start.elapsed());
+
+        let chunk = match compiled {
+            Ok(CompileOutput { chunk, warnings }) => {
+                if run == 0 {
+                    warnings.emit_as(&files, options.emit);
+                }
+                chunk
             }
+            Err(error) => {
+                error.emit_as(&files, options.emit);
+                return Ok(false);
+            }
+        };
+
+        let mut vm = options.virtual_machine();
+        if !options.preload(&mut vm)? {
+            return Ok(false);
+        }
+        let gc = GcClock::default();
+        vm.set_instrument(gc.clone());
+        let start = Instant::now();
+        #[cfg(not(feature = "register"))]
+        let result = vm.interpret(chunk);
+        #[cfg(feature = "register")]
+        let result = vm.interpret_register(chunk);
+        run_times.push(start.elapsed());
+        gc_times.push(gc.0.borrow().paused);
+        collections += gc.0.borrow().collections;
+
+        if let Err(error) = result {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    }
+
+    eprintln!("{} runs", runs);
+    report_times("compile", &compile_times);
+    report_times("execute", &run_times);
+    report_times("gc", &gc_times);
+    let overhead = gc_times.iter().sum::<Duration>().as_secs_f64()
+        / run_times
+            .iter()
+            .sum::<Duration>()
+            .as_secs_f64()
+            .max(f64::MIN_POSITIVE);
+    eprintln!(
+        "{:<8} {} collections per run, {:.1}% of execution",
+        "",
+        collections / runs,
+        overhead * 100.0
+    );
+    Ok(true)
+}
+
+/// Times the collections of a benchmarked run.
+#[derive(Default)]
+struct GcTimes {
+    collections: usize,
+    paused: Duration,
+    started: Option<Instant>,
+}
+
+// Shared with the VM, which owns its instrument.
+#[derive(Clone, Default)]
+struct GcClock(Rc<RefCell<GcTimes>>);
+
+impl Instrument for GcClock {
+    fn on_collect_start(&mut self) {
+        self.0.borrow_mut().started = Some(Instant::now());
+    }
+
+    fn on_collect(&mut self, _: usize, _: usize) {
+        let mut clock = self.0.borrow_mut();
+        if let Some(started) = clock.started.take() {
+            clock.paused += started.elapsed();
+            clock.collections += 1;
+        }
+    }
+}
+
+fn report_times(stage: &str, times: &[Duration]) {
+    let min = times.iter().min().copied().unwrap_or_default();
+    let average = times.iter().sum::<Duration>() / times.len() as u32;
+    eprintln!("{:<8} min {:>12.3?}  avg {:>12.3?}", stage, min, average);
+}
+
+/// Compiles the script ahead of time into a `.loxc` file next to it. Scripts read from
+/// standard input are written to standard output instead.
+fn compile_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, source) = read_source(&path)?;
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(filename, &source);
+    match compiler::compile_with(file_id, &source, &options.rules) {
+        Ok(CompileOutput { chunk, warnings }) => {
+            warnings.emit_as(&files, options.emit);
+            match path.as_ref() == Path::new(STDIN_PATH) {
+                true => io::stdout().write_all(&chunk.serialize())?,
+                false => fs::write(path.as_ref().with_extension("loxc"), chunk.serialize())?,
+            }
+        }
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Prints the bytecode of the script, or of a `.loxc` file, without running it.
+fn disassemble_file(path: impl AsRef<Path>, options: &Options) -> io::Result<bool> {
+    let (filename, bytes) = read_file(&path)?;
+    let mut files = SourceFileManager::new();
+    if Chunk::is_serialized(&bytes) {
+        let file_id = files.add(filename.as_str(), String::new());
+        return Ok(match Chunk::deserialize(file_id, &bytes) {
+            Ok(chunk) => {
+                chunk.disassemble(filename);
+                true
+            }
+            Err(error) => {
+                error.emit_as(&files, options.emit);
+                false
+            }
+        });
+    }
+
+    let source = into_source(bytes)?;
+    let file_id = files.add(filename.as_str(), source.clone());
+    match compiler::compile_with(file_id, &source, &options.rules) {
+        Ok(CompileOutput { chunk, warnings }) => {
+            warnings.emit_as(&files, options.emit);
+            chunk.disassemble(filename);
+        }
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Runs a serialized chunk, returning whether it succeeded. Diagnostics point into the `.lox`
+/// source beside it, if it still exists and is the one the chunk was compiled from.
+fn run_compiled(
+    vm: &mut VirtualMachine,
+    path: impl AsRef<Path>,
+    filename: String,
+    bytes: &[u8],
+    options: &Options,
+) -> io::Result<bool> {
+    let source_path = path.as_ref().with_extension("lox");
+    let source = match path.as_ref() == Path::new(STDIN_PATH) {
+        true => None,
+        false => fs::read_to_string(&source_path).ok(),
+    };
+
+    let mut files = SourceFileManager::new();
+    let (file_id, mut has_source) = match source {
+        Some(source) => (
+            files.add(source_path.to_string_lossy().into_owned(), source),
+            true,
+        ),
+        None => (files.add(filename, String::new()), false),
+    };
+
+    let chunk = match Chunk::deserialize(file_id, bytes) {
+        Ok(chunk) => chunk,
+        Err(error) => {
+            error.emit_as(&files, options.emit);
+            return Ok(false);
+        }
+    };
+    if has_source && chunk.is_stale(files.get(file_id).unwrap().source()) {
+        // Positions would point at the wrong code.
+        has_source = false;
+        let mut warnings = Warnings::new();
+        warnings.push(
+            ErrorItem::warning()
+                .with_code("W0006")
+                .with_message("bytecode is older than its source")
+                .with_notes(vec![format!(
+                    "{} changed since it was compiled; run `ruslox compile` again",
+                    source_path.display()
+                )]),
+        );
+        warnings.emit_as(&files, options.emit);
+    }
+    let lines = chunk.lines.clone();
+    let result = vm.interpret(chunk);
+    vm.clear_stack();
+    match result {
+        Ok(()) => Ok(true),
+        Err(error) => {
+            match has_source {
+                true => error.emit_as(&files, options.emit),
+                false => error.without_labels(&lines).emit_as(&files, options.emit),
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Compiles and runs the source, returning whether it did so without errors. The source is
+/// added to `files`, which diagnostics are rendered from.
+fn run(
+    vm: &mut VirtualMachine,
+    source: impl AsRef<str>,
+    filename: impl AsRef<str>,
+    files: &mut SourceFileManager<String, String>,
+    session: Option<&mut ReplSession>,
+    options: &Options,
+) -> bool {
+    let file_id = files.add(filename.as_ref().into(), source.as_ref().into());
+
+    // Whatever the VM defined so far, such as preloaded globals, is declared in strict mode.
+    let mut rules = options.rules.clone();
+    if rules.is_strict() {
+        rules.strict(vm.global_names());
+    }
+
+    #[cfg(not(feature = "register"))]
+    let (compiled, echoes) = match session {
+        Some(session) => {
+            let compiled = session.compile(file_id, source.as_ref());
+            (compiled, session.echoes())
+        }
+        None => (
+            compiler::compile_with(file_id, source.as_ref(), &rules),
+            false,
+        ),
+    };
+    #[cfg(feature = "register")]
+    let compiled = match session {
+        Some(session) => session.compile_register(file_id, source.as_ref()),
+        None => compiler::compile_register_with(file_id, source.as_ref(), &rules),
+    };
+
+    match compiled {
+        Ok(CompileOutput { chunk, warnings }) => {
+            warnings.emit_as(files, options.emit);
+
+            // REPL entries ending with an expression show its value as a literal would be typed.
+            #[cfg(not(feature = "register"))]
+            let result = match echoes {
+                true => vm.evaluate(chunk).map(|value| println!("{}", value.repr())),
+                false => vm.interpret(chunk),
+            };
+            #[cfg(feature = "register")]
+            let result = vm.interpret_register(chunk);
+
             vm.clear_stack();
+            match result {
+                Ok(()) => true,
+                Err(error) => {
+                    error.emit_as(files, options.emit);
+                    false
+                }
+            }
+        }
+        Err(error) => {
+            error.emit_as(files, options.emit);
+            false
         }
-        Err(error) => error.emit(&files),
     }
 }
@@ -0,0 +1,75 @@
+//! Runs the `ruslox` binary, checking that failures show in its exit status, as scripts and
+//! build tools calling it rely on.
+
+use std::{
+    env, fs,
+    io::Write,
+    process::{self, Command, Stdio},
+};
+
+/// Runs `ruslox` with the arguments and standard input, returning its exit code.
+fn ruslox(args: &[&str], stdin: &str) -> Option<i32> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ruslox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait().unwrap().code()
+}
+
+#[test]
+fn run_fails_on_errors() {
+    assert_eq!(ruslox(&["run", "-"], "print 1;"), Some(0));
+    assert_eq!(ruslox(&["run", "-"], "print 1 +;"), Some(1));
+    assert_eq!(ruslox(&["run", "-"], "print -nil;"), Some(1));
+    assert_eq!(ruslox(&["eval", "print nope;"], ""), Some(1));
+}
+
+#[test]
+fn missing_files_fail() {
+    for command in ["run", "check", "fmt", "lint", "disasm"] {
+        assert_eq!(ruslox(&[command, "missing/script.lox"], ""), Some(1));
+    }
+}
+
+#[test]
+fn fmt_fails_on_unparsable_scripts() {
+    assert_eq!(ruslox(&["fmt", "-"], "print  1;"), Some(0));
+    assert_eq!(ruslox(&["fmt", "-"], "print (;"), Some(1));
+}
+
+#[test]
+fn denied_warnings_fail() {
+    let unused = "{ var a = 1; }";
+    assert_eq!(ruslox(&["run", "-"], unused), Some(0));
+    assert_eq!(ruslox(&["--deny-warnings", "run", "-"], unused), Some(1));
+    assert_eq!(ruslox(&["lint", "-"], unused), Some(0));
+    assert_eq!(ruslox(&["--deny-warnings", "lint", "-"], unused), Some(1));
+}
+
+#[test]
+fn help_follows_subcommands() {
+    assert_eq!(ruslox(&["fmt", "--help"], ""), Some(0));
+    assert_eq!(ruslox(&["test", "-h"], ""), Some(0));
+    assert_eq!(ruslox(&["fmt", "--helpful"], ""), Some(2));
+}
+
+#[test]
+fn test_passes_options_on_to_scripts() {
+    let script = env::temp_dir().join(format!("ruslox-options-{}.lox", process::id()));
+    fs::write(&script, "print \"a\" + 1; // expect: a1\n").unwrap();
+    let path = script.to_str().unwrap();
+    let passes = ruslox(&["test", path], "");
+    let strict_passes = ruslox(&["test", "--strict-compat", path], "");
+    fs::remove_file(&script).unwrap();
+    assert_eq!(passes, Some(0));
+    assert_eq!(strict_passes, Some(1));
+}
@@ -0,0 +1,379 @@
+//! Re-prints a parsed program in the canonical layout: four-space indentation, one statement
+//! per line, single spaces around binary operators and call arguments wrapped one per line
+//! when a line grows too long. Comments are kept, either on their own line or at the end of
+//! the line they trailed in the source.
+
+use std::ops::Range;
+
+use shared::{error::InterpretResult, line::LineIndex};
+
+use crate::{
+    parser::{parse, Expression, Statement},
+    scanner::{scan, ScannedContext, Token},
+};
+
+const INDENT: &str = "    ";
+const MAX_WIDTH: usize = 100;
+
+struct Formatter<'a> {
+    source: &'a str,
+    scanned: &'a ScannedContext,
+    lines: LineIndex,
+    output: Vec<String>,
+    indent: usize,
+    // Index of the next comment to emit.
+    comment: usize,
+    // Index of the token the formatter has reached. Blocks have no position of their own when
+    // they are the body of another statement, so their braces are found from here.
+    cursor: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, scanned: &'a ScannedContext) -> Self {
+        Self {
+            source,
+            scanned,
+            lines: LineIndex::new(source),
+            output: Vec::new(),
+            indent: 0,
+            comment: 0,
+            cursor: 0,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, position: &Range<usize>) {
+        self.comments_before(position.start);
+        self.blank_line_before(position.start);
+        self.cursor = self
+            .scanned
+            .positions
+            .partition_point(|token| token.start < position.start);
+
+        self.line("");
+        self.nested(statement);
+    }
+
+    /// Formats a statement whose first line has already been started.
+    fn nested(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VarDeclaration(name, Some(initializer)) => {
+                self.expression_line(&format!("var {} = ", name), initializer, ";")
+            }
+            Statement::VarDeclaration(name, None) => self.append(&format!("var {};", name)),
+            Statement::Print(expression) => self.expression_line("print ", expression, ";"),
+            Statement::Expressional(expression) => self.expression_line("", expression, ";"),
+            Statement::If(condition, then, otherwise) => {
+                self.append(&format!("if ({})", self.expression(condition, 1)));
+                self.body(then, false);
+                if let Some(otherwise) = otherwise {
+                    match **then {
+                        Statement::Block(..) => self.append(" else"),
+                        _ => self.line("else"),
+                    }
+                    self.body(otherwise, true);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.append(&format!("while ({})", self.expression(condition, 1)));
+                self.body(body, false);
+            }
+            Statement::For(initializer, condition, increment, body) => {
+                let initializer = self.optional(initializer);
+                self.for_header(&format!("{};", initializer), condition, increment);
+                self.body(body, false);
+            }
+            Statement::ForWithInit(initializer, condition, increment, body) => {
+                let initializer = match initializer.as_deref() {
+                    Some(Statement::VarDeclaration(name, initializer)) => match initializer {
+                        Some(initializer) => {
+                            format!("var {} = {};", name, self.expression(initializer, 1))
+                        }
+                        None => format!("var {};", name),
+                    },
+                    _ => ";".into(),
+                };
+                self.for_header(&initializer, condition, increment);
+                self.body(body, false);
+            }
+            Statement::Block(..) => self.body(statement, false),
+            Statement::Error => unreachable!("still trying to format after reporting diagnostics"),
+        }
+    }
+
+    fn for_header(
+        &mut self,
+        initializer: &str,
+        condition: &Option<Box<Expression>>,
+        increment: &Option<Box<Expression>>,
+    ) {
+        let condition = self.optional(condition);
+        let increment = self.optional(increment);
+        let condition = match condition.is_empty() {
+            true => ";".to_string(),
+            false => format!(" {};", condition),
+        };
+        let increment = match increment.is_empty() {
+            true => String::new(),
+            false => format!(" {}", increment),
+        };
+        self.append(&format!("for ({}{}{})", initializer, condition, increment));
+    }
+
+    /// Formats the body of a control flow statement. Blocks open on the current line, other
+    /// statements go on the next one, one level deeper.
+    fn body(&mut self, statement: &Statement, after_else: bool) {
+        match statement {
+            Statement::Block(statements, positions) => {
+                if !self.current().trim().is_empty() {
+                    self.append(" ");
+                }
+                self.append("{");
+                self.cursor = self.find_token(|token| matches!(token, Token::LeftBrace));
+                let close = self.matching_brace();
+
+                self.indent += 1;
+                for (statement, position) in statements.iter().zip(positions) {
+                    self.statement(statement, position);
+                }
+                let inner = self.comment;
+                self.comments_before(close.start);
+                self.indent -= 1;
+
+                match statements.is_empty() && inner == self.comment {
+                    true => self.append("}"),
+                    false => self.line("}"),
+                }
+                self.cursor = self
+                    .scanned
+                    .positions
+                    .partition_point(|token| token.start <= close.start);
+            }
+            Statement::If(..) if after_else => {
+                self.append(" ");
+                self.nested(statement);
+            }
+            _ => {
+                self.indent += 1;
+                self.line("");
+                self.nested(statement);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    /// Appends `prefix`, the expression and `suffix` to the current line, wrapping call
+    /// arguments if the line would be too long otherwise.
+    fn expression_line(&mut self, prefix: &str, expression: &Expression, suffix: &str) {
+        let flat = self.expression(expression, 1);
+        let width = self.current().len() + prefix.len() + flat.len() + suffix.len();
+        let rendered = match width > MAX_WIDTH {
+            true => self.wrapped(expression, 1),
+            false => flat,
+        };
+        self.append(&format!("{}{}{}", prefix, rendered, suffix));
+    }
+
+    fn optional(&self, expression: &Option<Box<Expression>>) -> String {
+        expression
+            .as_ref()
+            .map(|expression| self.expression(expression, 1))
+            .unwrap_or_default()
+    }
+
+    fn expression(&self, expression: &Expression, min_precedence: u8) -> String {
+        self.render(expression, min_precedence, None)
+    }
+
+    fn wrapped(&self, expression: &Expression, min_precedence: u8) -> String {
+        self.render(expression, min_precedence, Some(self.indent))
+    }
+
+    /// Renders the expression, parenthesized if it binds looser than `min_precedence`. With
+    /// `wrap` set, call arguments go one per line below the given indentation level.
+    fn render(&self, expression: &Expression, min_precedence: u8, wrap: Option<usize>) -> String {
+        let precedence = precedence(expression);
+        let rendered = match expression {
+            Expression::String(string) => format!("\"{}\"", string),
+            Expression::Int(int) => int.to_string(),
+            Expression::Number(number) => number.to_string(),
+            Expression::Identifier(identifier) => identifier.to_string(),
+            Expression::True => "true".into(),
+            Expression::False => "false".into(),
+            Expression::Nil => "nil".into(),
+            Expression::Unary(operator, operand) => {
+                format!("{}{}", operator, self.render(operand, precedence, wrap))
+            }
+            // Assignment is right-associative.
+            Expression::Assign(target, source) => format!(
+                "{} = {}",
+                self.render(target, precedence + 1, wrap),
+                self.render(source, precedence, wrap)
+            ),
+            Expression::Arithmetic(left, operator, right)
+            | Expression::Logic(left, operator, right) => format!(
+                "{} {} {}",
+                self.render(left, precedence, wrap),
+                operator,
+                self.render(right, precedence + 1, wrap)
+            ),
+            Expression::Call(callee, arguments) => {
+                let callee = self.render(callee, precedence, wrap);
+                match wrap {
+                    Some(indent) if !arguments.is_empty() => {
+                        let inner = INDENT.repeat(indent + 1);
+                        let arguments = arguments
+                            .iter()
+                            .map(|argument| {
+                                format!("{}{}", inner, self.render(argument, 1, Some(indent + 1)))
+                            })
+                            .collect::<Vec<_>>()
+                            .join(",\n");
+                        format!("{}(\n{}\n{})", callee, arguments, INDENT.repeat(indent))
+                    }
+                    _ => {
+                        let arguments = arguments
+                            .iter()
+                            .map(|argument| self.render(argument, 1, None))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        format!("{}({})", callee, arguments)
+                    }
+                }
+            }
+        };
+        match precedence < min_precedence {
+            true => format!("({})", rendered),
+            false => rendered,
+        }
+    }
+
+    /// Emits the comments which start before `offset`. A comment sharing its line with the
+    /// code before it stays at the end of that line.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.scanned.comments.get(self.comment) {
+            if comment.start >= offset {
+                break;
+            }
+            let text = self.source[comment.clone()].trim_end();
+            let previous = self.previous_end(comment.start);
+            let trailing = previous.is_some_and(|previous| {
+                self.lines.line_column(previous).0 == self.lines.line_column(comment.start).0
+            });
+            match trailing && !self.output.is_empty() {
+                true => self.append(&format!(" {}", text)),
+                false => {
+                    self.blank_line_before(comment.start);
+                    self.line(text);
+                }
+            }
+            self.comment += 1;
+        }
+    }
+
+    /// Keeps one blank line where the source had at least one before `offset`.
+    fn blank_line_before(&mut self, offset: usize) {
+        let Some(previous) = self.previous_end(offset) else {
+            return;
+        };
+        let gap = self.lines.line_column(offset).0 - self.lines.line_column(previous).0;
+        let opening = self.current().ends_with('{') || self.current().is_empty();
+        if gap > 1 && !opening {
+            self.output.push(String::new());
+        }
+    }
+
+    /// End of the last token or comment before `offset`.
+    fn previous_end(&self, offset: usize) -> Option<usize> {
+        let token = self
+            .scanned
+            .positions
+            .iter()
+            .take_while(|token| token.end <= offset)
+            .last();
+        let comment = self.scanned.comments[..self.comment]
+            .iter()
+            .take_while(|comment| comment.end <= offset)
+            .last();
+        token
+            .into_iter()
+            .chain(comment)
+            .map(|range| range.end)
+            .max()
+    }
+
+    /// Index of the first token from the cursor matching `predicate`.
+    fn find_token(&self, predicate: impl Fn(&Token) -> bool) -> usize {
+        (self.cursor..self.scanned.tokens.len())
+            .find(|&index| predicate(&self.scanned.tokens[index]))
+            .expect("formatting a block without its braces")
+    }
+
+    /// Position of the brace closing the one at the cursor.
+    fn matching_brace(&self) -> Range<usize> {
+        let mut depth = 0;
+        for index in self.cursor..self.scanned.tokens.len() {
+            match self.scanned.tokens[index] {
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace if depth == 1 => return self.scanned.positions[index].clone(),
+                Token::RightBrace => depth -= 1,
+                _ => {}
+            }
+        }
+        unreachable!("formatting a block without its closing brace")
+    }
+
+    fn current(&self) -> &str {
+        self.output.last().map_or("", String::as_str)
+    }
+
+    fn line(&mut self, text: &str) {
+        self.output
+            .push(format!("{}{}", INDENT.repeat(self.indent), text));
+    }
+
+    fn append(&mut self, text: &str) {
+        match self.output.last_mut() {
+            Some(line) => line.push_str(text),
+            None => self.line(text),
+        }
+    }
+}
+
+/// Binding power of the expression, from assignment up to primary expressions.
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Assign(..) => 1,
+        Expression::Logic(_, Token::Or, _) => 2,
+        Expression::Logic(..) => 3,
+        Expression::Arithmetic(_, Token::EqualEqual | Token::BangEqual, _) => 4,
+        Expression::Arithmetic(
+            _,
+            Token::Greater | Token::GreaterEqual | Token::Less | Token::LessEqual,
+            _,
+        ) => 5,
+        Expression::Arithmetic(_, Token::Plus | Token::Minus, _) => 6,
+        Expression::Arithmetic(..) => 7,
+        Expression::Unary(..) => 8,
+        Expression::Call(..) => 9,
+        _ => 10,
+    }
+}
+
+/// Formats the source in the canonical layout. The source must parse without errors.
+pub fn format(file_id: usize, source: impl AsRef<str>) -> InterpretResult<String> {
+    let source = source.as_ref();
+    let scanned = scan(file_id, source)?;
+    let parsed = parse(file_id, &scanned)?;
+
+    let mut formatter = Formatter::new(source, &scanned);
+    for (statement, position) in parsed.statements.iter().zip(parsed.positions.iter()) {
+        formatter.statement(statement, position);
+    }
+    formatter.comments_before(source.len());
+
+    Ok(formatter
+        .output
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect())
+}
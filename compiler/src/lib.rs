@@ -1,51 +1,104 @@
-use std::ops::Range;
+use std::{mem, ops::Range};
 
-use parser::{Expression, ParsedContext, Statement};
-use scanner::Token;
 use shared::{
     chunk::{Chunk, Instruction},
     constant::Constant,
-    error::{ErrorItem, InterpretError, InterpretResult, Label},
+    error::{ErrorItem, InterpretError, InterpretResult, Label, Warnings},
     stack::Stack,
 };
 
+mod format;
+mod lint;
 mod parser;
+#[cfg(feature = "register")]
+mod register;
+mod repl;
+mod resolver;
 mod scanner;
+pub mod visit;
+
+// Frontend stages, exported for tools that reuse them without compiling.
+pub use format::format;
+use lint::lint_program;
+pub use lint::{lint, LintRules, Rule};
+use parser::parse_within;
+pub use parser::{parse, Expression, ParsedContext, Statement};
+pub use repl::{complete, is_incomplete, ReplSession};
+pub use resolver::{resolve, Resolutions};
+pub use scanner::{
+    classify, scan, ClassifiedToken, ScannedContext, Token, TokenCategory, KEYWORDS,
+};
 
 struct Local {
     depth: usize,
-    name: String,
 }
 
 struct Compiler<'a> {
     file_id: usize,
     parsed_context: &'a ParsedContext<'a>,
+    resolutions: &'a Resolutions<'a>,
     chunk: &'a mut Chunk,
     locals: Stack<Local>,
     local_depth: usize,
+    errors: Vec<ErrorItem>,
+    // Leaves the value of a final expression statement on the stack instead of discarding it.
+    keep_value: bool,
 }
 
 impl<'a> Compiler<'a> {
-    fn new(file_id: usize, parsed_context: &'a ParsedContext, chunk: &'a mut Chunk) -> Self {
+    fn new(
+        file_id: usize,
+        parsed_context: &'a ParsedContext,
+        resolutions: &'a Resolutions<'a>,
+        chunk: &'a mut Chunk,
+    ) -> Self {
         Self {
             file_id,
             parsed_context,
+            resolutions,
             chunk,
             locals: Stack::new(),
             local_depth: 0,
+            errors: Vec::new(),
+            keep_value: false,
         }
     }
 
     fn compile(&mut self) -> InterpretResult {
-        for (statement, position) in self
+        let last = self.parsed_context.statements.len().checked_sub(1);
+        for (index, (statement, position)) in self
             .parsed_context
             .statements
             .iter()
             .zip(self.parsed_context.positions.iter())
+            .enumerate()
         {
-            self.emit_statement(statement, position)?;
+            match statement {
+                Statement::Expressional(expression) if self.keep_value && Some(index) == last => {
+                    if let Err(error) = self.emit_expression(expression, position) {
+                        record_error(&mut self.errors, error);
+                    }
+                }
+                _ => self.emit_recovering(statement, position),
+            }
+        }
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(InterpretError::Compound(mem::take(&mut self.errors))),
+        }
+    }
+
+    /// Emits a statement of a program or block. A failure is recorded and the scopes are
+    /// restored, so that the statements after it are still checked.
+    fn emit_recovering(&mut self, statement: &Statement, position: &Range<usize>) {
+        let (locals, local_depth) = (self.locals.len(), self.local_depth);
+        if let Err(error) = self.emit_statement(statement, position) {
+            while self.locals.len() > locals {
+                let _ = self.locals.pop();
+            }
+            self.local_depth = local_depth;
+            record_error(&mut self.errors, error);
         }
-        Ok(())
     }
 
     fn emit_statement(
@@ -59,125 +112,79 @@ impl<'a> Compiler<'a> {
                     Some(expression) => self.emit_expression(expression, position)?,
                     None => self.chunk.write(Instruction::Nil, position),
                 };
-                let index = self.emit_constant(Constant::String((*name).clone()), position)?;
                 match self.local_depth {
-                    0 => self.chunk.write(Instruction::DefineGlobal(index), position),
-                    _ => {
-                        self.locals.push(Local {
-                            depth: self.local_depth,
-                            name: (*name).clone(),
-                        })?;
+                    0 => {
+                        let index =
+                            self.emit_constant(Constant::String((*name).clone()), position)?;
+                        self.chunk.write(Instruction::DefineGlobal(index), position);
                     }
+                    _ => self.locals.push(Local {
+                        depth: self.local_depth,
+                    })?,
                 }
             }
             Statement::Print(expression) => {
                 self.emit_expression(expression, position)?;
                 self.chunk.write(Instruction::Print, position);
             }
-            Statement::If(condition, then, otherwise) => {
-                self.emit_expression(&condition, position)?;
-                // spare the patch.
-                let then_patch = self.chunk.spare_jumpfalse(position);
-                self.chunk.write(Instruction::Pop, position);
-                self.emit_statement(&then, position)?;
-                let otherwise_patch = self.chunk.spare_jump(position);
-                // backpatch BEFORE the destination
-                self.chunk.patch(then_patch);
-                self.chunk.write(Instruction::Pop, position);
-                if let Some(otherwise) = otherwise {
-                    self.emit_statement(&otherwise, position)?;
+            Statement::If(condition, then, otherwise) => match truthiness(condition) {
+                // Only the branch taken is kept for constant conditions.
+                Some(true) => {
+                    self.emit_statement(then, position)?;
+                    if let Some(otherwise) = otherwise {
+                        self.emit_discarded(|compiler| {
+                            compiler.emit_statement(otherwise, position)
+                        })?;
+                    }
                 }
-                self.chunk.patch(otherwise_patch);
-            }
+                Some(false) => {
+                    self.emit_discarded(|compiler| compiler.emit_statement(then, position))?;
+                    if let Some(otherwise) = otherwise {
+                        self.emit_statement(otherwise, position)?;
+                    }
+                }
+                None => {
+                    self.emit_expression(condition, position)?;
+                    // spare the patch.
+                    let then_patch = self.chunk.spare_jumpfalse(position);
+                    self.chunk.write(Instruction::Pop, position);
+                    self.emit_statement(then, position)?;
+                    let otherwise_patch = self.chunk.spare_jump(position);
+                    // backpatch BEFORE the destination
+                    self.chunk.patch(then_patch, position)?;
+                    self.chunk.write(Instruction::Pop, position);
+                    if let Some(otherwise) = otherwise {
+                        self.emit_statement(otherwise, position)?;
+                    }
+                    self.chunk.patch(otherwise_patch, position)?;
+                }
+            },
             Statement::While(condition, body) => {
-                let loop_patch = self.chunk.code.len();
-                self.emit_expression(condition, position)?;
-                let condition_patch = self.chunk.spare_jumpfalse(position);
-                self.chunk.write(Instruction::Pop, position);
-                self.emit_statement(body, position)?;
-                self.chunk.write(
-                    Instruction::Loop((self.chunk.code.len() - loop_patch) as u16),
-                    position,
-                );
-                self.chunk.patch(condition_patch);
-                self.chunk.write(Instruction::Pop, position);
+                self.emit_loop(Some(condition), None, body, position)?;
             }
             Statement::For(init, condition, inc, body) => {
                 self.local_depth += 1;
                 if let Some(init) = init {
                     self.emit_expression(init, position)?;
+                    self.chunk.write(Instruction::Pop, position);
                 }
-                let condition_forwardpatch = self.chunk.code.len();
-                if let Some(condition) = condition {
-                    self.emit_expression(condition, position)?;
-                }
-                let break_backpatch = self.chunk.spare_jumpfalse(position);
-                self.chunk.write(Instruction::Pop, position);
-                let body_backpatch = self.chunk.spare_jump(position);
-                let inc_forwardpatch = self.chunk.code.len();
-                if let Some(inc) = inc {
-                    self.emit_expression(inc, position)?;
-                }
-                self.chunk.write(Instruction::Pop, position);
-                self.chunk.write(
-                    Instruction::Loop((self.chunk.code.len() - condition_forwardpatch) as u16),
-                    position,
-                );
-                self.chunk.patch(body_backpatch);
-                self.emit_statement(body, position)?;
-                self.chunk.write(
-                    Instruction::Loop((self.chunk.code.len() - inc_forwardpatch) as u16),
-                    position,
-                );
-                self.chunk.patch(break_backpatch);
-                self.chunk.write(Instruction::Pop, position);
-                self.local_depth -= 1;
+                self.emit_loop(condition.as_deref(), inc.as_deref(), body, position)?;
+                self.end_scope(position)?;
             }
             Statement::ForWithInit(init, condition, inc, body) => {
                 self.local_depth += 1;
                 if let Some(init) = init {
                     self.emit_statement(init, position)?;
                 }
-                let condition_forwardpatch = self.chunk.code.len();
-                if let Some(condition) = condition {
-                    self.emit_expression(condition, position)?;
-                }
-                let break_backpatch = self.chunk.spare_jumpfalse(position);
-                self.chunk.write(Instruction::Pop, position);
-                let body_backpatch = self.chunk.spare_jump(position);
-                let inc_forwardpatch = self.chunk.code.len();
-                if let Some(inc) = inc {
-                    self.emit_expression(inc, position)?;
-                }
-                self.chunk.write(Instruction::Pop, position);
-                self.chunk.write(
-                    Instruction::Loop((self.chunk.code.len() - condition_forwardpatch) as u16),
-                    position,
-                );
-                self.chunk.patch(body_backpatch);
-                self.emit_statement(body, position)?;
-                self.chunk.write(
-                    Instruction::Loop((self.chunk.code.len() - inc_forwardpatch) as u16),
-                    position,
-                );
-                self.chunk.patch(break_backpatch);
-                self.chunk.write(Instruction::Pop, position);
-                self.local_depth -= 1;
+                self.emit_loop(condition.as_deref(), inc.as_deref(), body, position)?;
+                self.end_scope(position)?;
             }
             Statement::Block(statements, positions) => {
                 self.local_depth += 1;
                 for (statement, position) in statements.iter().zip(positions) {
-                    self.emit_statement(statement, position)?;
-                }
-                while let Some(local) = self.locals.peek() {
-                    if local.depth == self.local_depth {
-                        self.chunk.write(Instruction::Pop, position);
-                        self.locals.pop()?;
-                    } else {
-                        break;
-                    }
+                    self.emit_recovering(statement, position);
                 }
-                self.local_depth -= 1;
+                self.end_scope(position)?;
             }
             Statement::Expressional(expression) => {
                 self.emit_expression(expression, position)?;
@@ -199,27 +206,22 @@ impl<'a> Compiler<'a> {
                 let index = self.emit_constant(Constant::String((*string).clone()), position)?;
                 self.chunk.write(Instruction::Constant(index), position);
             }
+            Expression::Int(int) => {
+                let index = self.emit_constant(Constant::Int(*int), position)?;
+                self.chunk.write(Instruction::Constant(index), position);
+            }
             Expression::Number(number) => {
                 let index = self.emit_constant(Constant::Number(*number), position)?;
                 self.chunk.write(Instruction::Constant(index), position);
             }
-            Expression::Identifier(identifier) => {
-                let index =
-                    self.emit_constant(Constant::String((*identifier).clone()), position)?;
-                let mut is_local = false;
-                for slot in (0..self.locals.len()).rev() {
-                    let local = &self.locals[slot];
-                    if local.name == **identifier {
-                        self.chunk
-                            .write(Instruction::GetLocal(slot as u8), position);
-                        is_local = true;
-                        break;
-                    }
-                }
-                if !is_local {
+            Expression::Identifier(identifier) => match self.resolutions.local(expression) {
+                Some(slot) => self.chunk.write(Instruction::GetLocal(slot), position),
+                None => {
+                    let index =
+                        self.emit_constant(Constant::String((*identifier).clone()), position)?;
                     self.chunk.write(Instruction::GetGlobal(index), position);
                 }
-            }
+            },
             Expression::True => self.chunk.write(Instruction::True, position),
             Expression::False => self.chunk.write(Instruction::False, position),
             Expression::Nil => self.chunk.write(Instruction::Nil, position),
@@ -233,22 +235,15 @@ impl<'a> Compiler<'a> {
             }
             Expression::Assign(target, source) => match &**target {
                 Expression::Identifier(identifier) => {
-                    let index =
-                        self.emit_constant(Constant::String((*identifier).clone()), position)?;
-                    self.emit_expression(&source, position)?;
-                    let mut is_local = false;
-                    for slot in (0..self.locals.len()).rev() {
-                        let local = &self.locals[slot];
-                        if local.name == **identifier {
-                            self.chunk
-                                .write(Instruction::SetLocal(slot as u8), position);
-                            is_local = true;
-                            break;
+                    self.emit_expression(source, position)?;
+                    match self.resolutions.local(target) {
+                        Some(slot) => self.chunk.write(Instruction::SetLocal(slot), position),
+                        None => {
+                            let index = self
+                                .emit_constant(Constant::String((*identifier).clone()), position)?;
+                            self.chunk.write(Instruction::SetGlobal(index), position);
                         }
                     }
-                    if !is_local {
-                        self.chunk.write(Instruction::SetGlobal(index), position);
-                    }
                 }
                 _ => {
                     return self.report(
@@ -260,13 +255,23 @@ impl<'a> Compiler<'a> {
                 }
             },
             Expression::Arithmetic(left, operator, right) => {
-                self.emit_expression(&left, position)?;
-                self.emit_expression(&right, position)?;
+                let constant = match **right {
+                    Expression::Int(int) => Some(Constant::Int(int)),
+                    Expression::Number(number) => Some(Constant::Number(number)),
+                    _ => None,
+                };
+                if let Some(constant) = constant {
+                    if self.emit_fused_arithmetic(left, operator, constant, position)? {
+                        return Ok(());
+                    }
+                }
+                self.emit_expression(left, position)?;
+                self.emit_expression(right, position)?;
                 match operator {
                     Token::Plus => self.chunk.write(Instruction::Add, position),
                     Token::Minus => self.chunk.write(Instruction::Subtract, position),
                     Token::Star => self.chunk.write(Instruction::Multiply, position),
-                    Token::Slash => self.chunk.write(Instruction::Multiply, position),
+                    Token::Slash => self.chunk.write(Instruction::Divide, position),
                     Token::Greater => self.chunk.write(Instruction::Greater, position),
                     Token::Less => self.chunk.write(Instruction::Less, position),
                     Token::EqualEqual => self.chunk.write(Instruction::Equal, position),
@@ -291,23 +296,144 @@ impl<'a> Compiler<'a> {
                     let patch = self.chunk.spare_jumpfalse(position);
                     self.chunk.write(Instruction::Pop, position);
                     self.emit_expression(right, position)?;
-                    self.chunk.patch(patch);
+                    self.chunk.patch(patch, position)?;
                 }
                 Token::Or => {
                     self.emit_expression(left, position)?;
                     let false_patch = self.chunk.spare_jumpfalse(position);
                     let patch = self.chunk.spare_jump(position);
-                    self.chunk.patch(false_patch);
+                    self.chunk.patch(false_patch, position)?;
                     self.chunk.write(Instruction::Pop, position);
                     self.emit_expression(right, position)?;
-                    self.chunk.patch(patch);
+                    self.chunk.patch(patch, position)?;
                 }
                 _ => unreachable!("emit failure due to parse error at logic expressions."),
             },
+            Expression::Call(callee, arguments) => {
+                if arguments.len() > u8::MAX as usize {
+                    return self.report(
+                        position,
+                        "E0009",
+                        "too many arguments in one call",
+                        "call within this statement",
+                    );
+                }
+                self.emit_expression(callee, position)?;
+                for argument in arguments {
+                    self.emit_expression(argument, position)?;
+                }
+                self.chunk
+                    .write(Instruction::Call(arguments.len() as u8), position);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits code only to check it: whatever `emit` writes to the chunk is dropped again, but
+    /// its diagnostics are kept. Dead branches still report invalid targets and the like.
+    fn emit_discarded(
+        &mut self,
+        emit: impl FnOnce(&mut Self) -> InterpretResult,
+    ) -> InterpretResult {
+        let (code, constants) = (self.chunk.code.len(), self.chunk.constants.len());
+        let result = emit(self);
+        self.chunk.code.truncate(code);
+        self.chunk.positions.truncate(code);
+        self.chunk.constants.truncate(constants);
+        result
+    }
+
+    /// Emits a loop which tests `condition` before every iteration. A missing condition loops
+    /// forever, and constant ones need no test at all. Loops which never run are discarded.
+    fn emit_loop(
+        &mut self,
+        condition: Option<&Expression>,
+        inc: Option<&Expression>,
+        body: &Statement,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        let condition = match condition.map(|condition| (condition, truthiness(condition))) {
+            Some((_, Some(false))) => {
+                return self
+                    .emit_discarded(|compiler| compiler.emit_loop(None, inc, body, position))
+            }
+            Some((condition, None)) => Some(condition),
+            _ => None,
+        };
+        let start = self.chunk.code.len();
+        let exit_patch = match condition {
+            Some(condition) => {
+                self.emit_expression(condition, position)?;
+                let patch = self.chunk.spare_jumpfalse(position);
+                self.chunk.write(Instruction::Pop, position);
+                Some(patch)
+            }
+            None => None,
+        };
+        self.emit_statement(body, position)?;
+        if let Some(inc) = inc {
+            self.emit_expression(inc, position)?;
+            self.chunk.write(Instruction::Pop, position);
+        }
+        self.chunk.write_loop(start, position)?;
+        if let Some(exit_patch) = exit_patch {
+            self.chunk.patch(exit_patch, position)?;
+            self.chunk.write(Instruction::Pop, position);
+        }
+        Ok(())
+    }
+
+    /// Discards the locals of the innermost scope with a single instruction.
+    fn end_scope(&mut self, position: &Range<usize>) -> InterpretResult {
+        let mut count = 0;
+        while let Some(local) = self.locals.peek() {
+            if local.depth != self.local_depth {
+                break;
+            }
+            self.locals.pop()?;
+            count += 1;
+        }
+        // A full locals stack holds one more local than a u8 operand can count.
+        while count > 0 {
+            let popped = count.min(u8::MAX as usize);
+            match popped {
+                1 => self.chunk.write(Instruction::Pop, position),
+                _ => self.chunk.write(Instruction::PopN(popped as u8), position),
+            }
+            count -= popped;
         }
+        self.local_depth -= 1;
         Ok(())
     }
 
+    /// Emits superinstructions for arithmetic whose right operand is a number literal.
+    /// Returns `false` if the operator has no fused form.
+    fn emit_fused_arithmetic(
+        &mut self,
+        left: &Expression,
+        operator: &Token,
+        constant: Constant,
+        position: &Range<usize>,
+    ) -> InterpretResult<bool> {
+        let fused: fn(u8) -> Instruction = match operator {
+            Token::Plus => Instruction::AddConstant,
+            Token::Less => Instruction::LessConstant,
+            Token::Greater => Instruction::GreaterConstant,
+            _ => return Ok(false),
+        };
+        let index = self.emit_constant(constant, position)?;
+        match (operator, self.resolutions.local(left)) {
+            (Token::Plus, Some(slot)) => self
+                .chunk
+                .write(Instruction::GetLocalAddConstant(slot, index), position),
+            _ => {
+                self.emit_expression(left, position)?;
+                self.chunk.write(fused(index), position);
+            }
+        }
+        Ok(true)
+    }
+
     fn emit_constant(
         &mut self,
         constant: Constant,
@@ -347,11 +473,179 @@ impl<'a> Compiler<'a> {
     }
 }
 
-pub fn compile(file_id: usize, source: impl AsRef<str>) -> InterpretResult<Chunk> {
-    let scanned = scanner::scan(file_id, source.as_ref())?;
-    let parsed = parser::parse(file_id, &scanned)?;
+/// Adds the diagnostics of a failed statement to the ones collected so far.
+fn record_error(errors: &mut Vec<ErrorItem>, error: InterpretError) {
+    let diagnostics = match error {
+        InterpretError::Simple(diagnostic) => vec![diagnostic],
+        InterpretError::Compound(diagnostics) => diagnostics,
+    };
+    for diagnostic in diagnostics {
+        // Once the constant pool is full, every later constant fails the same way.
+        let exhausted = |diagnostic: &ErrorItem| diagnostic.code.as_deref() == Some("E0001");
+        if !(exhausted(&diagnostic) && errors.iter().any(exhausted)) {
+            errors.push(diagnostic);
+        }
+    }
+}
+
+/// Truthiness of conditions made of literals only, which is known at compile time.
+fn truthiness(expression: &Expression) -> Option<bool> {
+    match expression {
+        Expression::True | Expression::Int(_) | Expression::Number(_) | Expression::String(_) => {
+            Some(true)
+        }
+        Expression::False | Expression::Nil => Some(false),
+        Expression::Unary(Token::Bang, operand) => truthiness(operand).map(|truthy| !truthy),
+        // The right operand is never evaluated once the left one decides.
+        Expression::Logic(left, Token::And, right) => match truthiness(left)? {
+            true => truthiness(right),
+            false => Some(false),
+        },
+        Expression::Logic(left, Token::Or, right) => match truthiness(left)? {
+            true => Some(true),
+            false => truthiness(right),
+        },
+        _ => None,
+    }
+}
+
+/// A successfully compiled chunk with the warnings found along the way.
+pub struct CompileOutput<C = Chunk> {
+    pub chunk: C,
+    pub warnings: Warnings,
+}
+
+/// Runs every stage, from scanning to emitting bytecode, with the default lint rules.
+pub fn compile(file_id: usize, source: impl AsRef<str>) -> InterpretResult<CompileOutput> {
+    compile_with(file_id, source, &LintRules::default())
+}
+
+/// Runs every stage, linting with the given rules.
+pub fn compile_with(
+    file_id: usize,
+    source: impl AsRef<str>,
+    rules: &LintRules,
+) -> InterpretResult<CompileOutput> {
+    limited(rules.error_limit(), || {
+        let scanned = scan(file_id, source.as_ref())?;
+        let parsed = parse_within(file_id, &scanned, rules.error_limit())?;
+        let mut output = emit(file_id, &parsed, rules)?;
+        output.chunk.attach_source(source.as_ref());
+        Ok(output)
+    })
+}
+
+/// Compiles the source like [`compile_with`], except that the value of a final expression
+/// statement is left on the stack, for hosts which evaluate code and want its result.
+pub fn compile_evaluation(
+    file_id: usize,
+    source: impl AsRef<str>,
+    rules: &LintRules,
+) -> InterpretResult<CompileOutput> {
+    limited(rules.error_limit(), || {
+        let scanned = scan(file_id, source.as_ref())?;
+        let parsed = parse_within(file_id, &scanned, rules.error_limit())?;
+        let mut output = emit_program(file_id, &parsed, rules, true)?;
+        output.chunk.attach_source(source.as_ref());
+        Ok(output)
+    })
+}
+
+/// Emits bytecode for a parsed program. The AST does not keep its source, so the line index
+/// of the chunk is left for the caller to fill.
+pub fn emit(
+    file_id: usize,
+    parsed: &ParsedContext,
+    rules: &LintRules,
+) -> InterpretResult<CompileOutput> {
+    emit_program(file_id, parsed, rules, false)
+}
+
+pub(crate) fn emit_program(
+    file_id: usize,
+    parsed: &ParsedContext,
+    rules: &LintRules,
+    keep_value: bool,
+) -> InterpretResult<CompileOutput> {
+    let resolutions = resolve(file_id, parsed, rules)?;
     let mut chunk = Chunk::new(file_id);
-    Compiler::new(file_id, &parsed, &mut chunk).compile()?;
+    chunk.name = Some("<script>".into());
+    let mut compiler = Compiler::new(file_id, parsed, &resolutions, &mut chunk);
+    compiler.keep_value = keep_value;
+    compiler.compile()?;
     chunk.write(Instruction::Return, &(0..0));
-    Ok(chunk)
+    Ok(CompileOutput {
+        chunk,
+        warnings: lint_program(file_id, parsed, rules, keep_value)?,
+    })
+}
+
+/// Checks the source against the given lint rules, without emitting any bytecode.
+pub fn check(
+    file_id: usize,
+    source: impl AsRef<str>,
+    rules: &LintRules,
+) -> InterpretResult<Warnings> {
+    limited(rules.error_limit(), || {
+        let scanned = scan(file_id, source.as_ref())?;
+        let parsed = parse_within(file_id, &scanned, rules.error_limit())?;
+        resolve(file_id, &parsed, rules)?;
+        lint(file_id, &parsed, rules)
+    })
+}
+
+// Runs the stages of compilation, cutting the errors they report down to `error_limit`.
+// The parser stops at the limit by itself, while the other stages report all they find.
+fn limited<T>(
+    error_limit: usize,
+    stages: impl FnOnce() -> InterpretResult<T>,
+) -> InterpretResult<T> {
+    stages().map_err(|error| error.truncated(error_limit))
+}
+
+/// Parses the source and renders its AST as S-expressions, without compiling it.
+pub fn dump_ast(file_id: usize, source: impl AsRef<str>) -> InterpretResult<String> {
+    let scanned = scan(file_id, source.as_ref())?;
+    Ok(parse(file_id, &scanned)?.dump())
+}
+
+/// Compiles the source for the experimental register machine.
+#[cfg(feature = "register")]
+pub fn compile_register(
+    file_id: usize,
+    source: impl AsRef<str>,
+) -> InterpretResult<CompileOutput<shared::register::RegisterChunk>> {
+    compile_register_with(file_id, source, &LintRules::default())
+}
+
+/// Compiles the source for the experimental register machine, linting with the given rules.
+#[cfg(feature = "register")]
+pub fn compile_register_with(
+    file_id: usize,
+    source: impl AsRef<str>,
+    rules: &LintRules,
+) -> InterpretResult<CompileOutput<shared::register::RegisterChunk>> {
+    limited(rules.error_limit(), || {
+        let scanned = scan(file_id, source.as_ref())?;
+        let parsed = parse_within(file_id, &scanned, rules.error_limit())?;
+        emit_register(file_id, &parsed, rules)
+    })
+}
+
+#[cfg(feature = "register")]
+fn emit_register(
+    file_id: usize,
+    parsed: &ParsedContext,
+    rules: &LintRules,
+) -> InterpretResult<CompileOutput<shared::register::RegisterChunk>> {
+    use shared::register::{RegisterChunk, RegisterInstruction};
+
+    let resolutions = resolve(file_id, parsed, rules)?;
+    let mut chunk = RegisterChunk::new(file_id);
+    register::RegisterCompiler::new(file_id, parsed, &resolutions, &mut chunk).compile()?;
+    chunk.write(RegisterInstruction::Return, &(0..0));
+    Ok(CompileOutput {
+        chunk,
+        warnings: lint(file_id, parsed, rules)?,
+    })
 }
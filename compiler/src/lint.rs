@@ -0,0 +1,391 @@
+//! Static checks over the AST which produce warnings instead of errors.
+//!
+//! Linting runs after a successful compilation, so it may assume the program is well-formed
+//! and only has to care about things that are legal but likely mistakes. Each check is a
+//! [`Rule`] which can be turned on or off through [`LintRules`].
+
+use std::{collections::HashSet, ops::Range};
+
+use shared::error::{ErrorItem, InterpretResult, Label, Warnings};
+
+use crate::{
+    parser::{Expression, ParsedContext, Statement},
+    scanner::Token,
+    visit::{walk_expression, walk_program, walk_statement, Visitor},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    UnusedVariable,
+    NoEffect,
+    Shadowing,
+    BoolComparison,
+    EmptyBlock,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::UnusedVariable,
+        Rule::NoEffect,
+        Rule::Shadowing,
+        Rule::BoolComparison,
+        Rule::EmptyBlock,
+    ];
+
+    /// The name used to refer to the rule on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::NoEffect => "no-effect",
+            Rule::Shadowing => "shadowing",
+            Rule::BoolComparison => "bool-comparison",
+            Rule::EmptyBlock => "empty-block",
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "W0001",
+            Rule::NoEffect => "W0002",
+            Rule::Shadowing => "W0003",
+            Rule::BoolComparison => "W0004",
+            Rule::EmptyBlock => "W0005",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+/// Which rules a lint pass checks, and whether their warnings fail the compilation. Shadowing
+/// is legal and common in Lox, so it is the only rule left off by default.
+#[derive(Clone)]
+pub struct LintRules {
+    enabled: [bool; Rule::ALL.len()],
+    deny: bool,
+    // Globals the host defines, like natives, if undeclared names are to be rejected.
+    strict: Option<HashSet<String>>,
+    error_limit: usize,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        let mut rules = Self::all();
+        rules.disable(Rule::Shadowing);
+        rules
+    }
+}
+
+impl LintRules {
+    pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+    pub fn all() -> Self {
+        Self {
+            enabled: [true; Rule::ALL.len()],
+            deny: false,
+            strict: None,
+            error_limit: Self::DEFAULT_ERROR_LIMIT,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            enabled: [false; Rule::ALL.len()],
+            deny: false,
+            strict: None,
+            error_limit: Self::DEFAULT_ERROR_LIMIT,
+        }
+    }
+
+    pub fn enable(&mut self, rule: Rule) {
+        self.enabled[rule as usize] = true;
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.enabled[rule as usize] = false;
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.enabled[rule as usize]
+    }
+
+    /// Makes any warning an error, so that nothing gets run until the code is clean.
+    pub fn deny_warnings(&mut self) {
+        self.deny = true;
+    }
+
+    pub fn denies_warnings(&self) -> bool {
+        self.deny
+    }
+
+    /// Makes reading or assigning a global which no `var` in the program declares an error at
+    /// compile time, rather than at runtime. Names the host defines before the program runs,
+    /// like natives, must be given as `globals`; calling this again adds to them.
+    pub fn strict<'a>(&mut self, globals: impl IntoIterator<Item = &'a str>) {
+        self.strict
+            .get_or_insert_with(HashSet::new)
+            .extend(globals.into_iter().map(String::from));
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict.is_some()
+    }
+
+    /// Stops compiling once `limit` errors are found, as later ones are mostly caused by them.
+    /// The errors end with one saying that there were too many.
+    pub fn set_error_limit(&mut self, limit: usize) {
+        self.error_limit = limit;
+    }
+
+    pub fn error_limit(&self) -> usize {
+        self.error_limit
+    }
+
+    /// The globals defined by the host, in strict mode.
+    pub(crate) fn host_globals(&self) -> Option<&HashSet<String>> {
+        self.strict.as_ref()
+    }
+}
+
+struct Local {
+    depth: usize,
+    name: String,
+    position: Range<usize>,
+    used: bool,
+}
+
+struct Linter<'a> {
+    file_id: usize,
+    rules: &'a LintRules,
+    locals: Vec<Local>,
+    globals: HashSet<String>,
+    local_depth: usize,
+    // Position of the statement being linted.
+    position: Range<usize>,
+    // The final statement when its value is kept for the host, which gives it an effect.
+    kept: Option<*const ()>,
+    warnings: Warnings,
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_statement(&mut self, statement: &Statement, position: &Range<usize>) {
+        self.position = position.clone();
+        match statement {
+            Statement::VarDeclaration(name, _) => {
+                walk_statement(self, statement, position);
+                if self.local_depth == 0 {
+                    self.globals.insert((*name).clone());
+                } else {
+                    self.check_shadowing(name, position);
+                    self.locals.push(Local {
+                        depth: self.local_depth,
+                        name: (*name).clone(),
+                        position: position.clone(),
+                        used: false,
+                    });
+                }
+            }
+            Statement::Block(statements, _) if statements.is_empty() => {
+                self.warn(
+                    position,
+                    Rule::EmptyBlock,
+                    "empty block",
+                    "this block does nothing",
+                );
+            }
+            Statement::ForWithInit(..) | Statement::Block(..) => {
+                self.local_depth += 1;
+                walk_statement(self, statement, position);
+                self.end_scope();
+            }
+            Statement::Expressional(expression) => {
+                let kept = self.kept == Some(statement as *const Statement as *const ());
+                if !kept && !has_effect(expression) {
+                    self.warn(
+                        position,
+                        Rule::NoEffect,
+                        "statement has no effect",
+                        "the value of this statement is discarded",
+                    );
+                }
+                walk_statement(self, statement, position);
+            }
+            _ => walk_statement(self, statement, position),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(identifier) => {
+                if let Some(local) = self
+                    .locals
+                    .iter_mut()
+                    .rev()
+                    .find(|local| &local.name == *identifier)
+                {
+                    local.used = true;
+                }
+            }
+            // Assigning to a variable is not a use of it.
+            Expression::Assign(target, source) => {
+                if !matches!(**target, Expression::Identifier(_)) {
+                    self.visit_expression(target);
+                }
+                self.visit_expression(source);
+            }
+            Expression::Arithmetic(left, Token::EqualEqual | Token::BangEqual, right)
+                if is_bool_literal(left) || is_bool_literal(right) =>
+            {
+                // Expressions carry no position, so point at the statement they belong to.
+                let position = self.position.clone();
+                self.warn(
+                    &position,
+                    Rule::BoolComparison,
+                    "comparison to a boolean literal",
+                    "this compares against `true` or `false`",
+                );
+                walk_expression(self, expression);
+            }
+            _ => walk_expression(self, expression),
+        }
+    }
+}
+
+impl<'a> Linter<'a> {
+    fn new(file_id: usize, rules: &'a LintRules) -> Self {
+        Self {
+            file_id,
+            rules,
+            locals: Vec::new(),
+            globals: HashSet::new(),
+            local_depth: 0,
+            position: 0..0,
+            kept: None,
+            warnings: Warnings::new(),
+        }
+    }
+
+    /// Warns about a local declaration hiding a variable of an outer scope. Redeclarations in
+    /// the same scope are already rejected by the resolver.
+    fn check_shadowing(&mut self, name: &str, position: &Range<usize>) {
+        let outer = self
+            .locals
+            .iter()
+            .rev()
+            .find(|local| local.name == name && local.depth < self.local_depth)
+            .map(|local| local.position.clone());
+        let shadowed = match outer {
+            Some(outer) => Some(
+                Label::secondary(self.file_id, outer)
+                    .with_message("shadowed variable declared here"),
+            ),
+            None if self.globals.contains(name) => None,
+            None => return,
+        };
+        if !self.rules.is_enabled(Rule::Shadowing) {
+            return;
+        }
+        let mut labels = vec![Label::primary(self.file_id, position.clone())
+            .with_message(format!("`{}` shadows an outer variable", name))];
+        labels.extend(shadowed);
+        self.warnings.push(
+            ErrorItem::warning()
+                .with_code(Rule::Shadowing.code())
+                .with_message(format!(
+                    "local variable `{}` shadows another variable",
+                    name
+                ))
+                .with_labels(labels),
+        );
+    }
+
+    fn end_scope(&mut self) {
+        self.local_depth -= 1;
+        let first = self
+            .locals
+            .iter()
+            .position(|local| local.depth > self.local_depth)
+            .unwrap_or(self.locals.len());
+        for local in self.locals.split_off(first) {
+            // Names starting with an underscore are intentionally unused.
+            if !local.used && !local.name.starts_with('_') {
+                self.warn(
+                    &local.position,
+                    Rule::UnusedVariable,
+                    format!("unused local variable `{}`", local.name),
+                    "declared here but never read",
+                );
+            }
+        }
+    }
+
+    fn warn(
+        &mut self,
+        position: &Range<usize>,
+        rule: Rule,
+        message: impl Into<String>,
+        label: impl Into<String>,
+    ) {
+        if !self.rules.is_enabled(rule) {
+            return;
+        }
+        self.warnings.push(
+            ErrorItem::warning()
+                .with_code(rule.code())
+                .with_message(message)
+                .with_labels(vec![
+                    Label::primary(self.file_id, position.clone()).with_message(label)
+                ]),
+        );
+    }
+}
+
+fn is_bool_literal(expression: &Expression) -> bool {
+    matches!(expression, Expression::True | Expression::False)
+}
+
+/// Whether evaluating the expression can change the program state. Runtime errors raised by
+/// operators don't count.
+fn has_effect(expression: &Expression) -> bool {
+    match expression {
+        Expression::Assign(..) | Expression::Call(..) => true,
+        Expression::Unary(_, operand) => has_effect(operand),
+        Expression::Arithmetic(left, _, right) | Expression::Logic(left, _, right) => {
+            has_effect(left) || has_effect(right)
+        }
+        _ => false,
+    }
+}
+
+/// Checks the program against the enabled rules. Warnings are returned as errors if the rules
+/// deny them.
+pub fn lint(
+    file_id: usize,
+    parsed_context: &ParsedContext,
+    rules: &LintRules,
+) -> InterpretResult<Warnings> {
+    lint_program(file_id, parsed_context, rules, false)
+}
+
+/// Lints like [`lint`]. With `keep_value`, a final expression statement counts as having an
+/// effect, since its value is handed back to the host.
+pub(crate) fn lint_program(
+    file_id: usize,
+    parsed_context: &ParsedContext,
+    rules: &LintRules,
+    keep_value: bool,
+) -> InterpretResult<Warnings> {
+    let mut linter = Linter::new(file_id, rules);
+    if keep_value {
+        linter.kept = parsed_context
+            .statements
+            .last()
+            .map(|statement| statement as *const Statement as *const ());
+    }
+    walk_program(&mut linter, parsed_context);
+    match rules.deny && !linter.warnings.is_empty() {
+        true => Err(linter.warnings.into_errors()),
+        false => Ok(linter.warnings),
+    }
+}
@@ -1,41 +1,136 @@
-use std::{cell::RefCell, mem, ops::Range};
+// Rules generated by peg take every grammar argument plus its own internals.
+#![allow(clippy::too_many_arguments)]
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Write},
+    mem,
+    ops::Range,
+};
 
 use shared::error::{ErrorItem, InterpretError, InterpretResult, Label};
 
-use crate::scanner::{ScannedContext, Token};
+use crate::scanner::{internal_error, ScannedContext, Token};
 
 pub struct ParsedContext<'a> {
     pub statements: Vec<Statement<'a>>,
     pub positions: Vec<Range<usize>>,
     pub errors: Vec<ErrorItem>,
     panic_mode: bool,
+    // Parsing stops once this many errors are reported.
+    error_limit: usize,
 }
 
 impl<'a> ParsedContext<'a> {
-    fn new() -> Self {
+    fn new(error_limit: usize) -> Self {
         Self {
             statements: Vec::new(),
             positions: Vec::new(),
             errors: Vec::new(),
             panic_mode: false,
+            error_limit,
         }
     }
 
+    fn within_limit(&self) -> bool {
+        self.errors.len() < self.error_limit
+    }
+
     fn record(&mut self, statement: Statement<'a>, position: &Range<usize>) {
         self.statements.push(statement);
         self.positions.push(position.clone());
     }
 
+    /// Reports an error, unless one was already reported since the last synchronization point.
+    /// Later errors are most likely caused by the first one, as the parser lost track of where
+    /// the broken statement ends.
     fn report(&mut self, error: ErrorItem) {
-        self.errors.push(error);
+        if !self.panic_mode {
+            self.errors.push(error);
+        }
         self.panic_mode = true;
     }
+
+    /// Renders the statements as S-expressions, one top-level statement per line and nested
+    /// statements indented below their parents.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        for statement in &self.statements {
+            dump_statement(&mut output, statement, 0);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn dump_statement(output: &mut String, statement: &Statement, indent: usize) {
+    let child = |output: &mut String, statement: &Statement| {
+        write!(output, "\n{:width$}", "", width = (indent + 1) * 2).unwrap();
+        dump_statement(output, statement, indent + 1);
+    };
+    let optional = |expression: &Option<Box<Expression>>| match expression {
+        Some(expression) => expression.to_string(),
+        None => "_".to_string(),
+    };
+
+    match statement {
+        Statement::VarDeclaration(name, Some(initializer)) => {
+            write!(output, "(var {} {})", name, initializer).unwrap()
+        }
+        Statement::VarDeclaration(name, None) => write!(output, "(var {})", name).unwrap(),
+        Statement::Print(expression) => write!(output, "(print {})", expression).unwrap(),
+        Statement::If(condition, then, otherwise) => {
+            write!(output, "(if {}", condition).unwrap();
+            child(output, then);
+            if let Some(otherwise) = otherwise {
+                child(output, otherwise);
+            }
+            output.push(')');
+        }
+        Statement::While(condition, body) => {
+            write!(output, "(while {}", condition).unwrap();
+            child(output, body);
+            output.push(')');
+        }
+        Statement::For(initializer, condition, increment, body) => {
+            write!(
+                output,
+                "(for {} {} {}",
+                optional(initializer),
+                optional(condition),
+                optional(increment)
+            )
+            .unwrap();
+            child(output, body);
+            output.push(')');
+        }
+        Statement::ForWithInit(initializer, condition, increment, body) => {
+            output.push_str("(for ");
+            match initializer {
+                Some(initializer) => dump_statement(output, initializer, indent + 1),
+                None => output.push('_'),
+            }
+            write!(output, " {} {}", optional(condition), optional(increment)).unwrap();
+            child(output, body);
+            output.push(')');
+        }
+        Statement::Block(statements, _) => {
+            output.push_str("(block");
+            for statement in statements {
+                child(output, statement);
+            }
+            output.push(')');
+        }
+        Statement::Expressional(expression) => write!(output, "(expr {})", expression).unwrap(),
+        Statement::Error => output.push_str("(error)"),
+    }
 }
 
 pub enum Expression<'a> {
     // Literal expressions. Since we've known their types at parsing time, we don't have
     // to store Token and match its type later.
     String(&'a String),
+    Int(i64),
     Number(f64),
     Identifier(&'a String),
     True,
@@ -46,6 +141,34 @@ pub enum Expression<'a> {
     Assign(Box<Expression<'a>>, Box<Expression<'a>>),
     Arithmetic(Box<Expression<'a>>, &'a Token, Box<Expression<'a>>),
     Logic(Box<Expression<'a>>, &'a Token, Box<Expression<'a>>),
+    Call(Box<Expression<'a>>, Vec<Expression<'a>>),
+}
+
+impl Display for Expression<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::String(string) => write!(f, "{:?}", string),
+            Expression::Int(int) => write!(f, "{}", int),
+            Expression::Number(number) => write!(f, "{}", number),
+            Expression::Identifier(identifier) => write!(f, "{}", identifier),
+            Expression::True => write!(f, "true"),
+            Expression::False => write!(f, "false"),
+            Expression::Nil => write!(f, "nil"),
+            Expression::Unary(operator, operand) => write!(f, "({} {})", operator, operand),
+            Expression::Assign(target, source) => write!(f, "(= {} {})", target, source),
+            Expression::Arithmetic(left, operator, right)
+            | Expression::Logic(left, operator, right) => {
+                write!(f, "({} {} {})", operator, left, right)
+            }
+            Expression::Call(callee, arguments) => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
 }
 
 pub enum Statement<'a> {
@@ -78,29 +201,31 @@ pub enum Statement<'a> {
 
 peg::parser!(grammar pegparser(
     file_id: usize,
-    token_positions: &'input Vec<Range<usize>>,
+    token_positions: &'input [Range<usize>],
     context: &RefCell<ParsedContext<'input>>
 ) for ScannedContext {
 
     pub rule parse()
-        = ds:top_declaration()*
+        = (within_limit() (synchronize() / top_declaration()))*
+
+    // Fails once the error limit is reached, which ends every repetition of declarations.
+    rule within_limit()
+        = {? if context.borrow().within_limit() { Ok(()) } else { Err("fewer errors") } }
 
     rule top_declaration()
-        = pos:position!() s:_declaration() { context.borrow_mut().record(s, &token_positions[pos]) }
+        = recovered() pos:position!() s:_declaration() { context.borrow_mut().record(s, &token_positions[pos]) }
         // This is weird because statements are often not allowed to be top-level.
         / pos:position!() s:statement() { context.borrow_mut().record(s, &token_positions[pos]) }
-        / pos:position!() ![ // Right brace is consumable error token here.
-            Token::Semicolon |
-            Token::LeftBrace |
-            Token::Var
-          ] [t]+ [Token::Semicolon]? {
+        / pos:position!() s:unrecognized_statement() { context.borrow_mut().record(s, &token_positions[pos]) }
+        // Right brace is consumable error token here.
+        / pos:position!() [Token::RightBrace] {
             context.borrow_mut().report(
                 ErrorItem::error()
-                    .with_code("E0005")
-                    .with_message("unrecognized statement")
+                    .with_code("E0017")
+                    .with_message("unmatched closing brace")
                     .with_labels(vec![
                         Label::secondary(file_id, token_positions[pos].clone())
-                            .with_message("statement starting from here is unrecognizable")
+                            .with_message("there's no block to close here")
                     ])
             );
             context.borrow_mut().panic_mode = false;
@@ -108,13 +233,29 @@ peg::parser!(grammar pegparser(
         }
 
     rule inblock_declaration() -> (Statement<'input>, &'input Range<usize>)
-        = start:position!() s:_declaration() { (s, &token_positions[start]) }
+        = recovered() start:position!() s:_declaration() { (s, &token_positions[start]) }
         / start:position!() s:statement() { (s, &token_positions[start]) }
-        / pos:position!() ![Token::RightBrace] /* Right brace is unconsumable boundary in blocks. */ ![
-            Token::Semicolon |
-            Token::LeftBrace |
-            Token::Var
-          ] [t]+ [Token::Semicolon]? {
+        / start:position!() s:unrecognized_statement() { (s, &token_positions[start]) }
+
+    // After an error, skips what is left of the broken statement up to the next synchronization
+    // point, a `;` included. It is tried as a declaration of its own, so the tokens it skips
+    // stay skipped even if nothing follows them.
+    rule synchronize()
+        = panicking() ((!synchronization_point() [_])+ [Token::Semicolon]? / [Token::Semicolon]) {
+            context.borrow_mut().panic_mode = false;
+        }
+
+    // Starts reporting errors again, once nothing is left to skip.
+    rule recovered()
+        = { context.borrow_mut().panic_mode = false; }
+
+    rule panicking()
+        = {? if context.borrow().panic_mode { Ok(()) } else { Err("no error to recover from") } }
+
+    // Skips to the end of the broken statement. At least one token is consumed so that
+    // parsing always makes progress, and right brace is unconsumable boundary in blocks.
+    rule unrecognized_statement() -> Statement<'input>
+        = pos:position!() ![Token::RightBrace] [_] (!synchronization_point() [_])* [Token::Semicolon]? {
             context.borrow_mut().report(
                 ErrorItem::error()
                     .with_code("E0005")
@@ -125,23 +266,34 @@ peg::parser!(grammar pegparser(
                     ])
             );
             context.borrow_mut().panic_mode = false;
-            (Statement::Error, &token_positions[pos])
+            Statement::Error
         }
 
+    rule synchronization_point()
+        = [
+            Token::Semicolon |
+            Token::LeftBrace |
+            Token::RightBrace |
+            Token::Var |
+            Token::Print |
+            Token::If |
+            Token::While |
+            Token::For |
+            Token::Return |
+            Token::Class |
+            Token::Fun
+          ]
+
     rule _declaration() -> Statement<'input>
         = var_declaration()
 
     rule var_declaration() -> Statement<'input>
-        = [Token::Var] name:variable_name() [Token::Equal] init:expression() must_consume(Token::Semicolon) {
-            match name {
-                Some(name) => Statement::VarDeclaration(name, Some(Box::new(init))),
-                None => Statement::Error,
-            }
-        }
-        / [Token::Var] name:variable_name() must_consume(Token::Semicolon) {
-            match name {
-                Some(name) => Statement::VarDeclaration(name, None),
-                None => Statement::Error,
+        = [Token::Var] name:variable_name() init:([Token::Equal] e:must_expression() { e })?
+          must_consume(Token::Semicolon) {
+            match (name, init) {
+                (Some(name), Some(Some(init))) => Statement::VarDeclaration(name, Some(Box::new(init))),
+                (Some(name), None) => Statement::VarDeclaration(name, None),
+                _ => Statement::Error,
             }
         }
 
@@ -170,24 +322,29 @@ peg::parser!(grammar pegparser(
         / expression_statement()
 
     rule print_statement() -> Statement<'input>
-        = [Token::Print] e:expression() must_consume(Token::Semicolon) {
-            Statement::Print(Box::new(e))
+        = [Token::Print] e:must_expression() must_consume(Token::Semicolon) {
+            match e {
+                Some(e) => Statement::Print(Box::new(e)),
+                None => Statement::Error,
+            }
         }
 
     rule if_statement() -> Statement<'input>
-        = [Token::If] must_consume(Token::LeftParenthesis) condition:expression() must_consume(Token::RightParenthesis)
-          then:statement() [Token::Else] otherwise:statement() {
-            Statement::If(Box::new(condition), Box::new(then), Some(Box::new(otherwise)))
-        }
-        / [Token::If] must_consume(Token::LeftParenthesis) condition:expression() must_consume(Token::RightParenthesis)
-          then:statement() {
-            Statement::If(Box::new(condition), Box::new(then), None)
+        = [Token::If] must_consume(Token::LeftParenthesis) condition:must_expression() must_consume(Token::RightParenthesis)
+          then:statement() otherwise:([Token::Else] s:statement() { s })? {
+            match condition {
+                Some(condition) => Statement::If(Box::new(condition), Box::new(then), otherwise.map(Box::new)),
+                None => Statement::Error,
+            }
         }
 
     rule while_statement() -> Statement<'input>
-        = [Token::While] must_consume(Token::LeftParenthesis) condition:expression() must_consume(Token::RightParenthesis)
+        = [Token::While] must_consume(Token::LeftParenthesis) condition:must_expression() must_consume(Token::RightParenthesis)
           body:statement() {
-            Statement::While(Box::new(condition), Box::new(body))
+            match condition {
+                Some(condition) => Statement::While(Box::new(condition), Box::new(body)),
+                None => Statement::Error,
+            }
         }
 
     rule for_statement() -> Statement<'input>
@@ -197,9 +354,9 @@ peg::parser!(grammar pegparser(
           inc:expression()?       must_consume(Token::RightParenthesis)
           body:statement() {
             Statement::For(
-                init.and_then(|e| Some(Box::new(e))),
-                condition.and_then(|e| Some(Box::new(e))),
-                inc.and_then(|e| Some(Box::new(e))),
+                init.map(Box::new),
+                condition.map(Box::new),
+                inc.map(Box::new),
                 Box::new(body),
             )
         }
@@ -211,18 +368,19 @@ peg::parser!(grammar pegparser(
           inc:expression()?       must_consume(Token::RightParenthesis)
           body:statement() {
             Statement::ForWithInit(
-                init.and_then(|e| Some(Box::new(e))),
-                condition.and_then(|e| Some(Box::new(e))),
-                inc.and_then(|e| Some(Box::new(e))),
+                init.map(Box::new),
+                condition.map(Box::new),
+                inc.map(Box::new),
                 Box::new(body),
             )
         }
 
     rule block_statement() -> Statement<'input>
-    = [Token::LeftBrace] ds:inblock_declaration()* must_consume(Token::RightBrace) {
+    = [Token::LeftBrace] ds:(within_limit() d:(synchronize() { None } / d:inblock_declaration() { Some(d) }) { d })*
+      must_consume(Token::RightBrace) {
         let mut statements = Vec::new();
         let mut positions = Vec::new();
-        for (statement, position) in ds {
+        for (statement, position) in ds.into_iter().flatten() {
             statements.push(statement);
             positions.push(position.clone());
         }
@@ -247,6 +405,21 @@ peg::parser!(grammar pegparser(
             );
         }
 
+    rule must_expression() -> Option<Expression<'input>>
+        = e:expression() { Some(e) }
+        / pos:position!() {
+            context.borrow_mut().report(
+                ErrorItem::error()
+                    .with_code("E0016")
+                    .with_message("missing expression")
+                    .with_labels(vec![
+                        Label::secondary(file_id, token_positions[pos - 1].clone())
+                            .with_message("expected expression after this")
+                    ])
+            );
+            None
+        }
+
     rule expression() -> Expression<'input> = precedence! {
         // Assignment
         x:@ op:[Token::Equal] y:(@) { Expression::Assign(Box::new(x), Box::new(y)) }
@@ -266,7 +439,12 @@ peg::parser!(grammar pegparser(
         x:(@) op:[Token::Star | Token::Slash] y:@ { Expression::Arithmetic(Box::new(x), op, Box::new(y)) }
         -- // Unary
         op:[Token::Minus | Token::Bang] e:(@) { Expression::Unary(op, Box::new(e)) }
+        -- // Call
+        callee:(@) [Token::LeftParenthesis] arguments:(expression() ** [Token::Comma]) [Token::RightParenthesis] {
+            Expression::Call(Box::new(callee), arguments)
+        }
         -- // Primary
+        [Token::Int(n)] { Expression::Int(*n) }
         [Token::Number(n)] { Expression::Number(*n) }
         [Token::String(s)] { Expression::String(s) }
         [Token::Identifier(identifier)] { Expression::Identifier(identifier) }
@@ -277,10 +455,40 @@ peg::parser!(grammar pegparser(
     }
 });
 
-pub fn parse(file_id: usize, scanned: &ScannedContext) -> InterpretResult<ParsedContext> {
-    let context = RefCell::new(ParsedContext::new());
-    pegparser::parse(scanned, file_id, &scanned.positions, &context).expect("internal parse error");
-    let context = RefCell::into_inner(context);
+/// Builds the AST from scanned tokens, recovering from errors to report as many as possible.
+pub fn parse(file_id: usize, scanned: &ScannedContext) -> InterpretResult<ParsedContext<'_>> {
+    parse_within(file_id, scanned, usize::MAX)
+}
+
+/// Builds the AST like [`parse`], but gives up once `error_limit` errors are reported, leaving
+/// the rest of the tokens unparsed.
+pub(crate) fn parse_within(
+    file_id: usize,
+    scanned: &ScannedContext,
+    error_limit: usize,
+) -> InterpretResult<ParsedContext<'_>> {
+    let context = RefCell::new(ParsedContext::new(error_limit));
+    let result = pegparser::parse(scanned, file_id, &scanned.positions, &context);
+    let mut context = RefCell::into_inner(context);
+    match result {
+        // Stopping at the limit leaves tokens unparsed, which isn't an error of its own.
+        Err(_) if !context.within_limit() => {
+            context.errors.truncate(error_limit);
+            context.errors.push(InterpretError::too_many(error_limit));
+        }
+        Err(error) => {
+            // The error points at a token, or just past the last one.
+            let span = match scanned.positions.get(error.location) {
+                Some(span) => span.clone(),
+                None => {
+                    let end = scanned.positions.last().map_or(0, |span| span.end);
+                    end..end
+                }
+            };
+            context.report(internal_error(file_id, "parser", span, error.expected));
+        }
+        Ok(()) => {}
+    }
 
     if context.errors.is_empty() {
         Ok(context)
@@ -0,0 +1,524 @@
+//! Code generator for the experimental register machine.
+//!
+//! It walks the same AST as the stack compiler. Locals live in fixed registers for their whole
+//! scope, and temporaries are allocated above them in a stack-like fashion.
+
+use std::{mem, ops::Range};
+
+use shared::{
+    constant::Constant,
+    error::{ErrorItem, InterpretError, InterpretResult, Label},
+    register::{Register, RegisterChunk, RegisterInstruction},
+};
+
+use crate::{
+    parser::{Expression, ParsedContext, Statement},
+    record_error,
+    resolver::Resolutions,
+    scanner::Token,
+    truthiness,
+};
+
+struct Local {
+    depth: usize,
+    register: Register,
+}
+
+pub struct RegisterCompiler<'a> {
+    file_id: usize,
+    parsed_context: &'a ParsedContext<'a>,
+    resolutions: &'a Resolutions<'a>,
+    chunk: &'a mut RegisterChunk,
+    locals: Vec<Local>,
+    local_depth: usize,
+    next_register: usize,
+    errors: Vec<ErrorItem>,
+}
+
+impl<'a> RegisterCompiler<'a> {
+    pub fn new(
+        file_id: usize,
+        parsed_context: &'a ParsedContext,
+        resolutions: &'a Resolutions<'a>,
+        chunk: &'a mut RegisterChunk,
+    ) -> Self {
+        Self {
+            file_id,
+            parsed_context,
+            resolutions,
+            chunk,
+            locals: Vec::new(),
+            local_depth: 0,
+            next_register: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn compile(&mut self) -> InterpretResult {
+        for (statement, position) in self
+            .parsed_context
+            .statements
+            .iter()
+            .zip(self.parsed_context.positions.iter())
+        {
+            self.emit_recovering(statement, position);
+        }
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(InterpretError::Compound(mem::take(&mut self.errors))),
+        }
+    }
+
+    /// Emits a statement of a program or block. A failure is recorded and the scopes and
+    /// registers are restored, so that the statements after it are still checked.
+    fn emit_recovering(&mut self, statement: &Statement, position: &Range<usize>) {
+        let (locals, local_depth, next_register) =
+            (self.locals.len(), self.local_depth, self.next_register);
+        if let Err(error) = self.emit_statement(statement, position) {
+            self.locals.truncate(locals);
+            self.local_depth = local_depth;
+            self.next_register = next_register;
+            record_error(&mut self.errors, error);
+        }
+    }
+
+    fn emit_statement(
+        &mut self,
+        statement: &Statement,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        let mark = self.next_register;
+        match statement {
+            Statement::VarDeclaration(name, initializer) => {
+                let register = self.allocate(position)?;
+                match initializer {
+                    Some(expression) => self.emit_expression(expression, register, position)?,
+                    None => {
+                        self.chunk
+                            .write(RegisterInstruction::LoadNil(register), position);
+                    }
+                }
+                match self.local_depth {
+                    0 => {
+                        let index =
+                            self.emit_constant(Constant::String((*name).clone()), position)?;
+                        self.chunk
+                            .write(RegisterInstruction::DefineGlobal(index, register), position);
+                        self.next_register = mark;
+                    }
+                    // The register stays allocated until the scope ends.
+                    _ => self.locals.push(Local {
+                        depth: self.local_depth,
+                        register,
+                    }),
+                }
+            }
+            Statement::Print(expression) => {
+                let register = self.emit_operand(expression, position)?;
+                self.chunk
+                    .write(RegisterInstruction::Print(register), position);
+                self.next_register = mark;
+            }
+            Statement::If(condition, then, otherwise) => match truthiness(condition) {
+                // Only the branch taken is kept for constant conditions.
+                Some(true) => {
+                    self.emit_statement(then, position)?;
+                    if let Some(otherwise) = otherwise {
+                        self.emit_discarded(|compiler| {
+                            compiler.emit_statement(otherwise, position)
+                        })?;
+                    }
+                }
+                Some(false) => {
+                    self.emit_discarded(|compiler| compiler.emit_statement(then, position))?;
+                    if let Some(otherwise) = otherwise {
+                        self.emit_statement(otherwise, position)?;
+                    }
+                }
+                None => {
+                    let register = self.emit_operand(condition, position)?;
+                    let then_patch = self
+                        .chunk
+                        .write(RegisterInstruction::JumpFalse(register, 0), position);
+                    self.next_register = mark;
+                    self.emit_statement(then, position)?;
+                    match otherwise {
+                        Some(otherwise) => {
+                            let otherwise_patch =
+                                self.chunk.write(RegisterInstruction::Jump(0), position);
+                            self.chunk.patch(then_patch, position)?;
+                            self.emit_statement(otherwise, position)?;
+                            self.chunk.patch(otherwise_patch, position)?;
+                        }
+                        None => self.chunk.patch(then_patch, position)?,
+                    }
+                }
+            },
+            Statement::While(condition, body) => {
+                self.emit_loop(Some(condition), None, body, position)?;
+            }
+            Statement::For(init, condition, inc, body) => {
+                self.local_depth += 1;
+                if let Some(init) = init {
+                    self.emit_operand(init, position)?;
+                    self.next_register = mark;
+                }
+                self.emit_loop(condition.as_deref(), inc.as_deref(), body, position)?;
+                self.end_scope();
+            }
+            Statement::ForWithInit(init, condition, inc, body) => {
+                self.local_depth += 1;
+                if let Some(init) = init {
+                    self.emit_statement(init, position)?;
+                }
+                self.emit_loop(condition.as_deref(), inc.as_deref(), body, position)?;
+                self.end_scope();
+            }
+            Statement::Block(statements, positions) => {
+                self.local_depth += 1;
+                for (statement, position) in statements.iter().zip(positions) {
+                    self.emit_recovering(statement, position);
+                }
+                self.end_scope();
+            }
+            Statement::Expressional(expression) => {
+                self.emit_operand(expression, position)?;
+                self.next_register = mark;
+            }
+            // Unreachable
+            Statement::Error => unreachable!("still trying to emit after reporting diagnostics"),
+        }
+        Ok(())
+    }
+
+    /// Emits code only to check it: whatever `emit` writes to the chunk is dropped again, but
+    /// its diagnostics are kept. Dead branches still report invalid targets and the like.
+    fn emit_discarded(
+        &mut self,
+        emit: impl FnOnce(&mut Self) -> InterpretResult,
+    ) -> InterpretResult {
+        let (code, constants) = (self.chunk.code.len(), self.chunk.constants.len());
+        let result = emit(self);
+        self.chunk.code.truncate(code);
+        self.chunk.positions.truncate(code);
+        self.chunk.constants.truncate(constants);
+        result
+    }
+
+    fn emit_loop(
+        &mut self,
+        condition: Option<&Expression>,
+        inc: Option<&Expression>,
+        body: &Statement,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        let condition = match condition.map(|condition| (condition, truthiness(condition))) {
+            Some((_, Some(false))) => {
+                return self
+                    .emit_discarded(|compiler| compiler.emit_loop(None, inc, body, position))
+            }
+            Some((condition, None)) => Some(condition),
+            _ => None,
+        };
+        let mark = self.next_register;
+        let start = self.chunk.code.len();
+        let exit_patch = match condition {
+            Some(condition) => {
+                let register = self.emit_operand(condition, position)?;
+                self.next_register = mark;
+                Some(
+                    self.chunk
+                        .write(RegisterInstruction::JumpFalse(register, 0), position),
+                )
+            }
+            None => None,
+        };
+        self.emit_statement(body, position)?;
+        if let Some(inc) = inc {
+            self.emit_operand(inc, position)?;
+            self.next_register = mark;
+        }
+        self.chunk.write_loop(start, position)?;
+        if let Some(exit_patch) = exit_patch {
+            self.chunk.patch(exit_patch, position)?;
+        }
+        Ok(())
+    }
+
+    fn end_scope(&mut self) {
+        while let Some(local) = self.locals.last() {
+            if local.depth != self.local_depth {
+                break;
+            }
+            self.next_register = local.register as usize;
+            self.locals.pop();
+        }
+        self.local_depth -= 1;
+    }
+
+    /// Evaluates `expression` into the register `destination`.
+    fn emit_expression(
+        &mut self,
+        expression: &Expression,
+        destination: Register,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        let mark = self.next_register;
+        match expression {
+            Expression::String(string) => {
+                let index = self.emit_constant(Constant::String((*string).clone()), position)?;
+                self.chunk.write(
+                    RegisterInstruction::LoadConstant(destination, index),
+                    position,
+                );
+            }
+            Expression::Int(int) => {
+                let index = self.emit_constant(Constant::Int(*int), position)?;
+                self.chunk.write(
+                    RegisterInstruction::LoadConstant(destination, index),
+                    position,
+                );
+            }
+            Expression::Number(number) => {
+                let index = self.emit_constant(Constant::Number(*number), position)?;
+                self.chunk.write(
+                    RegisterInstruction::LoadConstant(destination, index),
+                    position,
+                );
+            }
+            Expression::Identifier(identifier) => match self.resolve_local(expression) {
+                Some(register) if register == destination => {}
+                Some(register) => {
+                    self.chunk
+                        .write(RegisterInstruction::Move(destination, register), position);
+                }
+                None => {
+                    let index =
+                        self.emit_constant(Constant::String((*identifier).clone()), position)?;
+                    self.chunk
+                        .write(RegisterInstruction::GetGlobal(destination, index), position);
+                }
+            },
+            Expression::True => {
+                self.chunk
+                    .write(RegisterInstruction::LoadTrue(destination), position);
+            }
+            Expression::False => {
+                self.chunk
+                    .write(RegisterInstruction::LoadFalse(destination), position);
+            }
+            Expression::Nil => {
+                self.chunk
+                    .write(RegisterInstruction::LoadNil(destination), position);
+            }
+            Expression::Unary(operator, expression) => {
+                let operand = self.emit_operand(expression, position)?;
+                match operator {
+                    Token::Minus => self
+                        .chunk
+                        .write(RegisterInstruction::Negate(destination, operand), position),
+                    Token::Bang => self
+                        .chunk
+                        .write(RegisterInstruction::Not(destination, operand), position),
+                    _ => unreachable!("emit failure due to parse error at unary expressions."),
+                };
+            }
+            Expression::Assign(target, source) => match &**target {
+                Expression::Identifier(identifier) => {
+                    self.emit_expression(source, destination, position)?;
+                    match self.resolve_local(target) {
+                        Some(register) => self
+                            .chunk
+                            .write(RegisterInstruction::Move(register, destination), position),
+                        None => {
+                            let index = self
+                                .emit_constant(Constant::String((*identifier).clone()), position)?;
+                            self.chunk
+                                .write(RegisterInstruction::SetGlobal(index, destination), position)
+                        }
+                    };
+                }
+                _ => {
+                    return self.report(
+                        position,
+                        "E0008",
+                        "invalid assignment target",
+                        "assignment within this statement",
+                    )
+                }
+            },
+            Expression::Arithmetic(left, operator, right) => {
+                // A local on the left may only be read in place if the right side cannot
+                // assign to it in the meantime.
+                let left = match is_pure(right) {
+                    true => self.emit_operand(left, position)?,
+                    false => {
+                        let register = self.allocate(position)?;
+                        self.emit_expression(left, register, position)?;
+                        register
+                    }
+                };
+                let right = self.emit_operand(right, position)?;
+                let (instruction, negate) = match operator {
+                    Token::Plus => (RegisterInstruction::Add(destination, left, right), false),
+                    Token::Minus => (
+                        RegisterInstruction::Subtract(destination, left, right),
+                        false,
+                    ),
+                    Token::Star => (
+                        RegisterInstruction::Multiply(destination, left, right),
+                        false,
+                    ),
+                    Token::Slash => (RegisterInstruction::Divide(destination, left, right), false),
+                    Token::Greater => (
+                        RegisterInstruction::Greater(destination, left, right),
+                        false,
+                    ),
+                    Token::Less => (RegisterInstruction::Less(destination, left, right), false),
+                    Token::EqualEqual => {
+                        (RegisterInstruction::Equal(destination, left, right), false)
+                    }
+                    Token::GreaterEqual => {
+                        (RegisterInstruction::Less(destination, left, right), true)
+                    }
+                    Token::LessEqual => {
+                        (RegisterInstruction::Greater(destination, left, right), true)
+                    }
+                    Token::BangEqual => {
+                        (RegisterInstruction::Equal(destination, left, right), true)
+                    }
+                    _ => unreachable!("emit failure due to parse error at binary expressions."),
+                };
+                self.chunk.write(instruction, position);
+                if negate {
+                    self.chunk
+                        .write(RegisterInstruction::Not(destination, destination), position);
+                }
+            }
+            Expression::Logic(left, operator, right) => {
+                self.emit_expression(left, destination, position)?;
+                let patch = match operator {
+                    Token::And => self
+                        .chunk
+                        .write(RegisterInstruction::JumpFalse(destination, 0), position),
+                    Token::Or => self
+                        .chunk
+                        .write(RegisterInstruction::JumpTrue(destination, 0), position),
+                    _ => unreachable!("emit failure due to parse error at logic expressions."),
+                };
+                self.emit_expression(right, destination, position)?;
+                self.chunk.patch(patch, position)?;
+            }
+            Expression::Call(callee, arguments) => {
+                if arguments.len() > u8::MAX as usize {
+                    return self.report(
+                        position,
+                        "E0009",
+                        "too many arguments in one call",
+                        "call within this statement",
+                    );
+                }
+                // Callee and arguments must occupy consecutive registers.
+                let base = self.allocate(position)?;
+                self.emit_expression(callee, base, position)?;
+                for argument in arguments {
+                    let register = self.allocate(position)?;
+                    self.emit_expression(argument, register, position)?;
+                }
+                self.chunk.write(
+                    RegisterInstruction::Call(base, arguments.len() as u8),
+                    position,
+                );
+                self.chunk
+                    .write(RegisterInstruction::Move(destination, base), position);
+            }
+        }
+        self.next_register = mark;
+        Ok(())
+    }
+
+    /// Evaluates `expression` into some register and returns it. Locals are used in place.
+    fn emit_operand(
+        &mut self,
+        expression: &Expression,
+        position: &Range<usize>,
+    ) -> InterpretResult<Register> {
+        if let Some(register) = self.resolve_local(expression) {
+            return Ok(register);
+        }
+        let register = self.allocate(position)?;
+        self.emit_expression(expression, register, position)?;
+        Ok(register)
+    }
+
+    fn resolve_local(&self, identifier: &Expression) -> Option<Register> {
+        self.resolutions
+            .local(identifier)
+            .map(|slot| self.locals[slot as usize].register)
+    }
+
+    fn allocate(&mut self, position: &Range<usize>) -> InterpretResult<Register> {
+        if self.next_register > u8::MAX as usize {
+            self.report(
+                position,
+                "E0010",
+                "too many registers in one chunk",
+                "error originated within this statement",
+            )?;
+        }
+        self.next_register += 1;
+        self.chunk.register_count = self.chunk.register_count.max(self.next_register);
+        Ok((self.next_register - 1) as Register)
+    }
+
+    fn emit_constant(
+        &mut self,
+        constant: Constant,
+        position: &Range<usize>,
+    ) -> InterpretResult<u8> {
+        let index = match self.chunk.add_constant(constant) {
+            Some(index) => index,
+            None => {
+                return Err(InterpretError::Simple(
+                    ErrorItem::error()
+                        .with_code("E0001")
+                        .with_message("too many constants in one chunk")
+                        .with_labels(vec![Label::secondary(self.file_id, position.clone())
+                            .with_message("error originated within this statement")]),
+                ))
+            }
+        };
+        Ok(index)
+    }
+
+    #[inline(always)]
+    fn report(
+        &self,
+        position: &Range<usize>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+        label: impl Into<String>,
+    ) -> InterpretResult {
+        Err(InterpretError::Simple(
+            ErrorItem::error()
+                .with_code(code)
+                .with_message(message)
+                .with_labels(vec![
+                    Label::secondary(self.file_id, position.clone()).with_message(label)
+                ]),
+        ))
+    }
+}
+
+fn is_pure(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::String(_)
+            | Expression::Int(_)
+            | Expression::Number(_)
+            | Expression::Identifier(_)
+            | Expression::True
+            | Expression::False
+            | Expression::Nil
+    )
+}
@@ -0,0 +1,209 @@
+//! Compile-time state kept across REPL entries.
+//!
+//! Every entry is compiled on its own, and the VM keeps the values of globals. Locals can't
+//! outlive the entry declaring them, since blocks are closed within one entry, so globals are
+//! the only names to remember.
+//!
+//! Entries are a bit more lenient than scripts: the final `;` may be left out, and an entry
+//! ending with an expression statement is evaluated, so `1 + 2` shows `3` and `"hi"` shows
+//! `"hi"`. See [`ReplSession::echoes`].
+
+use std::collections::BTreeSet;
+
+use shared::error::InterpretResult;
+
+use crate::{
+    classify, emit_program, limited, parse_within, parser::ParsedContext, scan, CompileOutput,
+    Expression, LintRules, Statement, TokenCategory, KEYWORDS,
+};
+
+#[derive(Default)]
+pub struct ReplSession {
+    globals: BTreeSet<String>,
+    rules: LintRules,
+    echoes: bool,
+}
+
+impl ReplSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A session linting every entry with the given rules.
+    pub fn with_rules(rules: LintRules) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Compiles one entry. Previous entries are never compiled again.
+    ///
+    /// When the entry ends with an expression statement other than an assignment, its value is
+    /// left on the stack for the VM's `evaluate` to return, and [`echoes`](Self::echoes) tells
+    /// so.
+    pub fn compile(
+        &mut self,
+        file_id: usize,
+        source: impl AsRef<str>,
+    ) -> InterpretResult<CompileOutput> {
+        self.echoes = false;
+        let mut echoes = false;
+        let mut output = self.compile_entry(file_id, source.as_ref(), |parsed, rules| {
+            echoes = ends_with_value(parsed);
+            emit_program(file_id, parsed, rules, echoes)
+        })?;
+        self.echoes = echoes;
+        output.chunk.attach_source(source.as_ref());
+        Ok(output)
+    }
+
+    /// Compiles one entry for the experimental register machine. It can't evaluate yet, so
+    /// the values of top-level expression statements are printed by the entry itself, which
+    /// shows strings without quotes.
+    #[cfg(feature = "register")]
+    pub fn compile_register(
+        &mut self,
+        file_id: usize,
+        source: impl AsRef<str>,
+    ) -> InterpretResult<CompileOutput<shared::register::RegisterChunk>> {
+        self.echoes = false;
+        self.compile_entry(file_id, source.as_ref(), |parsed, rules| {
+            echo(parsed);
+            crate::emit_register(file_id, parsed, rules)
+        })
+    }
+
+    /// Whether the entry compiled last leaves its value to be printed. The host should then
+    /// run it with `evaluate` and print the `repr` of the result, so strings show quoted.
+    pub fn echoes(&self) -> bool {
+        self.echoes
+    }
+
+    /// Globals declared by compiled entries, in alphabetical order. An entry failing at runtime
+    /// may have left some of them undefined in the VM.
+    pub fn globals(&self) -> impl Iterator<Item = &str> {
+        self.globals.iter().map(String::as_str)
+    }
+
+    pub fn is_declared(&self, name: &str) -> bool {
+        self.globals.contains(name)
+    }
+
+    fn compile_entry<C>(
+        &mut self,
+        file_id: usize,
+        source: &str,
+        emit: impl FnOnce(&mut ParsedContext, &LintRules) -> InterpretResult<C>,
+    ) -> InterpretResult<C> {
+        let error_limit = self.rules.error_limit();
+        limited(error_limit, || {
+            let scanned = scan(file_id, source)?;
+            let amended;
+            let mut parsed = match parse_within(file_id, &scanned, error_limit) {
+                Ok(parsed) => parsed,
+                // Retry as if the final `;` had been typed. The amended source only adds a
+                // token past the end, so positions into the entry stay valid.
+                Err(error) => {
+                    amended = match scan(file_id, &format!("{};", source)) {
+                        Ok(amended) => amended,
+                        Err(_) => return Err(error),
+                    };
+                    parse_within(file_id, &amended, error_limit).map_err(|_| error)?
+                }
+            };
+            // Globals of earlier entries are declared as far as strict mode is concerned.
+            let mut rules = self.rules.clone();
+            if rules.is_strict() {
+                rules.strict(self.globals());
+            }
+            let output = emit(&mut parsed, &rules)?;
+            self.record(&parsed);
+            Ok(output)
+        })
+    }
+
+    fn record(&mut self, parsed: &ParsedContext) {
+        for statement in &parsed.statements {
+            if let Statement::VarDeclaration(name, _) = statement {
+                self.globals.insert((*name).clone());
+            }
+        }
+    }
+}
+
+/// Whether the entry ends with an expression statement whose value is worth showing.
+/// Assignments are left out, as their value is the one just typed in.
+fn ends_with_value(parsed: &ParsedContext) -> bool {
+    match parsed.statements.last() {
+        Some(Statement::Expressional(expression)) => {
+            !matches!(**expression, Expression::Assign(..))
+        }
+        _ => false,
+    }
+}
+
+/// Prints the values of top-level expression statements instead of discarding them, for
+/// backends that can't evaluate. Assignments are left alone.
+#[cfg(feature = "register")]
+fn echo(parsed: &mut ParsedContext) {
+    for statement in &mut parsed.statements {
+        if let Statement::Expressional(expression) = statement {
+            if matches!(**expression, Expression::Assign(..)) {
+                continue;
+            }
+            if let Statement::Expressional(expression) =
+                std::mem::replace(statement, Statement::Error)
+            {
+                *statement = Statement::Print(expression);
+            }
+        }
+    }
+}
+
+/// Whether the entry obviously goes on in the next line: it has unclosed parentheses, braces
+/// or strings, or ends with an operator or keyword that needs something after it. Such input
+/// should be continued rather than compiled.
+pub fn is_incomplete(source: &str) -> bool {
+    let tokens = classify(source);
+    let mut depth = 0isize;
+    for token in &tokens {
+        match &source[token.span.clone()] {
+            "(" | "{" => depth += 1,
+            ")" | "}" => depth -= 1,
+            _ => {}
+        }
+    }
+    let last = tokens
+        .iter()
+        .rev()
+        .find(|token| token.category != TokenCategory::Comment);
+    let dangling = last.is_some_and(|token| match token.category {
+        TokenCategory::Operator | TokenCategory::Keyword => true,
+        TokenCategory::Punctuation => matches!(&source[token.span.clone()], "," | "."),
+        // The scanner gives up on a string without its closing quote.
+        TokenCategory::Error => source[token.span.clone()].starts_with('"'),
+        _ => false,
+    });
+    depth > 0 || dangling
+}
+
+/// Keywords and global names completing the word the line ends with, in alphabetical order.
+/// Globals should come from the VM, so that natives and globals defined at runtime are offered
+/// too. Nothing is offered when the line doesn't end within a name.
+pub fn complete<'a>(line: &str, globals: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let start = line
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |index| index + 1);
+    let prefix = &line[start..];
+    if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Vec::new();
+    }
+
+    let candidates: BTreeSet<_> = KEYWORDS
+        .into_iter()
+        .chain(globals)
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    candidates.into_iter().map(String::from).collect()
+}
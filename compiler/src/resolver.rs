@@ -0,0 +1,407 @@
+//! Resolves identifiers to local slots or globals ahead of code generation, and checks the
+//! scoping rules on the way. Backends only have to look the results up.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use shared::{
+    error::{ErrorItem, InterpretError, InterpretResult, Label},
+    stack::Stack,
+};
+
+use crate::{
+    parser::{Expression, ParsedContext, Statement},
+    LintRules,
+};
+
+struct Local<'a> {
+    depth: usize,
+    name: &'a String,
+    position: Range<usize>,
+    initialized: bool,
+}
+
+/// Local slots of the identifier expressions in one AST. Identifiers without a slot are
+/// globals.
+///
+/// Every name is also linked to the one in the declaration it refers to. Names are borrowed
+/// from the tokens, so tools can find the span of a declaration among them.
+#[derive(Default)]
+pub struct Resolutions<'a> {
+    // Keyed by the address of the identifier expression, which the AST never moves.
+    slots: HashMap<*const (), u8>,
+    // Keyed by the address of the name, which lives in its token.
+    declarations: HashMap<*const String, &'a String>,
+}
+
+impl<'a> Resolutions<'a> {
+    pub fn local(&self, identifier: &Expression) -> Option<u8> {
+        self.slots.get(&key(identifier)).copied()
+    }
+
+    /// The name in the declaration `name` refers to, if it is declared in the program. A
+    /// declared name refers to itself.
+    pub fn declaration(&self, name: &String) -> Option<&'a String> {
+        self.declarations.get(&(name as *const String)).copied()
+    }
+}
+
+fn key(expression: &Expression) -> *const () {
+    expression as *const Expression as *const ()
+}
+
+struct Resolver<'a> {
+    file_id: usize,
+    locals: Stack<Local<'a>>,
+    local_depth: usize,
+    resolutions: Resolutions<'a>,
+    // The first declaration of each global anywhere in the program, and every use of a global
+    // with its position. Uses are linked and checked once all declarations are known.
+    globals: HashMap<&'a str, &'a String>,
+    global_uses: Vec<(&'a String, Range<usize>)>,
+    // Scoping errors don't disturb resolution, so they are collected instead of returned.
+    errors: Vec<ErrorItem>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(file_id: usize) -> Self {
+        Self {
+            file_id,
+            locals: Stack::new(),
+            local_depth: 0,
+            resolutions: Resolutions::default(),
+            globals: HashMap::new(),
+            global_uses: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn resolve_statement(
+        &mut self,
+        statement: &Statement<'a>,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        match statement {
+            Statement::VarDeclaration(name, initializer) => {
+                self.resolutions.declarations.insert(*name, name);
+                if self.local_depth > 0 {
+                    self.check_redeclaration(name, position);
+                    // Declared but not initialized until the initializer is resolved.
+                    self.locals.push(Local {
+                        depth: self.local_depth,
+                        name,
+                        position: position.clone(),
+                        initialized: false,
+                    })?;
+                }
+                if let Some(expression) = initializer {
+                    self.resolve_expression(expression, position)?;
+                }
+                match self.local_depth > 0 {
+                    true => {
+                        let slot = self.locals.len() - 1;
+                        self.locals[slot].initialized = true;
+                    }
+                    false => {
+                        self.globals.entry(name.as_str()).or_insert(name);
+                    }
+                }
+            }
+            Statement::Print(expression) | Statement::Expressional(expression) => {
+                self.resolve_expression(expression, position)?
+            }
+            Statement::If(condition, then, otherwise) => {
+                self.resolve_expression(condition, position)?;
+                self.resolve_statement(then, position)?;
+                if let Some(otherwise) = otherwise {
+                    self.resolve_statement(otherwise, position)?;
+                }
+            }
+            Statement::While(condition, body) => {
+                self.resolve_expression(condition, position)?;
+                self.resolve_statement(body, position)?;
+            }
+            Statement::For(initializer, condition, increment, body) => {
+                for expression in [initializer, condition, increment].into_iter().flatten() {
+                    self.resolve_expression(expression, position)?;
+                }
+                self.resolve_statement(body, position)?;
+            }
+            Statement::ForWithInit(initializer, condition, increment, body) => {
+                self.local_depth += 1;
+                if let Some(initializer) = initializer {
+                    self.resolve_statement(initializer, position)?;
+                }
+                for expression in [condition, increment].into_iter().flatten() {
+                    self.resolve_expression(expression, position)?;
+                }
+                self.resolve_statement(body, position)?;
+                self.end_scope()?;
+            }
+            Statement::Block(statements, positions) => {
+                self.local_depth += 1;
+                for (statement, position) in statements.iter().zip(positions) {
+                    self.resolve_statement(statement, position)?;
+                }
+                self.end_scope()?;
+            }
+            Statement::Error => unreachable!("still trying to resolve after reporting diagnostics"),
+        }
+        Ok(())
+    }
+
+    fn resolve_expression(
+        &mut self,
+        expression: &Expression<'a>,
+        position: &Range<usize>,
+    ) -> InterpretResult {
+        match expression {
+            Expression::Identifier(identifier) => match self.resolve_local(identifier, position) {
+                Some(slot) => {
+                    self.resolutions.slots.insert(key(expression), slot);
+                }
+                None => self.global_uses.push((identifier, position.clone())),
+            },
+            Expression::Assign(target, source) => {
+                self.resolve_expression(source, position)?;
+                self.resolve_expression(target, position)?;
+            }
+            Expression::Unary(_, operand) => self.resolve_expression(operand, position)?,
+            Expression::Arithmetic(left, _, right) | Expression::Logic(left, _, right) => {
+                self.resolve_expression(left, position)?;
+                self.resolve_expression(right, position)?;
+            }
+            Expression::Call(callee, arguments) => {
+                self.resolve_expression(callee, position)?;
+                for argument in arguments {
+                    self.resolve_expression(argument, position)?;
+                }
+            }
+            Expression::String(_)
+            | Expression::Int(_)
+            | Expression::Number(_)
+            | Expression::True
+            | Expression::False
+            | Expression::Nil => {}
+        }
+        Ok(())
+    }
+
+    fn end_scope(&mut self) -> InterpretResult {
+        while let Some(local) = self.locals.peek() {
+            if local.depth != self.local_depth {
+                break;
+            }
+            self.locals.pop()?;
+        }
+        self.local_depth -= 1;
+        Ok(())
+    }
+
+    /// Rejects a second declaration of `name` within the innermost scope.
+    fn check_redeclaration(&mut self, name: &str, position: &Range<usize>) {
+        for slot in (0..self.locals.len()).rev() {
+            let local = &self.locals[slot];
+            if local.depth < self.local_depth {
+                break;
+            }
+            if local.name == name {
+                self.errors.push(
+                    ErrorItem::error()
+                        .with_code("E0011")
+                        .with_message(format!(
+                            "variable `{}` is already declared in this scope",
+                            name
+                        ))
+                        .with_labels(vec![
+                            Label::primary(self.file_id, position.clone())
+                                .with_message("declared again here"),
+                            Label::secondary(self.file_id, local.position.clone())
+                                .with_message("first declared here"),
+                        ]),
+                );
+                return;
+            }
+        }
+    }
+
+    fn resolve_local(&mut self, name: &'a String, position: &Range<usize>) -> Option<u8> {
+        let slot = (0..self.locals.len())
+            .rev()
+            .find(|&slot| self.locals[slot].name == name)?;
+        self.resolutions
+            .declarations
+            .insert(name, self.locals[slot].name);
+        if !self.locals[slot].initialized {
+            self.errors.push(
+                ErrorItem::error()
+                    .with_code("E0012")
+                    .with_message("can't read local variable in its own initializer")
+                    .with_labels(vec![Label::primary(self.file_id, position.clone())
+                        .with_message(format!(
+                            "`{}` is used while being declared here",
+                            name
+                        ))]),
+            );
+        }
+        Some(slot as u8)
+    }
+
+    /// Links the uses of globals to their declarations, wherever those are in the program.
+    fn link_globals(&mut self) {
+        for (name, _) in &self.global_uses {
+            if let Some(declaration) = self.globals.get(name.as_str()) {
+                self.resolutions.declarations.insert(*name, declaration);
+            }
+        }
+    }
+
+    /// Rejects the uses of globals which are neither declared in the program nor by the host.
+    fn check_undeclared(&mut self, host_globals: &HashSet<String>) {
+        for (name, position) in &self.global_uses {
+            if self.globals.contains_key(name.as_str()) || host_globals.contains(*name) {
+                continue;
+            }
+            self.errors.push(
+                ErrorItem::error()
+                    .with_code("E0019")
+                    .with_message(format!("variable `{}` is never declared", name))
+                    .with_labels(vec![Label::primary(self.file_id, position.clone())
+                        .with_message(format!("`{}` is used within this statement", name))])
+                    .with_notes(vec![format!(
+                        "declare it with `var {};`, or check the spelling",
+                        name
+                    )]),
+            );
+        }
+    }
+}
+
+pub fn resolve<'a>(
+    file_id: usize,
+    parsed_context: &ParsedContext<'a>,
+    rules: &LintRules,
+) -> InterpretResult<Resolutions<'a>> {
+    let mut resolver = Resolver::new(file_id);
+    for (statement, position) in parsed_context
+        .statements
+        .iter()
+        .zip(parsed_context.positions.iter())
+    {
+        resolver.resolve_statement(statement, position)?;
+    }
+    resolver.link_globals();
+    if let Some(host_globals) = rules.host_globals() {
+        resolver.check_undeclared(host_globals);
+    }
+    match resolver.errors.is_empty() {
+        true => Ok(resolver.resolutions),
+        false => Err(InterpretError::Compound(resolver.errors)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::{parse, scan};
+
+    /// Resolves the source, handing the statements and their resolutions to `check`.
+    fn resolved(source: &str, check: impl FnOnce(&[Statement], &Resolutions)) {
+        let scanned = scan(0, source).unwrap();
+        let parsed = parse(0, &scanned).unwrap();
+        let resolutions = resolve(0, &parsed, &LintRules::default()).unwrap();
+        check(&parsed.statements, &resolutions);
+    }
+
+    fn rejection(source: &str) -> Vec<String> {
+        let scanned = scan(0, source).unwrap();
+        let parsed = parse(0, &scanned).unwrap();
+        match resolve(0, &parsed, &LintRules::default()) {
+            Ok(_) => Vec::new(),
+            Err(error) => error
+                .diagnostics()
+                .iter()
+                .filter_map(|item| item.code.clone())
+                .collect(),
+        }
+    }
+
+    /// The expression printed by a `print` statement.
+    fn printed<'s, 'a>(statement: &'s Statement<'a>) -> &'s Expression<'a> {
+        match statement {
+            Statement::Print(expression) => expression,
+            _ => panic!("not a print statement"),
+        }
+    }
+
+    fn declared<'a>(statement: &Statement<'a>) -> &'a String {
+        match statement {
+            Statement::VarDeclaration(name, _) => name,
+            _ => panic!("not a declaration"),
+        }
+    }
+
+    #[test]
+    fn resolves_locals_to_slots() {
+        resolved(
+            "{ var a = 1; var b = 2; print b; { var c; print a; print c; } }",
+            |statements, resolutions| {
+                let Statement::Block(outer, _) = &statements[0] else {
+                    panic!("not a block");
+                };
+                let Statement::Block(inner, _) = &outer[3] else {
+                    panic!("not a block");
+                };
+                assert_eq!(resolutions.local(printed(&outer[2])), Some(1));
+                assert_eq!(resolutions.local(printed(&inner[1])), Some(0));
+                assert_eq!(resolutions.local(printed(&inner[2])), Some(2));
+            },
+        );
+    }
+
+    #[test]
+    fn links_names_to_declarations() {
+        resolved(
+            "print g; var g = 1; { var g = 2; print g; }",
+            |statements, resolutions| {
+                let global = declared(&statements[1]);
+                let Statement::Block(block, _) = &statements[2] else {
+                    panic!("not a block");
+                };
+                let local = declared(&block[0]);
+                let Expression::Identifier(early) = printed(&statements[0]) else {
+                    panic!("not an identifier");
+                };
+                let Expression::Identifier(inner) = printed(&block[1]) else {
+                    panic!("not an identifier");
+                };
+                // Globals are found wherever they are declared.
+                assert_eq!(resolutions.local(printed(&statements[0])), None);
+                assert!(ptr::eq(resolutions.declaration(early).unwrap(), global));
+                assert!(ptr::eq(resolutions.declaration(inner).unwrap(), local));
+                assert!(ptr::eq(resolutions.declaration(local).unwrap(), local));
+            },
+        );
+    }
+
+    #[test]
+    fn rejects_redeclarations_in_one_scope() {
+        assert_eq!(rejection("{ var a = 1; var a = 2; }"), ["E0011"]);
+        assert_eq!(
+            rejection("{ var a = 1; { var a = 2; } }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(rejection("var a = 1; var a = 2;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_reading_locals_in_their_own_initializer() {
+        assert_eq!(rejection("{ var a = a; }"), ["E0012"]);
+        assert_eq!(rejection("var a = 1; { var a = a + 1; }"), ["E0012"]);
+        assert_eq!(rejection("var a = a;"), Vec::<String>::new());
+    }
+}
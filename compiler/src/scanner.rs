@@ -14,7 +14,7 @@ pub enum Token {
     Greater, GreaterEqual, Less, LessEqual,
 
     // Literals.
-    Identifier(String), String(String), Number(f64),
+    Identifier(String), String(String), Int(i64), Number(f64),
 
     // Keywords.
     And, Class, Else, False, For, Fun, If, Nil,
@@ -48,7 +48,7 @@ impl Display for Token {
             Token::LessEqual => write!(f, "<="),
             Token::Identifier(_) => write!(f, "identifier"),
             Token::String(_) => write!(f, "string literal"),
-            Token::Number(_) => write!(f, "number literal"),
+            Token::Int(_) | Token::Number(_) => write!(f, "number literal"),
             Token::And => write!(f, "and"),
             Token::Class => write!(f, "class"),
             Token::Else => write!(f, "else"),
@@ -70,9 +70,84 @@ impl Display for Token {
     }
 }
 
+/// Reserved words, as spelled in the source.
+#[rustfmt::skip]
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil",
+    "or", "print", "return", "super", "this", "true", "var", "while",
+];
+
+/// Coarse kinds of tokens, for tools which color or otherwise present source code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenCategory {
+    Keyword,
+    Identifier,
+    Literal,
+    Operator,
+    Punctuation,
+    Comment,
+    Error,
+}
+
+impl Token {
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            Token::LeftParenthesis
+            | Token::RightParenthesis
+            | Token::LeftBrace
+            | Token::RightBrace
+            | Token::Comma
+            | Token::Dot
+            | Token::Semicolon => TokenCategory::Punctuation,
+            Token::Minus
+            | Token::Plus
+            | Token::Slash
+            | Token::Star
+            | Token::Bang
+            | Token::BangEqual
+            | Token::Equal
+            | Token::EqualEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Less
+            | Token::LessEqual => TokenCategory::Operator,
+            Token::Identifier(_) => TokenCategory::Identifier,
+            Token::String(_)
+            | Token::Int(_)
+            | Token::Number(_)
+            | Token::True
+            | Token::False
+            | Token::Nil => TokenCategory::Literal,
+            Token::And
+            | Token::Class
+            | Token::Else
+            | Token::For
+            | Token::Fun
+            | Token::If
+            | Token::Or
+            | Token::Print
+            | Token::Return
+            | Token::Super
+            | Token::This
+            | Token::Var
+            | Token::While => TokenCategory::Keyword,
+            Token::Error => TokenCategory::Error,
+        }
+    }
+}
+
+/// A token or comment of the source with the byte range it spans.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClassifiedToken {
+    pub category: TokenCategory,
+    pub span: Range<usize>,
+}
+
 pub struct ScannedContext {
     pub tokens: Vec<Token>,
     pub positions: Vec<Range<usize>>,
+    // Comments are trivia to the parser, but tools like the formatter preserve them.
+    pub comments: Vec<Range<usize>>,
     pub errors: Vec<ErrorItem>,
 }
 
@@ -81,6 +156,7 @@ impl ScannedContext {
         Self {
             tokens: Vec::new(),
             positions: Vec::new(),
+            comments: Vec::new(),
             errors: Vec::new(),
         }
     }
@@ -90,6 +166,17 @@ impl ScannedContext {
         self.positions.push(position);
     }
 
+    // Trivia may be scanned again when the grammar backtracks.
+    fn record_comment(&mut self, position: Range<usize>) {
+        if self
+            .comments
+            .last()
+            .is_none_or(|last| last.start < position.start)
+        {
+            self.comments.push(position);
+        }
+    }
+
     fn report(&mut self, error: ErrorItem) {
         self.errors.push(error);
     }
@@ -98,15 +185,15 @@ impl ScannedContext {
 impl Parse for ScannedContext {
     type PositionRepr = usize;
 
-    fn start<'input>(&'input self) -> usize {
+    fn start(&self) -> usize {
         0
     }
 
-    fn is_eof<'input>(&'input self, p: usize) -> bool {
+    fn is_eof(&self, p: usize) -> bool {
         p >= self.tokens.len()
     }
 
-    fn position_repr<'input>(&'input self, p: usize) -> Self::PositionRepr {
+    fn position_repr(&self, p: usize) -> Self::PositionRepr {
         p
     }
 }
@@ -132,13 +219,13 @@ peg::parser!(grammar pegscanner(file_id: usize, context: &mut ScannedContext) fo
         = start:position!() t:recognized_token() end:position!() {
             context.record(t, start..end);
         }
-        / start:position!() [_] {
-            context.record(Error, start..start + 1);
+        / start:position!() [_] end:position!() {
+            context.record(Error, start..end);
             context.report(ErrorItem::error()
                 .with_code("E0002")
                 .with_message("unexpected character")
                 .with_labels(vec![
-                    Label::primary(file_id, start..start + 1)
+                    Label::primary(file_id, start..end)
                         .with_message("this character is beyond Lox's syntax rule.")
                 ])
             );
@@ -147,7 +234,8 @@ peg::parser!(grammar pegscanner(file_id: usize, context: &mut ScannedContext) fo
     rule recognized_token() -> Token
         = single()
         / one_or_two()
-        / keywords()
+        // A keyword ends where a word does, so `format` is an identifier, not `for` and `mat`.
+        / k:keywords() !alphanumeric() { k }
         / literals()
 
     rule single() -> Token
@@ -196,7 +284,11 @@ peg::parser!(grammar pegscanner(file_id: usize, context: &mut ScannedContext) fo
     rule identifier() -> Token
         = s:$(alpha() alphanumeric()*) { Identifier(s.into()) }
     rule number() -> Token
-        = start:position!() s:$(numeric()+ ("." numeric()+)?) end:position!() {
+        = s:$(numeric()+) !("." numeric()) {?
+            // Integers too large for an `i64` are scanned as floats instead.
+            s.parse().map(Int).map_err(|_| "integer")
+        }
+        / start:position!() s:$(numeric()+ ("." numeric()+)?) end:position!() {
             match s.parse::<f64>() {
                 Ok(n) => Number(n),
                 Err(_) => {
@@ -232,16 +324,73 @@ peg::parser!(grammar pegscanner(file_id: usize, context: &mut ScannedContext) fo
     rule numeric() = ['0'..='9']
     rule alphanumeric() = ['a'..='z' | 'A'..='Z' | '_' | '0'..='9']
 
-    rule _ = blank()* comment()**"\n" blank()*
+    rule _ = (blank() / comment())*
     rule blank() = [' ' | '\t' | '\r' | '\n']
-    rule comment() = "//" [^'\n']*
+    rule comment() = start:position!() "//" [^'\n']* end:position!() {
+        context.record_comment(start..end);
+    }
 });
 
+/// Splits the source into tokens, reporting every unrecognizable character at once.
 pub fn scan(file_id: usize, input: &str) -> InterpretResult<ScannedContext> {
     let mut context = ScannedContext::new();
-    pegscanner::scan(input, file_id, &mut context).expect("internal scan error.");
+    if let Err(error) = pegscanner::scan(input, file_id, &mut context) {
+        let offset = error.location.offset;
+        context.report(internal_error(
+            file_id,
+            "scanner",
+            offset..offset,
+            error.expected,
+        ));
+    }
     match context.errors.is_empty() {
         true => Ok(context),
         false => Err(InterpretError::Compound(context.errors)),
     }
 }
+
+/// Reports that a grammar failed to match, which its catch-all rules should make impossible.
+/// Returned instead of panicking, so arbitrary input can't crash the frontend.
+pub(crate) fn internal_error(
+    file_id: usize,
+    stage: &str,
+    span: Range<usize>,
+    expected: impl Display,
+) -> ErrorItem {
+    ErrorItem::error()
+        .with_code("E0018")
+        .with_message(format!("internal {} error", stage))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("the {} gave up here", stage))
+        ])
+        .with_notes(vec![
+            format!("expected {}", expected),
+            "this is a bug in Ruslox, please report it".into(),
+        ])
+}
+
+/// Splits the source into categorized spans, comments included, in source order. Unlike
+/// [`scan`] this never fails: characters the scanner rejects come out as
+/// [`TokenCategory::Error`], so editors can keep highlighting code that is being typed.
+pub fn classify(input: &str) -> Vec<ClassifiedToken> {
+    let mut context = ScannedContext::new();
+    // Diagnostics are dropped, so the file id is never looked up. Should the scanner give up
+    // early, the tokens before that point are still worth highlighting.
+    let _ = pegscanner::scan(input, 0, &mut context);
+
+    let tokens = context
+        .tokens
+        .iter()
+        .zip(context.positions)
+        .map(|(token, span)| ClassifiedToken {
+            category: token.category(),
+            span,
+        });
+    let comments = context.comments.into_iter().map(|span| ClassifiedToken {
+        category: TokenCategory::Comment,
+        span,
+    });
+    let mut classified: Vec<_> = tokens.chain(comments).collect();
+    classified.sort_by_key(|token| token.span.start);
+    classified
+}
@@ -0,0 +1,108 @@
+//! Read-only traversal over the AST.
+//!
+//! Implementors override the methods for the nodes they care about and call the matching
+//! `walk_*` function to keep the default recursion into children, so new kinds of nodes only
+//! need to be handled here.
+
+use std::ops::Range;
+
+use crate::parser::{Expression, ParsedContext, Statement};
+
+pub trait Visitor {
+    /// `position` is the one recorded for the statement, or for its parent if the parser
+    /// recorded none for the statement itself.
+    fn visit_statement(&mut self, statement: &Statement, position: &Range<usize>) {
+        walk_statement(self, statement, position);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+}
+
+/// Visits every top-level statement of a parsed program.
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, parsed_context: &ParsedContext) {
+    for (statement, position) in parsed_context
+        .statements
+        .iter()
+        .zip(parsed_context.positions.iter())
+    {
+        visitor.visit_statement(statement, position);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    statement: &Statement,
+    position: &Range<usize>,
+) {
+    match statement {
+        Statement::VarDeclaration(_, initializer) => {
+            if let Some(expression) = initializer {
+                visitor.visit_expression(expression);
+            }
+        }
+        Statement::Print(expression) | Statement::Expressional(expression) => {
+            visitor.visit_expression(expression)
+        }
+        Statement::If(condition, then, otherwise) => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(then, position);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_statement(otherwise, position);
+            }
+        }
+        Statement::While(condition, body) => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(body, position);
+        }
+        Statement::For(initializer, condition, increment, body) => {
+            for expression in [initializer, condition, increment].into_iter().flatten() {
+                visitor.visit_expression(expression);
+            }
+            visitor.visit_statement(body, position);
+        }
+        Statement::ForWithInit(initializer, condition, increment, body) => {
+            if let Some(initializer) = initializer {
+                visitor.visit_statement(initializer, position);
+            }
+            for expression in [condition, increment].into_iter().flatten() {
+                visitor.visit_expression(expression);
+            }
+            visitor.visit_statement(body, position);
+        }
+        Statement::Block(statements, positions) => {
+            for (statement, position) in statements.iter().zip(positions) {
+                visitor.visit_statement(statement, position);
+            }
+        }
+        Statement::Error => {}
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Unary(_, operand) => visitor.visit_expression(operand),
+        Expression::Assign(target, source) => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(source);
+        }
+        Expression::Arithmetic(left, _, right) | Expression::Logic(left, _, right) => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Call(callee, arguments) => {
+            visitor.visit_expression(callee);
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        Expression::String(_)
+        | Expression::Int(_)
+        | Expression::Number(_)
+        | Expression::Identifier(_)
+        | Expression::True
+        | Expression::False
+        | Expression::Nil => {}
+    }
+}
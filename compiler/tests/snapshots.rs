@@ -0,0 +1,107 @@
+//! Golden-file tests of the frontend and codegen. Every `.lox` file under `tests/snapshots` is
+//! parsed, formatted and compiled, and its AST dump, canonical layout and disassembly are
+//! compared with the `.ast`, `.fmt` and `.disasm` files beside it. Formatting the layout again
+//! must not change it. After an intended change, run the tests with `UPDATE_SNAPSHOTS=1` to
+//! rewrite those files, and review them as part of the diff.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use compiler::{CompileOutput, LintRules};
+
+#[test]
+fn snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let mut sources: Vec<PathBuf> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no snapshots under {}", root.display());
+
+    let mut failures = Vec::new();
+    for source_path in sources {
+        let source = fs::read_to_string(&source_path).unwrap();
+        let name = source_path.file_stem().unwrap().to_string_lossy();
+        let renders = [
+            ("ast", render_ast(&source)),
+            ("fmt", render_format(&source)),
+            ("disasm", render_disassembly(&name, &source)),
+        ];
+        for (extension, actual) in renders {
+            let golden = source_path.with_extension(extension);
+            if update {
+                fs::write(&golden, &actual).unwrap();
+                continue;
+            }
+            match fs::read_to_string(&golden) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) => failures.push(format!(
+                    "{} differs (- expected, + actual):\n{}",
+                    golden.display(),
+                    diff(&expected, &actual)
+                )),
+                Err(_) => failures.push(format!("{} is missing", golden.display())),
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "\n{}\nrun with UPDATE_SNAPSHOTS=1 if the changes are intended",
+        failures.join("\n")
+    );
+}
+
+fn render_ast(source: &str) -> String {
+    match compiler::dump_ast(0, source) {
+        Ok(ast) => ast,
+        Err(error) => panic!("snapshot fails to parse: {}", error),
+    }
+}
+
+fn render_format(source: &str) -> String {
+    let formatted = match compiler::format(0, source) {
+        Ok(formatted) => formatted,
+        Err(error) => panic!("snapshot fails to format: {}", error),
+    };
+    match compiler::format(0, &formatted) {
+        Ok(again) if again == formatted => formatted,
+        Ok(again) => panic!(
+            "formatting is not idempotent (- once, + twice):\n{}",
+            diff(&formatted, &again)
+        ),
+        Err(error) => panic!("formatted snapshot fails to parse: {}", error),
+    }
+}
+
+fn render_disassembly(name: &str, source: &str) -> String {
+    match compiler::compile_with(0, source, &LintRules::none()) {
+        Ok(CompileOutput { chunk, .. }) => chunk.disassembly(name).to_string(),
+        Err(error) => panic!("snapshot fails to compile: {}", error),
+    }
+}
+
+// Pairs lines up by position, which is enough to point at what changed.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let mut output = String::new();
+    for index in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(index), actual.get(index));
+        if old == new {
+            continue;
+        }
+        if let Some(old) = old {
+            output += &format!("{:4} - {}\n", index + 1, old);
+        }
+        if let Some(new) = new {
+            output += &format!("{:4} + {}\n", index + 1, new);
+        }
+    }
+    output
+}
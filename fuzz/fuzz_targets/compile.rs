@@ -0,0 +1,9 @@
+#![no_main]
+
+use compiler::LintRules;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    let _ = compiler::compile_with(0, &source, &LintRules::all());
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    if let Ok(scanned) = compiler::scan(0, &source) {
+        let _ = compiler::parse(0, &scanned);
+    }
+});
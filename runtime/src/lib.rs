@@ -1,4 +1,4 @@
 mod object;
-mod value;
+pub mod value;
 
 pub mod vm;
@@ -1,7 +1,18 @@
 use std::{
+    any::Any,
+    borrow::Borrow,
+    fmt::{self, Display},
+    hash::{BuildHasher, BuildHasherDefault, Hash, Hasher},
     mem,
     ops::{Deref, DerefMut},
     ptr,
+    rc::Rc,
+    str,
+};
+
+use crate::{
+    value::Value,
+    vm::{Capability, VirtualMachine},
 };
 
 pub trait GarbageCollect {
@@ -12,8 +23,14 @@ pub trait FromUnmanaged<T> {
     fn from_unmanaged<G: GarbageCollect>(value: T, gc: &mut G) -> Self;
 }
 
+/// Bytes owned by an object outside of its own struct, such as the buffer of a string.
+pub trait Measure {
+    fn extra_size(&self) -> usize;
+}
+
 pub trait Downcast<T> {
     fn downcast(&self) -> Option<&T>;
+    #[allow(dead_code)]
     fn downcast_mut(&mut self) -> Option<&mut T>;
 }
 
@@ -50,6 +67,7 @@ macro_rules! register_object {
                             data: Box::into_raw(Box::new(value)) as *mut (),
                             meta: Box::into_raw(Box::new(ObjectMeta {
                                 typ: ObjectType::$objtype,
+                                marked: false,
                             })),
                         };
                         gc.register(reference.clone());
@@ -67,18 +85,218 @@ macro_rules! register_object {
                     }
                     mem::drop(Box::from_raw(self.meta));
                 }
+
+                pub fn size(&self) -> usize {
+                    let object_size = match self.typ {
+                        $(
+                            ObjectType::$objtype => {
+                                let object: &[<$objtype Object>] = self.downcast().unwrap();
+                                mem::size_of::<[<$objtype Object>]>() + object.extra_size()
+                            }
+                        )*
+                    };
+                    mem::size_of::<ObjectMeta>() + object_size
+                }
             }
         }
     };
 }
 
-register_object!(String);
+register_object!(String, Native, Foreign);
 
 pub struct ObjectMeta {
     pub typ: ObjectType,
+    pub marked: bool,
+}
+
+// As many bytes as fit beside the length in the space of a shared string.
+const INLINE_CAPACITY: usize = 2 * mem::size_of::<usize>() - 1;
+
+/// The characters of a string object, with their hash computed once when it is created. Short
+/// strings are kept inline, without an allocation of their own, and longer ones in an allocation
+/// shared with the table of interned strings. Either way, interning a string or handing it out
+/// never copies its characters.
+#[derive(Clone)]
+pub struct StringObject {
+    hash: u64,
+    characters: Characters,
+}
+
+#[derive(Clone)]
+enum Characters {
+    Inline {
+        length: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Shared(Rc<str>),
+}
+
+impl From<&str> for StringObject {
+    fn from(string: &str) -> Self {
+        let characters = match string.len() <= INLINE_CAPACITY {
+            true => {
+                let mut bytes = [0; INLINE_CAPACITY];
+                bytes[..string.len()].copy_from_slice(string.as_bytes());
+                Characters::Inline {
+                    length: string.len() as u8,
+                    bytes,
+                }
+            }
+            false => Characters::Shared(Rc::from(string)),
+        };
+        Self {
+            hash: BuildStringHasher::default().hash_one(string),
+            characters,
+        }
+    }
+}
+
+impl Deref for StringObject {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &self.characters {
+            // Only ever filled from a `str`, up to a character boundary.
+            Characters::Inline { length, bytes } => unsafe {
+                str::from_utf8_unchecked(&bytes[..*length as usize])
+            },
+            Characters::Shared(string) => string,
+        }
+    }
+}
+
+impl Borrow<str> for StringObject {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl PartialEq for StringObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && **self == **other
+    }
 }
 
-pub type StringObject = String;
+impl Eq for StringObject {}
+
+// Hands the hasher the stored hash, which [`StringHasher`] takes as is. Maps using it can then
+// be looked up by a `str`, hashed to the same value, without hashing their keys again.
+impl Hash for StringObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl Display for StringObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl Measure for StringObject {
+    fn extra_size(&self) -> usize {
+        match &self.characters {
+            Characters::Inline { .. } => 0,
+            Characters::Shared(string) => string.len(),
+        }
+    }
+}
+
+/// The hasher of maps keyed by [`StringObject`]s, which hashes bytes with FNV-1a but takes a
+/// `u64` written to it as the finished hash. It is only meant for string keys.
+pub struct StringHasher(u64);
+
+pub type BuildStringHasher = BuildHasherDefault<StringHasher>;
+
+impl Default for StringHasher {
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for StringHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+/// A function implemented in Rust. Returning an error fails the call with E1017.
+pub type NativeFunction = Rc<dyn Fn(&mut VirtualMachine, &[Value]) -> Result<Value, String>>;
+
+/// How many arguments a native function takes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Arity {
+    Exactly(usize),
+    /// The given number or more, the rest being passed along as they are.
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, argc: usize) -> bool {
+        match self {
+            Arity::Exactly(arity) => argc == *arity,
+            Arity::AtLeast(arity) => argc >= *arity,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(arity) => write!(f, "{}", arity),
+            Arity::AtLeast(arity) => write!(f, "at least {}", arity),
+        }
+    }
+}
+
+pub struct NativeObject {
+    pub name: String,
+    pub arity: Arity,
+    // Capability the VM must allow before calling the function.
+    pub requires: Option<Capability>,
+    pub function: NativeFunction,
+}
+
+impl Measure for NativeObject {
+    fn extra_size(&self) -> usize {
+        self.name.capacity()
+    }
+}
+
+/// Releases the value of a foreign object once it is collected.
+pub type Finalizer = Box<dyn FnOnce(Box<dyn Any>)>;
+
+/// An opaque Rust value handed to Lox code, which can only pass it around and give it back to
+/// natives. The finalizer, if any, receives the value once the object is collected.
+pub struct ForeignObject {
+    pub type_name: &'static str,
+    pub value: Box<dyn Any>,
+    pub finalizer: Option<Finalizer>,
+}
+
+impl Measure for ForeignObject {
+    fn extra_size(&self) -> usize {
+        mem::size_of_val(&*self.value)
+    }
+}
+
+impl Drop for ForeignObject {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer.take() {
+            finalizer(mem::replace(&mut self.value, Box::new(())));
+        }
+    }
+}
 
 pub struct ManagedReference {
     data: *mut (),
@@ -89,6 +307,10 @@ impl ManagedReference {
     pub fn ptr(&self) -> usize {
         self.data as usize
     }
+
+    pub fn mark(&self) {
+        unsafe { (*self.meta).marked = true }
+    }
 }
 
 impl Deref for ManagedReference {
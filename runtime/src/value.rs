@@ -1,10 +1,21 @@
-use std::fmt::Display;
+use std::{any::Any, fmt::Display};
 
-use crate::object::{Downcast, ManagedReference, ObjectType, StringObject};
+use shared::number;
 
+use crate::object::{
+    Downcast, ForeignObject, ManagedReference, NativeObject, ObjectType, StringObject,
+};
+
+/// A value as the VM handles it. Objects are references into the heap of the VM which owns
+/// them, and are only valid as long as the VM keeps them alive.
+///
+/// Integer literals are [`Value::Int`], and arithmetic on integers stays exact while it can:
+/// results which overflow or aren't whole, and operations mixing in a [`Value::Number`],
+/// are promoted to floats.
 #[derive(Clone)]
 pub enum Value {
     Nil,
+    Int(i64),
     Number(f64),
     Boolean(bool),
     Object(ManagedReference),
@@ -18,18 +29,252 @@ impl Value {
             _ => true,
         }
     }
+
+    /// Any number as a float, integers included.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Int(int) => Some(*int as f64),
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// A number which is whole, such as an index, as an integer.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(int) => Some(*int),
+            Value::Number(number)
+                if number.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(number) =>
+            {
+                Some(*number as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn add(&self, other: &Value) -> Option<Value> {
+        Some(match Operands::of(self, other)? {
+            Operands::Int(left, right) => match left.checked_add(right) {
+                Some(sum) => Value::Int(sum),
+                None => Value::Number(left as f64 + right as f64),
+            },
+            Operands::Float(left, right) => Value::Number(left + right),
+        })
+    }
+
+    pub fn subtract(&self, other: &Value) -> Option<Value> {
+        Some(match Operands::of(self, other)? {
+            Operands::Int(left, right) => match left.checked_sub(right) {
+                Some(difference) => Value::Int(difference),
+                None => Value::Number(left as f64 - right as f64),
+            },
+            Operands::Float(left, right) => Value::Number(left - right),
+        })
+    }
+
+    pub fn multiply(&self, other: &Value) -> Option<Value> {
+        Some(match Operands::of(self, other)? {
+            Operands::Int(left, right) => match left.checked_mul(right) {
+                Some(product) => signed(product, left, right),
+                None => Value::Number(left as f64 * right as f64),
+            },
+            Operands::Float(left, right) => Value::Number(left * right),
+        })
+    }
+
+    /// Integers divide to an integer only when the division is exact, so `7 / 2` is `3.5`.
+    pub fn divide(&self, other: &Value) -> Option<Value> {
+        Some(match Operands::of(self, other)? {
+            Operands::Int(left, right) => match left.checked_rem(right) {
+                Some(0) => signed(left / right, left, right),
+                _ => Value::Number(left as f64 / right as f64),
+            },
+            Operands::Float(left, right) => Value::Number(left / right),
+        })
+    }
+
+    pub fn negate(&self) -> Option<Value> {
+        match self {
+            Value::Int(0) => Some(Value::Number(-0.0)),
+            Value::Int(int) => Some(match int.checked_neg() {
+                Some(negated) => Value::Int(negated),
+                None => Value::Number(-(*int as f64)),
+            }),
+            Value::Number(number) => Some(Value::Number(-number)),
+            _ => None,
+        }
+    }
+
+    pub fn less(&self, other: &Value) -> Option<Value> {
+        Some(Value::Boolean(match Operands::of(self, other)? {
+            Operands::Int(left, right) => left < right,
+            Operands::Float(left, right) => left < right,
+        }))
+    }
+
+    pub fn greater(&self, other: &Value) -> Option<Value> {
+        Some(Value::Boolean(match Operands::of(self, other)? {
+            Operands::Int(left, right) => left > right,
+            Operands::Float(left, right) => left > right,
+        }))
+    }
+
+    /// Whether this is a number equal to zero, which strict arithmetic refuses to divide by.
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Value::Int(int) => *int == 0,
+            Value::Number(number) => *number == 0.0,
+            _ => false,
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        matches!(self, Value::Number(number) if number.is_nan())
+    }
+
+    /// The contents of a string value. The string lives in the heap of the VM, and should not
+    /// be kept beyond the native call which received it.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Object(reference) => match reference.typ {
+                ObjectType::String => {
+                    let string_object: &StringObject = reference.downcast().unwrap();
+                    Some(string_object)
+                }
+                #[allow(unreachable_patterns)]
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The Rust value wrapped by a foreign object, if this is one holding a `T`. Like strings,
+    /// it should not be kept beyond the native call which received it.
+    pub fn as_foreign<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Object(reference) => match reference.typ {
+                ObjectType::Foreign => {
+                    let foreign: &ForeignObject = reference.downcast().unwrap();
+                    foreign.value.downcast_ref()
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The value as programmers see it, with strings quoted, so that `"1"` and `1` differ.
+    /// [`Display`] shows strings as they are, the way `print` does.
+    pub fn repr(&self) -> impl Display + '_ {
+        Repr(self)
+    }
+
+    pub fn mark(&self) {
+        if let Value::Object(reference) = self {
+            reference.mark();
+        }
+    }
+
+    /// Copies the value out of the VM.
+    pub fn to_owned_value(&self) -> OwnedValue {
+        match self {
+            Value::Nil => OwnedValue::Nil,
+            Value::Int(int) => OwnedValue::Int(*int),
+            Value::Number(number) => OwnedValue::Number(*number),
+            Value::Boolean(boolean) => OwnedValue::Boolean(*boolean),
+            Value::Object(reference) => match reference.typ {
+                ObjectType::String => {
+                    let string_object: &StringObject = reference.downcast().unwrap();
+                    OwnedValue::String(string_object.to_string())
+                }
+                #[allow(unreachable_patterns)]
+                _ => OwnedValue::Object(self.to_string()),
+            },
+        }
+    }
+}
+
+/// A value detached from any VM, which a host can keep for as long as it likes. Objects without
+/// a Rust counterpart are only described, the way `print` shows them.
+#[derive(Clone, PartialEq, Debug)]
+pub enum OwnedValue {
+    Nil,
+    Int(i64),
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    Object(String),
+}
+
+impl From<i64> for OwnedValue {
+    fn from(int: i64) -> Self {
+        OwnedValue::Int(int)
+    }
+}
+
+impl From<f64> for OwnedValue {
+    fn from(number: f64) -> Self {
+        OwnedValue::Number(number)
+    }
+}
+
+impl From<bool> for OwnedValue {
+    fn from(boolean: bool) -> Self {
+        OwnedValue::Boolean(boolean)
+    }
+}
+
+impl From<String> for OwnedValue {
+    fn from(string: String) -> Self {
+        OwnedValue::String(string)
+    }
+}
+
+impl From<&str> for OwnedValue {
+    fn from(string: &str) -> Self {
+        OwnedValue::String(string.into())
+    }
+}
+
+impl OwnedValue {
+    /// See [`Value::repr`].
+    pub fn repr(&self) -> impl Display + '_ {
+        Repr(self)
+    }
+}
+
+impl Display for OwnedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnedValue::Nil => write!(f, "nil"),
+            OwnedValue::Int(int) => write!(f, "{}", int),
+            OwnedValue::Number(number) => write!(f, "{}", number::display(*number)),
+            OwnedValue::Boolean(boolean) => write!(f, "{}", boolean),
+            OwnedValue::String(string) => write!(f, "{}", string),
+            OwnedValue::Object(description) => write!(f, "{}", description),
+        }
+    }
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
-            Value::Number(number) => write!(f, "{}", number),
+            Value::Int(int) => write!(f, "{}", int),
+            Value::Number(number) => write!(f, "{}", number::display(*number)),
             Value::Boolean(boolean) => write!(f, "{}", boolean),
             Value::Object(reference) => match reference.typ {
                 ObjectType::String => {
                     let string_object: &StringObject = reference.downcast().unwrap();
-                    write!(f, "\"{}\"", string_object)
+                    write!(f, "{}", string_object)
+                }
+                ObjectType::Native => {
+                    let native: &NativeObject = reference.downcast().unwrap();
+                    write!(f, "<native fn {}>", native.name)
+                }
+                ObjectType::Foreign => {
+                    let foreign: &ForeignObject = reference.downcast().unwrap();
+                    write!(f, "<foreign {}>", foreign.type_name)
                 }
                 #[allow(unreachable_patterns)]
                 _ => write!(f, "<object at {:#x}>", reference.ptr()),
@@ -38,11 +283,59 @@ impl Display for Value {
     }
 }
 
+// Operands of binary arithmetic, which stay integers only while both of them are.
+enum Operands {
+    Int(i64, i64),
+    Float(f64, f64),
+}
+
+impl Operands {
+    fn of(left: &Value, right: &Value) -> Option<Self> {
+        match (left, right) {
+            (Value::Int(left), Value::Int(right)) => Some(Operands::Int(*left, *right)),
+            _ => Some(Operands::Float(left.as_number()?, right.as_number()?)),
+        }
+    }
+}
+
+// The result of multiplying or dividing integers. A zero which would be negative as a float,
+// as in `0 * -1`, is one, since integers have no negative zero.
+fn signed(result: i64, left: i64, right: i64) -> Value {
+    match result == 0 && (left < 0) != (right < 0) {
+        true => Value::Number(-0.0),
+        false => Value::Int(result),
+    }
+}
+
+struct Repr<'a, T>(&'a T);
+
+impl Display for Repr<'_, Value> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_str() {
+            Some(string) => write!(f, "\"{}\"", string),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl Display for Repr<'_, OwnedValue> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            OwnedValue::String(string) => write!(f, "\"{}\"", string),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Nil, Self::Nil) => true,
+            (Self::Int(left), Self::Int(right)) => left == right,
             (Self::Number(left), Self::Number(right)) => (left - right).abs() < f64::EPSILON,
+            (Self::Int(int), Self::Number(number)) | (Self::Number(number), Self::Int(int)) => {
+                (*int as f64 - number).abs() < f64::EPSILON
+            }
             (Self::Boolean(left), Self::Boolean(right)) => left == right,
             (Self::Object(left), Self::Object(right)) => {
                 if left == right {
@@ -55,6 +348,7 @@ impl PartialEq for Value {
                         let right: &StringObject = right.downcast().unwrap();
                         left == right
                     }
+                    _ => false,
                 }
             }
 
@@ -1,55 +1,263 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
 
 use shared::{
     chunk::{Chunk, Instruction},
     constant::Constant,
     error::{ErrorItem, InterpretError, InterpretResult, Label},
-    stack::Stack,
+    stack::{Stack, DEFAULT_STACK_CAPACITY},
 };
 
 use crate::{
-    object::{Downcast, FromUnmanaged, ManagedReference, ObjectType, StringObject},
-    value::Value,
+    object::{Downcast, NativeObject, ObjectType},
+    value::{OwnedValue, Value},
 };
 
-use self::heap::Heap;
+use self::{
+    breakpoint::{Breakpoint, Exit},
+    globals::Globals,
+    heap::Heap,
+    random::Random,
+};
 
+mod breakpoint;
+mod builder;
+mod capabilities;
+mod globals;
 mod heap;
+mod instrument;
+mod native;
+mod pattern;
+mod random;
+#[cfg(feature = "register")]
+mod register;
+mod sync;
+
+pub use crate::object::Arity;
+
+pub use self::{
+    breakpoint::Paused,
+    builder::VirtualMachineBuilder,
+    capabilities::{Capabilities, Capability},
+    heap::{GcConfig, HeapDump, HeapObject, Referrer},
+    instrument::Instrument,
+    sync::SyncVirtualMachine,
+};
 
 pub struct VirtualMachine {
-    chunk: Option<Chunk>,
     offset: usize,
+    // Stack index of the current frame's first slot. Local slots are relative to it.
+    frame_base: usize,
     stack: Stack<Value>,
     heap: Heap,
-    globals: HashMap<String, Value>,
+    globals: Globals,
+    // Inline caches for global accesses, holding the resolved slot per instruction.
+    global_caches: Vec<Option<usize>>,
+    // Command-line arguments of the script, read through the `argc` and `arg` natives.
+    args: Vec<String>,
+    // Prints the chunk and every instruction executed along with the stack.
+    trace: bool,
+    // Where `readLine` reads from. Standard input is locked per line when unset, so that it
+    // isn't buffered away from the host.
+    input: Option<Box<dyn BufRead>>,
+    // Where `print` writes to.
+    output: Box<dyn Write>,
+    capabilities: Capabilities,
+    // Whether division by zero and NaN results are runtime errors.
+    strict_arithmetic: bool,
+    // Whether to refuse what reference Lox refuses, such as adding a string and a number.
+    strict_compat: bool,
+    // State of the generator behind the `random`, `randomInt` and `seed` natives.
+    random: Random,
+    breakpoints: Vec<Breakpoint>,
+    // The chunk the VM stopped in at a breakpoint, kept until it resumes.
+    paused: Option<Chunk>,
+}
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VirtualMachine {
+    /// How many values the stack holds unless configured otherwise.
+    pub const DEFAULT_STACK_SIZE: usize = DEFAULT_STACK_CAPACITY;
+    /// The most values the stack can be configured to hold. The stack lives on the heap and
+    /// only grows as far as it is used, so this only guards against runaway recursion.
+    pub const MAX_STACK_SIZE: usize = 1 << 20;
+
     pub fn new() -> Self {
-        Self {
-            chunk: None,
-            offset: 0,
-            stack: Stack::new(),
-            heap: Heap::new(),
-            globals: HashMap::new(),
-        }
+        Self::builder().build()
+    }
+
+    pub fn builder() -> VirtualMachineBuilder {
+        VirtualMachineBuilder::new()
     }
 
     pub fn interpret(&mut self, chunk: Chunk) -> InterpretResult {
-        self.chunk = Some(chunk);
+        self.paused = None;
         self.offset = 0;
-        self.run()
+        self.frame_base = self.stack.len();
+        self.global_caches = vec![None; chunk.code.len()];
+        self.execute(chunk, false)
+    }
+
+    /// Runs the chunk like [`Self::interpret`], returning the value it leaves on the stack, or
+    /// nil if it leaves none. Only chunks compiled to keep a value leave one.
+    pub fn evaluate(&mut self, chunk: Chunk) -> InterpretResult<OwnedValue> {
+        let base = self.stack.len();
+        self.interpret(chunk)?;
+        // A paused chunk hasn't produced its value yet.
+        match self.paused.is_none() && self.stack.len() > base {
+            true => Ok(self.stack.pop()?.to_owned_value()),
+            false => Ok(OwnedValue::Nil),
+        }
     }
 
     pub fn clear_stack(&mut self) {
         self.stack.clear();
     }
 
-    fn run(&mut self) -> InterpretResult {
-        let chunk = self.chunk.as_ref().unwrap();
+    /// Sets the command-line arguments scripts can read.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Makes `readLine` read from `input` instead of standard input.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Makes `print` write to `output` instead of standard output.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Reports calls, allocations and collections to `instrument` from now on.
+    pub fn set_instrument(&mut self, instrument: impl Instrument + 'static) {
+        self.heap.set_instrument(Some(Box::new(instrument)));
+    }
+
+    /// Restricts what the built-in natives may reach outside the VM.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Makes division by zero, and arithmetic producing NaN, fail with E1020 and E1021
+    /// instead of yielding infinities and NaN.
+    pub fn set_strict_arithmetic(&mut self, strict: bool) {
+        self.strict_arithmetic = strict;
+    }
+
+    /// Makes the VM as strict as reference Lox where Ruslox is more lenient. Adding a string and
+    /// a value of another type then fails with E1005, instead of concatenating the string with
+    /// the value as `print` shows it.
+    pub fn set_strict_compat(&mut self, strict: bool) {
+        self.strict_compat = strict;
+    }
+
+    /// Restarts the sequence `random` and `randomInt` draw from, as the `seed` native does. The
+    /// same seed yields the same sequence on every platform.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    /// Traces execution to standard output, instruction by instruction.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Limits how many values the stack holds before overflowing. Sizes beyond
+    /// [`Self::MAX_STACK_SIZE`] are capped.
+    pub fn set_stack_size(&mut self, size: usize) {
+        self.stack.set_limit(size.min(Self::MAX_STACK_SIZE));
+    }
+
+    /// Names of the defined globals, natives included, in no particular order.
+    pub fn global_names(&self) -> impl Iterator<Item = &str> {
+        self.globals.names()
+    }
+
+    /// The value of a global, copied out of the VM, or `None` if it isn't defined.
+    pub fn get_global(&self, name: &str) -> Option<OwnedValue> {
+        let slot = self.globals.resolve(name)?;
+        Some(self.globals.get(slot).to_owned_value())
+    }
+
+    /// Defines a global the way `var` does, so scripts run afterwards can read it. Fails for
+    /// [`OwnedValue::Object`], which only describes an object and can't recreate it.
+    pub fn set_global(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<OwnedValue>,
+    ) -> Result<(), String> {
+        let value = match value.into() {
+            OwnedValue::Nil => Value::Nil,
+            OwnedValue::Int(int) => Value::Int(int),
+            OwnedValue::Number(number) => Value::Number(number),
+            OwnedValue::Boolean(boolean) => Value::Boolean(boolean),
+            OwnedValue::String(string) => self.new_string(string),
+            OwnedValue::Object(description) => {
+                return Err(format!("{} can't be copied into the VM", description))
+            }
+        };
+        self.globals.define(&name.into(), value);
+        Ok(())
+    }
+
+    /// Caps the bytes the heap may hold. `None` removes the cap.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.heap.set_memory_limit(limit);
+    }
+
+    /// The objects alive on the heap, with the globals and stack slots referring to each.
+    pub fn heap_dump(&self) -> HeapDump {
+        let mut dump = self.heap.dump();
+        let objects: HashMap<_, _> = self
+            .heap
+            .references()
+            .enumerate()
+            .map(|(index, reference)| (reference.ptr(), index))
+            .collect();
+        let mut refer = |value: &Value, referrer: Referrer| {
+            if let Value::Object(reference) = value {
+                if let Some(&index) = objects.get(&reference.ptr()) {
+                    dump.objects[index].referrers.push(referrer);
+                }
+            }
+        };
+        let mut names: Vec<_> = self.globals.names().collect();
+        names.sort_unstable();
+        for name in names {
+            let slot = self.globals.resolve(name).unwrap();
+            refer(self.globals.get(slot), Referrer::Global(name.into()));
+        }
+        for (slot, value) in self.stack.iter().enumerate() {
+            refer(value, Referrer::Stack(slot));
+        }
+        dump
+    }
+
+    // Every live value sits either on the stack or in globals between instructions.
+    fn collect_garbage(&mut self, extra_root: Option<&Value>) {
+        self.heap.collect(
+            self.stack
+                .iter()
+                .chain(self.globals.values())
+                .chain(extra_root),
+        );
+    }
 
-        #[cfg(debug_assertions)]
-        {
+    // Runs until the chunk returns or, unless `resumed` from it, reaches a breakpoint.
+    fn run(&mut self, chunk: &Chunk, resumed: bool) -> InterpretResult<Exit> {
+        if self.trace {
             chunk.disassemble("Chunk Disassembly");
             println!();
         }
@@ -74,57 +282,189 @@ impl VirtualMachine {
         }
 
         macro_rules! arithmetic {
-            ($operator:tt, $typ:ident) => {{
+            ($operation:ident) => {{
                 let right = self.stack.pop()?;
                 let left = self.stack.pop()?;
 
-                match (left, right) {
-                    (Value::Number(left), Value::Number(right)) => {
-                        self.stack.push(Value::$typ(left $operator right))?;
+                match left.$operation(&right) {
+                    Some(result) => self.stack.push(result)?,
+                    None => report!(
+                        "E1003",
+                        "operands must be numbers",
+                        "arithmetic operation within this statement"
+                    ),
+                }
+            }};
+        }
+
+        // In strict arithmetic mode, a NaN left by an arithmetic instruction is an error.
+        macro_rules! check_nan {
+            () => {
+                if self.strict_arithmetic && self.stack.peek().is_some_and(Value::is_nan) {
+                    report!(
+                        "E1021",
+                        "arithmetic produced NaN",
+                        "arithmetic operation within this statement"
+                    );
+                }
+            };
+        }
+
+        // Collects garbage once the heap is over its limit, and fails if that isn't enough.
+        // Natives allocate on their own, so their results are checked as well.
+        macro_rules! within_limit {
+            ($value:expr) => {{
+                let value = $value;
+                if self.heap.is_exhausted() {
+                    self.collect_garbage(Some(&value));
+                    if self.heap.is_exhausted() {
+                        report!(
+                            "E1014",
+                            "out of memory",
+                            "allocation within this statement exceeds the heap limit"
+                        );
+                    }
+                }
+                value
+            }};
+        }
+
+        macro_rules! allocate {
+            ($reference:expr) => {
+                within_limit!(Value::Object($reference))
+            };
+        }
+
+        macro_rules! resolve_global {
+            ($index:expr) => {
+                match self.global_caches[self.offset] {
+                    Some(slot) => slot,
+                    None => {
+                        let name = match &chunk.constants[*$index as usize] {
+                            Constant::String(name) => name,
+                            _ => report!("E1006", "invalid name of global definition"),
+                        };
+                        let slot = match self.globals.resolve(name) {
+                            Some(slot) => slot,
+                            None => report!("E1008", format!("undefined global {}", name)),
+                        };
+                        self.global_caches[self.offset] = Some(slot);
+                        slot
                     }
+                }
+            };
+        }
+
+        // Operand of the fused arithmetic instructions, which the compiler only emits for
+        // number literals.
+        macro_rules! number_constant {
+            ($index:expr) => {
+                match chunk.constants[*$index as usize] {
+                    Constant::Int(int) => Value::Int(int),
+                    Constant::Number(number) => Value::Number(number),
                     _ => report!(
                         "E1003",
                         "operands must be numbers",
                         "arithmetic operation within this statement"
                     ),
                 }
+            };
+        }
+
+        macro_rules! arithmetic_constant {
+            ($left:expr, $index:expr, $operation:ident) => {{
+                match $left.$operation(&number_constant!($index)) {
+                    Some(result) => self.stack.push(result)?,
+                    None => report!(
+                        "E1003",
+                        "operands must be numbers",
+                        "arithmetic operation within this statement"
+                    ),
+                }
             }};
         }
-        #[rustfmt::skip] macro_rules! arithmetic_calc {($operator:tt) => { arithmetic!($operator, Number) };}
-        #[rustfmt::skip] macro_rules! arithmetic_cmp { ($operator:tt) => { arithmetic!($operator, Boolean) };}
 
-        #[cfg(debug_assertions)]
-        {
+        // Adds numbers or concatenates strings. Unless strictly compatible with Lox, a string
+        // is also concatenated with any other value, as `print` shows it.
+        macro_rules! add {
+            ($left:expr, $right:expr) => {{
+                let (left, right) = ($left, $right);
+                match left.add(&right) {
+                    Some(sum) => {
+                        self.stack.push(sum)?;
+                        check_nan!();
+                    }
+                    None => {
+                        let concatenable = match (left.as_str(), right.as_str()) {
+                            (Some(_), Some(_)) => true,
+                            (Some(_), None) | (None, Some(_)) => !self.strict_compat,
+                            (None, None) => false,
+                        };
+                        if !concatenable {
+                            report!(
+                                "E1005",
+                                "concatenation operands must be both numbers or both strings."
+                            );
+                        }
+                        let concat = format!("{}{}", left, right);
+                        let value = allocate!(self.heap.manage_string(&concat));
+                        self.stack.push(value)?;
+                    }
+                }
+            }};
+        }
+
+        if self.trace {
             println!("== VM Stack Steps ==");
         }
 
+        // Span of the instruction run last, so a statement only stops at its first instruction.
+        let mut previous = resumed.then(|| chunk.positions[self.offset].clone());
         loop {
-            #[cfg(debug_assertions)]
-            {
+            if !self.breakpoints.is_empty() {
+                let position = &chunk.positions[self.offset];
+                if previous.as_ref() != Some(position)
+                    && self
+                        .breakpoints
+                        .iter()
+                        .any(|breakpoint| breakpoint.covers(chunk.file_id, position))
+                {
+                    return Ok(Exit::Paused);
+                }
+                previous = Some(position.clone());
+            }
+
+            if self.trace {
                 if !self.stack.is_empty() {
                     print!("          ");
-                    for i in 0..self.stack.len() {
-                        print!("[ {} ]", self.stack[i]);
+                    for value in &self.stack {
+                        print!("[ {} ]", value.repr());
                     }
                     println!();
                 }
                 chunk.disassemble_instruction(self.offset);
             }
 
+            if self.heap.should_collect() {
+                self.collect_garbage(None);
+            }
+
             match &chunk.code[self.offset] {
                 // Instructions with operand.
                 Instruction::Constant(constant_index) => {
-                    let constant = chunk.constants[*constant_index as usize].clone();
-                    match constant {
-                        Constant::Number(number) => self.stack.push(Value::Number(number))?,
-                        Constant::String(string) => self
-                            .stack
-                            .push(Value::Object(self.heap.manage_string(string)))?,
+                    match &chunk.constants[*constant_index as usize] {
+                        Constant::Int(int) => self.stack.push(Value::Int(*int))?,
+                        Constant::Number(number) => self.stack.push(Value::Number(*number))?,
+                        Constant::String(string) => {
+                            let value = allocate!(self.heap.manage_string(string));
+                            self.stack.push(value)?;
+                        }
+                        Constant::Boolean(boolean) => self.stack.push(Value::Boolean(*boolean))?,
+                        Constant::Nil => self.stack.push(Value::Nil)?,
                     }
                 }
                 Instruction::DefineGlobal(index) => {
-                    let name = chunk.constants[*index as usize].clone();
-                    let name = match name {
+                    let name = match &chunk.constants[*index as usize] {
                         Constant::String(name) => name,
                         _ => report!("E1006", "invalid name of global definition"),
                     };
@@ -132,38 +472,23 @@ impl VirtualMachine {
                         Some(value) => value.clone(),
                         None => report!("E1007", "defining global with empty stack"),
                     };
-                    self.globals.insert(name, value);
+                    self.globals.define(name, value);
                     self.stack.pop()?; // We dont pop first then insert because of GC.
                 }
                 Instruction::GetGlobal(index) => {
-                    let name = chunk.constants[*index as usize].clone();
-                    let name = match name {
-                        Constant::String(name) => name,
-                        _ => report!("E1006", "invalid name of global definition"),
-                    };
-                    let value = match self.globals.get(&name) {
-                        Some(value) => value.clone(),
-                        None => report!("E1008", format!("undefined global {}", name)),
-                    };
-                    self.stack.push(value)?;
+                    let slot = resolve_global!(index);
+                    self.stack.push(self.globals.get(slot).clone())?;
                 }
                 Instruction::SetGlobal(index) => {
-                    let name = chunk.constants[*index as usize].clone();
-                    let name = match name {
-                        Constant::String(name) => name,
-                        _ => report!("E1006", "invalid name of global definition"),
-                    };
-                    if !self.globals.contains_key(&name) {
-                        report!("E1008", format!("undefined global {}", name));
-                    }
+                    let slot = resolve_global!(index);
                     let value = match self.stack.peek() {
                         Some(value) => value.clone(),
                         None => report!("E1007", "defining global with empty stack"),
                     };
-                    self.globals.insert(name, value);
+                    self.globals.set(slot, value);
                 }
                 Instruction::GetLocal(index) => {
-                    let index = *index as usize;
+                    let index = self.frame_base + *index as usize;
                     if index >= self.stack.len() {
                         report!("E1009", "get local with empty stack");
                     }
@@ -171,7 +496,7 @@ impl VirtualMachine {
                     self.stack.push(local)?;
                 }
                 Instruction::SetLocal(index) => {
-                    let index = *index as usize;
+                    let index = self.frame_base + *index as usize;
                     if index < self.stack.len() {
                         self.stack[index] = self.stack.peek().unwrap().clone();
                     } else {
@@ -185,26 +510,104 @@ impl VirtualMachine {
                     };
                     let falsiness = !value.as_bool();
                     if falsiness {
-                        let offset = *offset as usize;
-                        if self.offset + offset >= chunk.code.len() {
+                        let target = self.offset + *offset as usize;
+                        if *offset == 0 || target >= chunk.code.len() {
                             report!("E1011", "jumping out of code");
                         }
-                        self.offset += offset - 1; // Subtract by 1 because the offset is increased by 1 every loop.
+                        // Skips the increment at the end of the loop.
+                        self.offset = target;
+                        continue;
                     }
                 }
                 Instruction::Jump(offset) => {
-                    let offset = *offset as usize;
-                    if self.offset + offset >= chunk.code.len() {
+                    let target = self.offset + *offset as usize;
+                    if *offset == 0 || target >= chunk.code.len() {
                         report!("E1011", "jumping out of code");
                     }
-                    self.offset += offset - 1;
+                    self.offset = target;
+                    continue;
                 }
                 Instruction::Loop(offset) => {
                     let offset = *offset as usize;
-                    if self.offset < offset {
+                    if offset == 0 || self.offset < offset {
                         report!("E1013", "loop back out of code");
                     }
-                    self.offset -= offset + 1;
+                    self.offset -= offset;
+                    continue;
+                }
+                Instruction::Call(argc) => {
+                    let argc = *argc as usize;
+                    if self.stack.len() <= argc {
+                        report!("E1002", "stack underflow");
+                    }
+                    let callee_slot = self.stack.len() - argc - 1;
+                    let (arity, native) = match &self.stack[callee_slot] {
+                        Value::Object(reference) => match reference.typ {
+                            ObjectType::Native => {
+                                let native: &NativeObject = reference.downcast().unwrap();
+                                if let Some(capability) = native
+                                    .requires
+                                    .filter(|capability| !self.capabilities.allows(*capability))
+                                {
+                                    report!(
+                                        "E1018",
+                                        format!(
+                                            "`{}` needs the {} capability, which is disabled",
+                                            native.name, capability
+                                        )
+                                    );
+                                }
+                                (native.arity, reference.clone())
+                            }
+                            _ => report!("E1015", "can only call functions"),
+                        },
+                        _ => report!("E1015", "can only call functions"),
+                    };
+                    if !arity.accepts(argc) {
+                        report!(
+                            "E1016",
+                            format!("expected {} arguments but got {}", arity, argc)
+                        );
+                    }
+                    let arguments: Vec<Value> =
+                        self.stack.iter().skip(callee_slot + 1).cloned().collect();
+                    let result = match self.call_native(native, &arguments) {
+                        Ok(result) => result,
+                        Err(message) => {
+                            report!("E1017", message, "native call within this statement")
+                        }
+                    };
+                    self.stack.truncate(callee_slot);
+                    let result = within_limit!(result);
+                    self.stack.push(result)?;
+                }
+
+                Instruction::PopN(count) => {
+                    for _ in 0..*count {
+                        self.stack.pop()?;
+                    }
+                }
+
+                // Superinstructions fused by the compiler.
+                Instruction::AddConstant(index) => {
+                    let left = self.stack.pop()?;
+                    add!(left, number_constant!(index))
+                }
+                Instruction::LessConstant(index) => {
+                    let left = self.stack.pop()?;
+                    arithmetic_constant!(left, index, less)
+                }
+                Instruction::GreaterConstant(index) => {
+                    let left = self.stack.pop()?;
+                    arithmetic_constant!(left, index, greater)
+                }
+                Instruction::GetLocalAddConstant(slot, index) => {
+                    let slot = self.frame_base + *slot as usize;
+                    if slot >= self.stack.len() {
+                        report!("E1009", "get local with empty stack");
+                    }
+                    let left = self.stack[slot].clone();
+                    add!(left, number_constant!(index))
                 }
 
                 // Literal instructions.
@@ -216,34 +619,30 @@ impl VirtualMachine {
                 Instruction::Add => {
                     let right = self.stack.pop()?;
                     let left = self.stack.pop()?;
-                    match (left, right) {
-                        (Value::Number(left), Value::Number(right)) => {
-                            self.stack.push(Value::Number(left + right))?
-                        }
-                        (Value::Object(left), Value::Object(right)) => {
-                            match (left.typ, right.typ) {
-                                (ObjectType::String, ObjectType::String) => {
-                                    let left: &StringObject = left.downcast().unwrap();
-                                    let right: &StringObject = right.downcast().unwrap();
-                                    let concat = format!("{}{}", left, right);
-                                    self.stack.push(Value::Object(
-                                        ManagedReference::from_unmanaged(concat, &mut self.heap),
-                                    ))?;
-                                }
-                            }
-                        }
-                        _ => report!(
-                            "E1005",
-                            "concatenation operands must be both numbers or both strings."
-                        ),
+                    add!(left, right)
+                }
+                Instruction::Subtract => {
+                    arithmetic!(subtract);
+                    check_nan!();
+                }
+                Instruction::Multiply => {
+                    arithmetic!(multiply);
+                    check_nan!();
+                }
+                Instruction::Divide => {
+                    if self.strict_arithmetic && self.stack.peek().is_some_and(Value::is_zero) {
+                        report!(
+                            "E1020",
+                            "division by zero",
+                            "division within this statement"
+                        );
                     }
+                    arithmetic!(divide);
+                    check_nan!();
                 }
-                Instruction::Subtract => arithmetic_calc!(-),
-                Instruction::Multiply => arithmetic_calc!(*),
-                Instruction::Divide => arithmetic_calc!(/),
-                Instruction::Negate => match self.stack.pop()? {
-                    Value::Number(number) => self.stack.push(Value::Number(-number))?,
-                    _ => report!(
+                Instruction::Negate => match self.stack.pop()?.negate() {
+                    Some(negated) => self.stack.push(negated)?,
+                    None => report!(
                         "E1004",
                         "operand must be number",
                         "arithmetic negation within this statement"
@@ -264,12 +663,17 @@ impl VirtualMachine {
                     let left = self.stack.pop()?;
                     self.stack.push(Value::Boolean(left == right))?;
                 }
-                Instruction::Greater => arithmetic_cmp!(>),
-                Instruction::Less => arithmetic_cmp!(<),
+                Instruction::Greater => arithmetic!(greater),
+                Instruction::Less => arithmetic!(less),
 
                 // Miscellaneous.
-                Instruction::Return => return Ok(()),
-                Instruction::Print => println!("{}", self.stack.pop()?),
+                Instruction::Return => return Ok(Exit::Returned),
+                Instruction::Print => {
+                    let value = self.stack.pop()?;
+                    if let Err(error) = writeln!(self.output, "{}", value) {
+                        report!("E1019", format!("failed to write output: {}", error));
+                    }
+                }
                 Instruction::Pop => {
                     self.stack.pop()?;
                 }
@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use shared::{chunk::Chunk, error::InterpretResult};
+
+use crate::value::OwnedValue;
+
+use super::VirtualMachine;
+
+/// A source range the VM pauses at. It stops before running a statement which starts within
+/// it, so a breakpoint spanning a line stops at the statements starting on that line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(super) struct Breakpoint {
+    file_id: usize,
+    span: Range<usize>,
+}
+
+impl Breakpoint {
+    pub fn covers(&self, file_id: usize, position: &Range<usize>) -> bool {
+        let end = self.span.end.max(self.span.start + 1);
+        // The return ending a chunk has an empty position, belonging to no statement.
+        self.file_id == file_id
+            && !position.is_empty()
+            && (self.span.start..end).contains(&position.start)
+    }
+}
+
+/// What a VM paused at a breakpoint holds, as of when it stopped.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Paused {
+    pub file_id: usize,
+    /// Span of the statement about to run.
+    pub span: Range<usize>,
+    /// The whole stack, bottom first.
+    pub stack: Vec<OwnedValue>,
+    /// Slots of the locals in scope, in the order the resolver numbers them. Names aren't kept
+    /// in bytecode, so front ends match them up with the declarations in the source.
+    pub locals: Vec<OwnedValue>,
+}
+
+/// How running a chunk came to an end.
+pub(super) enum Exit {
+    Returned,
+    Paused,
+}
+
+impl VirtualMachine {
+    /// Makes the VM pause before running any statement starting within `span` of the file
+    /// `file_id`. [`Self::interpret`] then returns early, leaving [`Self::paused`] to inspect
+    /// the VM and [`Self::resume`] to carry on. Only the stack machine stops at breakpoints.
+    pub fn set_breakpoint(&mut self, file_id: usize, span: Range<usize>) {
+        let breakpoint = Breakpoint { file_id, span };
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes a breakpoint set with the same file and span. Returns whether there was one.
+    pub fn clear_breakpoint(&mut self, file_id: usize, span: Range<usize>) -> bool {
+        let breakpoint = Breakpoint { file_id, span };
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|other| *other != breakpoint);
+        self.breakpoints.len() < count
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Where the VM is paused and what it holds, or `None` unless it stopped at a breakpoint.
+    pub fn paused(&self) -> Option<Paused> {
+        let chunk = self.paused.as_ref()?;
+        let stack: Vec<_> = self
+            .stack
+            .iter()
+            .map(|value| value.to_owned_value())
+            .collect();
+        Some(Paused {
+            file_id: chunk.file_id,
+            span: chunk.positions[self.offset].clone(),
+            locals: stack[self.frame_base.min(stack.len())..].to_vec(),
+            stack,
+        })
+    }
+
+    /// Carries on running the chunk the VM paused in, until it ends or reaches a breakpoint
+    /// again. Does nothing unless the VM is paused.
+    pub fn resume(&mut self) -> InterpretResult {
+        match self.paused.take() {
+            Some(chunk) => self.execute(chunk, true),
+            None => Ok(()),
+        }
+    }
+
+    // Runs the chunk from the current offset, keeping it aside if the VM pauses.
+    pub(super) fn execute(&mut self, chunk: Chunk, resumed: bool) -> InterpretResult {
+        if let Exit::Paused = self.run(&chunk, resumed)? {
+            self.paused = Some(chunk);
+        }
+        Ok(())
+    }
+}
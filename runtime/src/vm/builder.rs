@@ -0,0 +1,141 @@
+use std::io::{self, BufRead, Write};
+
+use shared::stack::Stack;
+
+use super::{
+    heap::Heap, random::Random, Capabilities, GcConfig, Globals, Instrument, VirtualMachine,
+};
+
+/// Configures a [`VirtualMachine`] before creating it. Anything left unset keeps the default
+/// of [`VirtualMachine::new`].
+pub struct VirtualMachineBuilder {
+    stack_size: usize,
+    heap_limit: Option<usize>,
+    gc_config: GcConfig,
+    trace: bool,
+    input: Option<Box<dyn BufRead>>,
+    output: Option<Box<dyn Write>>,
+    capabilities: Capabilities,
+    instrument: Option<Box<dyn Instrument>>,
+    strict_arithmetic: bool,
+    strict_compat: bool,
+    seed: u64,
+}
+
+impl Default for VirtualMachineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualMachineBuilder {
+    pub fn new() -> Self {
+        Self {
+            stack_size: VirtualMachine::DEFAULT_STACK_SIZE,
+            heap_limit: None,
+            gc_config: GcConfig::default(),
+            trace: false,
+            input: None,
+            output: None,
+            capabilities: Capabilities::default(),
+            instrument: None,
+            strict_arithmetic: false,
+            strict_compat: false,
+            seed: Random::DEFAULT_SEED,
+        }
+    }
+
+    /// See [`VirtualMachine::set_stack_size`].
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    /// See [`VirtualMachine::set_memory_limit`].
+    pub fn heap_limit(mut self, limit: Option<usize>) -> Self {
+        self.heap_limit = limit;
+        self
+    }
+
+    pub fn gc_config(mut self, config: GcConfig) -> Self {
+        self.gc_config = config;
+        self
+    }
+
+    /// See [`VirtualMachine::set_trace`].
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// See [`VirtualMachine::set_input`].
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// See [`VirtualMachine::set_output`].
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// See [`VirtualMachine::set_capabilities`].
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// See [`VirtualMachine::set_instrument`].
+    pub fn instrument(mut self, instrument: impl Instrument + 'static) -> Self {
+        self.instrument = Some(Box::new(instrument));
+        self
+    }
+
+    /// See [`VirtualMachine::set_strict_arithmetic`].
+    pub fn strict_arithmetic(mut self, strict: bool) -> Self {
+        self.strict_arithmetic = strict;
+        self
+    }
+
+    /// See [`VirtualMachine::set_strict_compat`].
+    pub fn strict_compat(mut self, strict: bool) -> Self {
+        self.strict_compat = strict;
+        self
+    }
+
+    /// See [`VirtualMachine::set_seed`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn build(self) -> VirtualMachine {
+        let mut stack = Stack::new();
+        stack.set_limit(self.stack_size.min(VirtualMachine::MAX_STACK_SIZE));
+        let mut heap = Heap::new(self.gc_config);
+        heap.set_memory_limit(self.heap_limit);
+        heap.set_instrument(self.instrument);
+
+        let mut vm = VirtualMachine {
+            offset: 0,
+            frame_base: 0,
+            stack,
+            heap,
+            globals: Globals::new(),
+            global_caches: Vec::new(),
+            args: Vec::new(),
+            trace: self.trace,
+            input: self.input,
+            output: self.output.unwrap_or_else(|| Box::new(io::stdout())),
+            capabilities: self.capabilities,
+            strict_arithmetic: self.strict_arithmetic,
+            strict_compat: self.strict_compat,
+            random: Random::new(self.seed),
+            breakpoints: Vec::new(),
+            paused: None,
+        };
+        vm.define_natives();
+        vm
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::Display;
+
+/// Something outside the VM a native function may reach. Built-in natives only need `Process`
+/// and `WallClock`; the rest are for natives the host defines with
+/// [`VirtualMachine::define_native_requiring`].
+///
+/// [`VirtualMachine::define_native_requiring`]: super::VirtualMachine::define_native_requiring
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Capability {
+    /// Reading and writing files.
+    Filesystem,
+    /// The arguments and standard streams of the process.
+    Process,
+    /// Sockets and other network access.
+    Network,
+    /// The current time.
+    WallClock,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Filesystem => write!(f, "filesystem"),
+            Capability::Process => write!(f, "process"),
+            Capability::Network => write!(f, "network"),
+            Capability::WallClock => write!(f, "wall-clock"),
+        }
+    }
+}
+
+/// The capabilities natives are allowed to use. Calling a native which needs a
+/// disabled one fails with E1018. Everything is allowed by default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    pub filesystem: bool,
+    pub process: bool,
+    pub network: bool,
+    pub wall_clock: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            filesystem: true,
+            process: true,
+            network: true,
+            wall_clock: true,
+        }
+    }
+
+    /// For untrusted scripts, which then only compute.
+    pub fn none() -> Self {
+        Self {
+            filesystem: false,
+            process: false,
+            network: false,
+            wall_clock: false,
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Filesystem => self.filesystem,
+            Capability::Process => self.process,
+            Capability::Network => self.network,
+            Capability::WallClock => self.wall_clock,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use crate::{
+    object::{BuildStringHasher, StringObject},
+    value::Value,
+};
+
+/// Global variables stored in slots, so that a resolved name can be cached by its slot and
+/// later accesses skip hashing the name entirely. Names are kept as string objects, so growing
+/// the table reuses their hashes instead of hashing them again.
+///
+/// Globals are never removed, which keeps every handed-out slot valid.
+pub struct Globals {
+    slots: Vec<Value>,
+    indices: HashMap<StringObject, usize, BuildStringHasher>,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            indices: HashMap::default(),
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) -> usize {
+        match self.indices.get(name) {
+            Some(&slot) => {
+                self.slots[slot] = value;
+                slot
+            }
+            None => {
+                self.slots.push(value);
+                self.indices
+                    .insert(StringObject::from(name), self.slots.len() - 1);
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn get(&self, slot: usize) -> &Value {
+        &self.slots[slot]
+    }
+
+    pub fn set(&mut self, slot: usize, value: Value) {
+        self.slots[slot] = value;
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.indices.keys().map(|name| &**name)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.slots.iter()
+    }
+}
@@ -1,35 +1,247 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    mem,
+};
 
-use crate::object::{FromUnmanaged, GarbageCollect, ManagedReference, StringObject};
+use crate::{
+    object::{
+        BuildStringHasher, FromUnmanaged, GarbageCollect, ManagedReference, ObjectType,
+        StringObject,
+    },
+    value::Value,
+};
+
+use super::Instrument;
+
+const INITIAL_COLLECTION_THRESHOLD: usize = 1024;
+const COLLECTION_GROW_FACTOR: usize = 2;
+// Longer descriptions of objects are cut short in heap dumps.
+const DESCRIPTION_LIMIT: usize = 40;
+
+/// When the collector runs. The first collection happens once `initial_threshold` objects are
+/// alive, and each collection sets the next threshold to `grow_factor` times the objects that
+/// survived it, but never below `initial_threshold`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GcConfig {
+    pub initial_threshold: usize,
+    pub grow_factor: usize,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: INITIAL_COLLECTION_THRESHOLD,
+            grow_factor: COLLECTION_GROW_FACTOR,
+        }
+    }
+}
+
+/// The objects alive on the heap at some point, in the order they were allocated.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HeapDump {
+    pub objects: Vec<HeapObject>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HeapObject {
+    /// `string`, `native` or `foreign`.
+    pub kind: &'static str,
+    /// Bytes the object holds, counted as for the memory limit.
+    pub size: usize,
+    /// The object as `repr` shows it, cut short if long.
+    pub description: String,
+    /// Roots holding the object. Objects don't refer to each other yet, so these are all
+    /// there is to what keeps an object alive.
+    pub referrers: Vec<Referrer>,
+}
+
+/// Something holding a reference to an object.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Referrer {
+    Global(String),
+    /// A slot of the stack, counting from the bottom.
+    Stack(usize),
+}
+
+impl HeapDump {
+    pub fn bytes(&self) -> usize {
+        self.objects.iter().map(|object| object.size).sum()
+    }
+}
+
+impl Display for HeapDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} objects, {} bytes", self.objects.len(), self.bytes())?;
+        for object in &self.objects {
+            write!(
+                f,
+                "{:<8}{:>8}  {}",
+                object.kind, object.size, object.description
+            )?;
+            for (index, referrer) in object.referrers.iter().enumerate() {
+                let separator = if index == 0 { "  <- " } else { ", " };
+                write!(f, "{}{}", separator, referrer)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Referrer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Referrer::Global(name) => write!(f, "global {}", name),
+            Referrer::Stack(slot) => write!(f, "stack slot {}", slot),
+        }
+    }
+}
 
 pub struct Heap {
     references: Vec<ManagedReference>,
-    interned_strings: HashMap<StringObject, ManagedReference>,
+    // Interned strings are weak: they don't keep their objects alive and are purged on sweep.
+    interned_strings: HashMap<StringObject, ManagedReference, BuildStringHasher>,
+    next_collection: usize,
+    bytes_allocated: usize,
+    memory_limit: Option<usize>,
+    config: GcConfig,
+    // Lives here rather than in the VM since the heap is what sees allocations and collections.
+    instrument: Option<Box<dyn Instrument>>,
 }
 
 impl Heap {
-    pub fn new() -> Self {
+    pub fn new(config: GcConfig) -> Self {
         Self {
             references: Vec::new(),
-            interned_strings: HashMap::new(),
+            interned_strings: HashMap::default(),
+            next_collection: config.initial_threshold,
+            bytes_allocated: 0,
+            memory_limit: None,
+            config,
+            instrument: None,
         }
     }
 
-    pub fn manage_string(&mut self, string: String) -> ManagedReference {
-        match self.interned_strings.get(&string) {
+    pub fn set_instrument(&mut self, instrument: Option<Box<dyn Instrument>>) {
+        self.instrument = instrument;
+    }
+
+    pub fn instrument(&mut self) -> Option<&mut (dyn Instrument + 'static)> {
+        self.instrument.as_deref_mut()
+    }
+
+    /// The interned string object holding `string`, allocating it unless it is already alive.
+    pub fn manage_string(&mut self, string: &str) -> ManagedReference {
+        match self.interned_strings.get(string) {
             Some(reference) => reference.clone(),
             None => {
-                let reference =
-                    ManagedReference::from_unmanaged(StringObject::from(string.clone()), self);
-                self.interned_strings.insert(string, reference.clone());
+                let object = StringObject::from(string);
+                let reference = ManagedReference::from_unmanaged(object.clone(), self);
+                self.interned_strings.insert(object, reference.clone());
                 reference
             }
         }
     }
+
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        match self.memory_limit {
+            Some(limit) => self.bytes_allocated > limit,
+            None => false,
+        }
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn references(&self) -> impl Iterator<Item = &ManagedReference> {
+        self.references.iter()
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.references.len()
+    }
+
+    /// The objects alive, without their referrers, which only the VM knows of.
+    pub fn dump(&self) -> HeapDump {
+        let objects = self
+            .references
+            .iter()
+            .map(|reference| {
+                let kind = match reference.typ {
+                    ObjectType::String => "string",
+                    ObjectType::Native => "native",
+                    ObjectType::Foreign => "foreign",
+                };
+                let description = Value::Object(reference.clone()).repr().to_string();
+                let description = match description.char_indices().nth(DESCRIPTION_LIMIT) {
+                    Some((end, _)) => format!("{}...", &description[..end]),
+                    None => description,
+                };
+                HeapObject {
+                    kind,
+                    size: reference.size(),
+                    description,
+                    referrers: Vec::new(),
+                }
+            })
+            .collect();
+        HeapDump { objects }
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.references.len() >= self.next_collection
+    }
+
+    pub fn collect<'a>(&mut self, roots: impl Iterator<Item = &'a Value>) {
+        if let Some(instrument) = &mut self.instrument {
+            instrument.on_collect_start();
+        }
+        for root in roots {
+            root.mark();
+        }
+        self.sweep();
+    }
+
+    fn sweep(&mut self) {
+        let bytes_before = self.bytes_allocated;
+        // Purge the weak entries first, their objects are still valid at this point.
+        self.interned_strings
+            .retain(|_, reference| reference.marked);
+
+        let references = mem::take(&mut self.references);
+        for mut reference in references {
+            if reference.marked {
+                reference.marked = false;
+                self.references.push(reference);
+            } else {
+                self.bytes_allocated -= reference.size();
+                unsafe { reference.finalize() }
+            }
+        }
+        self.next_collection = self
+            .config
+            .initial_threshold
+            .max(self.references.len() * self.config.grow_factor);
+
+        if let Some(instrument) = &mut self.instrument {
+            instrument.on_collect(bytes_before - self.bytes_allocated, self.bytes_allocated);
+        }
+    }
 }
 
 impl GarbageCollect for Heap {
     fn register(&mut self, reference: ManagedReference) {
+        let size = reference.size();
+        self.bytes_allocated += size;
+        if let Some(instrument) = &mut self.instrument {
+            instrument.on_allocate(size);
+        }
         self.references.push(reference);
     }
 }
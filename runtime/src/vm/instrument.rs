@@ -0,0 +1,27 @@
+/// Callbacks from inside the VM, for profilers and the like. Every callback does nothing by
+/// default, so an instrument only implements those it needs. A VM without an instrument skips
+/// them entirely.
+pub trait Instrument {
+    /// A native function named `name` is about to be called.
+    fn on_call(&mut self, name: &str) {
+        let _ = name;
+    }
+
+    /// The native function named `name` has returned, successfully or not.
+    fn on_return(&mut self, name: &str) {
+        let _ = name;
+    }
+
+    /// An object taking `bytes` bytes was allocated on the heap.
+    fn on_allocate(&mut self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// A collection is about to mark and sweep the heap.
+    fn on_collect_start(&mut self) {}
+
+    /// A collection finished, freeing `freed` bytes and keeping `live` bytes.
+    fn on_collect(&mut self, freed: usize, live: usize) {
+        let _ = (freed, live);
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    any::Any,
+    io::{self, BufRead},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use shared::number;
+
+use crate::{
+    object::{
+        Arity, Downcast, Finalizer, ForeignObject, FromUnmanaged, ManagedReference, NativeObject,
+    },
+    value::Value,
+};
+
+use super::{pattern::Pattern, Capability, VirtualMachine};
+
+impl VirtualMachine {
+    pub(super) fn define_natives(&mut self) {
+        self.define_native("gc", 0, gc);
+        self.define_native("memory", 0, memory);
+        self.define_native("objects", 0, objects);
+        self.define_native("random", 0, random);
+        self.define_native("randomInt", 2, random_int);
+        self.define_native("seed", 1, seed);
+        self.define_native("match", 2, match_pattern);
+        self.define_native("replace", 3, replace);
+        self.define_variadic_native("format", 1, format_string);
+        self.define_native_requiring("argc", Arity::Exactly(0), Some(Capability::Process), argc);
+        self.define_native_requiring("arg", Arity::Exactly(1), Some(Capability::Process), arg);
+        self.define_native_requiring(
+            "readLine",
+            Arity::Exactly(0),
+            Some(Capability::Process),
+            read_line,
+        );
+        self.define_native_requiring(
+            "clock",
+            Arity::Exactly(0),
+            Some(Capability::WallClock),
+            clock,
+        );
+    }
+
+    /// Defines a global function implemented in Rust, replacing any global of the same name.
+    /// Calls with other than `arity` arguments fail before reaching it, and an error it returns
+    /// is reported as E1017.
+    pub fn define_native<F>(&mut self, name: impl Into<String>, arity: usize, function: F)
+    where
+        F: Fn(&mut VirtualMachine, &[Value]) -> Result<Value, String> + 'static,
+    {
+        self.define_native_requiring(name, Arity::Exactly(arity), None, function);
+    }
+
+    /// Like [`Self::define_native`], for a function taking `min_arity` arguments or more.
+    pub fn define_variadic_native<F>(
+        &mut self,
+        name: impl Into<String>,
+        min_arity: usize,
+        function: F,
+    ) where
+        F: Fn(&mut VirtualMachine, &[Value]) -> Result<Value, String> + 'static,
+    {
+        self.define_native_requiring(name, Arity::AtLeast(min_arity), None, function);
+    }
+
+    /// Like [`Self::define_native`], for a function which reaches outside the VM through
+    /// `requires`. While the [`Capabilities`] of the VM disallow it, calls fail with E1018
+    /// before reaching the function.
+    ///
+    /// [`Capabilities`]: super::Capabilities
+    pub fn define_native_requiring<F>(
+        &mut self,
+        name: impl Into<String>,
+        arity: Arity,
+        requires: Option<Capability>,
+        function: F,
+    ) where
+        F: Fn(&mut VirtualMachine, &[Value]) -> Result<Value, String> + 'static,
+    {
+        let name = name.into();
+        let native = NativeObject {
+            name: name.clone(),
+            arity,
+            requires,
+            function: Rc::new(function),
+        };
+        let reference = ManagedReference::from_unmanaged(native, &mut self.heap);
+        self.globals.define(&name, Value::Object(reference));
+    }
+
+    /// Calls the native `reference` points to, which must stay on the stack during the call.
+    pub(super) fn call_native(
+        &mut self,
+        reference: ManagedReference,
+        arguments: &[Value],
+    ) -> Result<Value, String> {
+        let native: &NativeObject = reference.downcast().unwrap();
+        if let Some(instrument) = self.heap.instrument() {
+            instrument.on_call(&native.name);
+        }
+        let result = (native.function)(self, arguments);
+        if let Some(instrument) = self.heap.instrument() {
+            instrument.on_return(&native.name);
+        }
+        result
+    }
+
+    /// A string value, for natives to return.
+    pub fn new_string(&mut self, string: impl AsRef<str>) -> Value {
+        Value::Object(self.heap.manage_string(string.as_ref()))
+    }
+
+    /// A foreign object wrapping an arbitrary Rust value, for natives to return. Natives get it
+    /// back with [`Value::as_foreign`].
+    pub fn new_foreign<T: Any>(&mut self, value: T) -> Value {
+        self.manage_foreign(value, None)
+    }
+
+    /// Like [`Self::new_foreign`], handing the value to `finalizer` once the object is collected
+    /// or the VM is dropped, to release what it holds.
+    pub fn new_foreign_with_finalizer<T: Any>(
+        &mut self,
+        value: T,
+        finalizer: impl FnOnce(T) + 'static,
+    ) -> Value {
+        let finalizer = move |value: Box<dyn Any>| finalizer(*value.downcast().unwrap());
+        self.manage_foreign(value, Some(Box::new(finalizer)))
+    }
+
+    fn manage_foreign<T: Any>(&mut self, value: T, finalizer: Option<Finalizer>) -> Value {
+        let foreign = ForeignObject {
+            type_name: std::any::type_name::<T>(),
+            value: Box::new(value),
+            finalizer,
+        };
+        Value::Object(ManagedReference::from_unmanaged(foreign, &mut self.heap))
+    }
+}
+
+/// Forces a full collection.
+fn gc(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    vm.collect_garbage(None);
+    Ok(Value::Nil)
+}
+
+/// Bytes currently held by managed objects.
+fn memory(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(vm.heap.bytes_allocated() as i64))
+}
+
+/// Number of managed objects currently alive, natives included.
+fn objects(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(vm.heap.object_count() as i64))
+}
+
+/// A number in `[0, 1)` from the VM's generator.
+fn random(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(vm.random.next_f64()))
+}
+
+/// A whole number between the two arguments, both included, from the VM's generator.
+fn random_int(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    let (Some(low), Some(high)) = (arguments[0].as_int(), arguments[1].as_int()) else {
+        return Err("bounds of randomInt must be whole numbers".into());
+    };
+    if low > high {
+        return Err(format!(
+            "lower bound {} of randomInt is above the upper bound {}",
+            low, high
+        ));
+    }
+    Ok(Value::Int(vm.random.next_in(low, high)))
+}
+
+/// Restarts the VM's generator, so the numbers drawn afterwards repeat from run to run.
+fn seed(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    match arguments[0].as_int() {
+        Some(seed) => {
+            vm.set_seed(seed as u64);
+            Ok(Value::Nil)
+        }
+        None => Err("seed must be a whole number".into()),
+    }
+}
+
+/// The leftmost part of the text matching the pattern, or nil if none does.
+fn match_pattern(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    let (Some(text), Some(pattern)) = (arguments[0].as_str(), arguments[1].as_str()) else {
+        return Err("arguments of match must be strings".into());
+    };
+    let pattern = Pattern::parse(pattern)?;
+    let text: Vec<_> = text.chars().collect();
+    match pattern.find(&text, 0) {
+        Some((start, end)) => {
+            let matched: String = text[start..end].iter().collect();
+            Ok(vm.new_string(matched))
+        }
+        None => Ok(Value::Nil),
+    }
+}
+
+/// The text with every part matching the pattern, from left to right, replaced by the
+/// replacement.
+fn replace(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    let (Some(text), Some(pattern), Some(replacement)) = (
+        arguments[0].as_str(),
+        arguments[1].as_str(),
+        arguments[2].as_str(),
+    ) else {
+        return Err("arguments of replace must be strings".into());
+    };
+    let pattern = Pattern::parse(pattern)?;
+    let text: Vec<_> = text.chars().collect();
+    let mut replaced = String::new();
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some((start, end)) = pattern.find(&text, from) {
+        replaced.extend(&text[copied..start]);
+        replaced.push_str(replacement);
+        copied = end;
+        // An empty match would be found again at the same place, so the search steps over it.
+        from = match start == end {
+            true => end + 1,
+            false => end,
+        };
+        if from > text.len() {
+            break;
+        }
+    }
+    replaced.extend(&text[copied..]);
+    Ok(vm.new_string(replaced))
+}
+
+/// The template with each placeholder replaced by an argument after it, shown as `print` shows
+/// it. `{0}` stands for the first of them, `{1}` for the second and so on, while `{0:.2}` shows
+/// a number with two decimals. `{{` and `}}` stand for braces.
+fn format_string(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    let Some(template) = arguments[0].as_str() else {
+        return Err("template of format must be a string".into());
+    };
+    let values = &arguments[1..];
+    let mut formatted = String::new();
+    let mut rest = template.chars();
+    while let Some(c) = rest.next() {
+        match c {
+            '{' if rest.as_str().starts_with('{') => {
+                rest.next();
+                formatted.push('{');
+            }
+            '}' if rest.as_str().starts_with('}') => {
+                rest.next();
+                formatted.push('}');
+            }
+            '{' => {
+                let Some((placeholder, after)) = rest.as_str().split_once('}') else {
+                    return Err("`{` in the template of format is never closed".into());
+                };
+                placeholder_into(&mut formatted, placeholder, values)?;
+                rest = after.chars();
+            }
+            '}' => return Err("`}` in the template of format closes nothing".into()),
+            c => formatted.push(c),
+        }
+    }
+    Ok(vm.new_string(formatted))
+}
+
+// Digits after the point a placeholder may ask for. Far more than an `f64` holds, and small
+// enough for the formatting machinery of Rust, which panics past `u16::MAX`.
+const MAX_PRECISION: usize = 100;
+
+// Appends the value a placeholder such as `1` or `1:.2` stands for.
+fn placeholder_into(
+    formatted: &mut String,
+    placeholder: &str,
+    values: &[Value],
+) -> Result<(), String> {
+    let (index, precision) = match placeholder.split_once(":.") {
+        Some((index, precision)) => match precision.parse::<usize>() {
+            Ok(precision) if precision <= MAX_PRECISION => (index, Some(precision)),
+            _ => return Err(format!("`{{{}}}` has an invalid precision", placeholder)),
+        },
+        None => (placeholder, None),
+    };
+    let Ok(index) = index.parse::<usize>() else {
+        return Err(format!(
+            "`{{{}}}` is not a placeholder like `{{0}}`",
+            placeholder
+        ));
+    };
+    let Some(value) = values.get(index) else {
+        return Err(format!(
+            "placeholder `{{{}}}` is out of range for {} values",
+            placeholder,
+            values.len()
+        ));
+    };
+    match (precision, value.as_number()) {
+        (None, _) => formatted.push_str(&value.to_string()),
+        (Some(precision), Some(number)) if number.is_finite() => {
+            formatted.push_str(&format!("{:.*}", precision, number))
+        }
+        (Some(_), Some(number)) => formatted.push_str(&number::display(number).to_string()),
+        (Some(_), None) => {
+            return Err(format!(
+                "placeholder `{{{}}}` needs a number, not {}",
+                placeholder,
+                value.repr()
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Number of command-line arguments passed to the script.
+fn argc(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(vm.args.len() as i64))
+}
+
+/// The command-line argument at the given index, counting from 0.
+fn arg(vm: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    let index = match arguments[0].as_int() {
+        Some(index) if index >= 0 => index as usize,
+        _ => return Err("argument index must be a non-negative whole number".into()),
+    };
+    match vm.args.get(index) {
+        Some(argument) => {
+            let argument = argument.clone();
+            Ok(vm.new_string(argument))
+        }
+        None => Err(format!(
+            "argument index {} is out of range for {} arguments",
+            index,
+            vm.args.len()
+        )),
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn clock(_: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|error| format!("system clock is before the Unix epoch: {}", error))?;
+    Ok(Value::Number(elapsed.as_secs_f64()))
+}
+
+/// The next line of input without its line break, or nil at the end of input.
+fn read_line(vm: &mut VirtualMachine, _: &[Value]) -> Result<Value, String> {
+    let mut line = String::new();
+    let read = match &mut vm.input {
+        Some(input) => input.read_line(&mut line),
+        None => io::stdin().lock().read_line(&mut line),
+    };
+    match read.map_err(|error| format!("failed to read a line: {}", error))? {
+        0 => Ok(Value::Nil),
+        _ => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line).to_string();
+            Ok(vm.new_string(line))
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! The patterns of the `match` and `replace` natives: a small subset of regular expressions,
+//! without groups or alternation.
+//!
+//! - `.` matches any character, and any other character matches itself;
+//! - `[abc]`, `[a-z]` and `[^abc]` match one character of, or not of, a class;
+//! - `\d`, `\w` and `\s` match a digit, a word character and whitespace, while `\` before
+//!   anything else matches that character literally;
+//! - `*`, `+` and `?` repeat what precedes them any number of times, at least once, or at
+//!   most once, as many times as possible;
+//! - `^` and `$` at the ends of the pattern anchor it to the ends of the text.
+
+/// A parsed pattern, matched against text as a sequence of characters.
+pub(super) struct Pattern {
+    pieces: Vec<Piece>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: usize,
+}
+
+enum Atom {
+    Any,
+    Char(char),
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let mut chars: Vec<_> = pattern.chars().collect();
+        let anchored_start = chars.first() == Some(&'^');
+        if anchored_start {
+            chars.remove(0);
+        }
+        let anchored_end = chars.last() == Some(&'$') && !ends_escaped(&chars);
+        if anchored_end {
+            chars.pop();
+        }
+
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut index = 0;
+        while let Some(&c) = chars.get(index) {
+            index += 1;
+            let atom = match c {
+                '.' => Atom::Any,
+                '\\' => match chars.get(index) {
+                    Some(&escaped) => {
+                        index += 1;
+                        escape(escaped)
+                    }
+                    None => return Err("pattern ends with a lone `\\`".into()),
+                },
+                '[' => class(&chars, &mut index)?,
+                '*' | '+' | '?' => {
+                    let (min, max) = match c {
+                        '*' => (0, usize::MAX),
+                        '+' => (1, usize::MAX),
+                        _ => (0, 1),
+                    };
+                    match pieces.last_mut() {
+                        Some(piece) if piece.min == 1 && piece.max == 1 => {
+                            piece.min = min;
+                            piece.max = max;
+                            continue;
+                        }
+                        _ => return Err(format!("`{}` in a pattern has nothing to repeat", c)),
+                    }
+                }
+                c => Atom::Char(c),
+            };
+            pieces.push(Piece {
+                atom,
+                min: 1,
+                max: 1,
+            });
+        }
+        Ok(Self {
+            pieces,
+            anchored_start,
+            anchored_end,
+        })
+    }
+
+    /// Start and end, in characters, of the leftmost match at or after `from`.
+    pub fn find(&self, text: &[char], from: usize) -> Option<(usize, usize)> {
+        let last_start = match self.anchored_start {
+            true if from > 0 => return None,
+            true => 0,
+            false => text.len(),
+        };
+        let mut failures = Failures::new(self.pieces.len(), text.len());
+        (from..=last_start).find_map(|start| {
+            self.match_at(text, start, 0, &mut failures)
+                .map(|end| (start, end))
+        })
+    }
+
+    // End of a match of the pieces from `piece` on, starting at `position`, preferring longer
+    // repetitions.
+    fn match_at(
+        &self,
+        text: &[char],
+        position: usize,
+        piece: usize,
+        failures: &mut Failures,
+    ) -> Option<usize> {
+        let Some(current) = self.pieces.get(piece) else {
+            return match !self.anchored_end || position == text.len() {
+                true => Some(position),
+                false => None,
+            };
+        };
+        if failures.contains(piece, position) {
+            return None;
+        }
+        let available = text[position..]
+            .iter()
+            .take(current.max)
+            .take_while(|&&c| current.atom.matches(c))
+            .count();
+        let end = match available < current.min {
+            true => None,
+            false => (current.min..=available)
+                .rev()
+                .find_map(|count| self.match_at(text, position + count, piece + 1, failures)),
+        };
+        if end.is_none() {
+            failures.insert(piece, position);
+        }
+        end
+    }
+}
+
+// Pieces which failed to match from a position. Whether they match depends on nothing else,
+// so each pair is tried once, and matching stays polynomial where backtracking would explode
+// on patterns like `a*a*a*b`.
+struct Failures {
+    positions: usize,
+    failed: Vec<bool>,
+}
+
+impl Failures {
+    fn new(pieces: usize, text_len: usize) -> Self {
+        let positions = text_len + 1;
+        Self {
+            positions,
+            failed: vec![false; pieces * positions],
+        }
+    }
+
+    fn contains(&self, piece: usize, position: usize) -> bool {
+        self.failed[piece * self.positions + position]
+    }
+
+    fn insert(&mut self, piece: usize, position: usize) {
+        self.failed[piece * self.positions + position] = true;
+    }
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(expected) => c == *expected,
+            Atom::Class { negated, items } => items.iter().any(|item| item.matches(c)) != *negated,
+        }
+    }
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(expected) => c == *expected,
+            ClassItem::Range(low, high) => (*low..=*high).contains(&c),
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => c.is_alphanumeric() || c == '_',
+            ClassItem::Space => c.is_whitespace(),
+        }
+    }
+}
+
+// Whether the last character is escaped by an odd number of backslashes before it.
+fn ends_escaped(chars: &[char]) -> bool {
+    let backslashes = chars[..chars.len() - 1]
+        .iter()
+        .rev()
+        .take_while(|&&c| c == '\\')
+        .count();
+    backslashes % 2 == 1
+}
+
+fn escape(c: char) -> Atom {
+    let item = match c {
+        'd' => ClassItem::Digit,
+        'w' => ClassItem::Word,
+        's' => ClassItem::Space,
+        c => return Atom::Char(c),
+    };
+    Atom::Class {
+        negated: false,
+        items: vec![item],
+    }
+}
+
+// Parses the class whose `[` precedes `index`, leaving `index` after its `]`.
+fn class(chars: &[char], index: &mut usize) -> Result<Atom, String> {
+    let negated = chars.get(*index) == Some(&'^');
+    if negated {
+        *index += 1;
+    }
+    let mut items = Vec::new();
+    loop {
+        let Some(&c) = chars.get(*index) else {
+            return Err("`[` in a pattern is never closed".into());
+        };
+        *index += 1;
+        let item = match c {
+            ']' if !items.is_empty() => break,
+            '\\' => {
+                let Some(&escaped) = chars.get(*index) else {
+                    return Err("`[` in a pattern is never closed".into());
+                };
+                *index += 1;
+                match escape(escaped) {
+                    Atom::Class { mut items, .. } => items.remove(0),
+                    _ => ClassItem::Char(escaped),
+                }
+            }
+            low => match (chars.get(*index), chars.get(*index + 1)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    if high < low {
+                        return Err(format!("range `{}-{}` in a pattern is reversed", low, high));
+                    }
+                    *index += 2;
+                    ClassItem::Range(low, high)
+                }
+                _ => ClassItem::Char(low),
+            },
+        };
+        items.push(item);
+    }
+    Ok(Atom::Class { negated, items })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        let text: Vec<_> = text.chars().collect();
+        Pattern::parse(pattern).unwrap().find(&text, 0)
+    }
+
+    fn parse_error(pattern: &str) -> String {
+        match Pattern::parse(pattern) {
+            Ok(_) => panic!("{:?} parses", pattern),
+            Err(message) => message,
+        }
+    }
+
+    #[test]
+    fn finds_the_leftmost_longest_match() {
+        assert_eq!(find("b+", "abbbc"), Some((1, 4)));
+        assert_eq!(find("a.?c", "xabc"), Some((1, 4)));
+        assert_eq!(find("[^a-c]\\d", "ab9x7"), Some((3, 5)));
+        assert_eq!(find("^b", "ab"), None);
+        assert_eq!(find("b$", "bab"), Some((2, 3)));
+        assert_eq!(find("a*", ""), Some((0, 0)));
+    }
+
+    #[test]
+    fn nested_repetitions_do_not_backtrack_exponentially() {
+        let text = "a".repeat(40);
+        let started = Instant::now();
+        assert_eq!(find("a*a*a*a*a*a*a*a*b", &text), None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn malformed_patterns_are_rejected() {
+        assert_eq!(parse_error("ab\\"), "pattern ends with a lone `\\`");
+        assert_eq!(parse_error("*a"), "`*` in a pattern has nothing to repeat");
+        assert_eq!(parse_error("a+?"), "`?` in a pattern has nothing to repeat");
+        assert_eq!(parse_error("[ab"), "`[` in a pattern is never closed");
+        assert_eq!(parse_error("[a\\"), "`[` in a pattern is never closed");
+        assert_eq!(parse_error("[]"), "`[` in a pattern is never closed");
+        assert_eq!(parse_error("[z-a]"), "range `z-a` in a pattern is reversed");
+    }
+}
@@ -0,0 +1,46 @@
+/// The pseudo-random generator behind the `random`, `randomInt` and `seed` natives. It is
+/// SplitMix64, which is small and fast, and yields the same sequence for the same seed on every
+/// platform. It is not fit for cryptography.
+#[derive(Clone, Debug)]
+pub(super) struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// The seed of a VM whose scripts haven't called `seed`, so runs are reproducible anyway.
+    pub const DEFAULT_SEED: u64 = 0x5EED_5EED;
+
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`, with all 53 bits of the mantissa random.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A whole number in `[low, high]`, which must not be empty.
+    pub fn next_in(&mut self, low: i64, high: i64) -> i64 {
+        let span = high.wrapping_sub(low) as u64;
+        if span == u64::MAX {
+            return self.next_u64() as i64;
+        }
+        // Rejects the top values which would make some results more likely than others.
+        let range = span + 1;
+        let zone = u64::MAX - (u64::MAX - range + 1) % range;
+        loop {
+            let value = self.next_u64();
+            if value <= zone {
+                return low.wrapping_add((value % range) as i64);
+            }
+        }
+    }
+}
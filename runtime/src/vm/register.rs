@@ -0,0 +1,307 @@
+use std::io::Write;
+
+use shared::{
+    constant::Constant,
+    error::{ErrorItem, InterpretError, InterpretResult, Label},
+    register::{RegisterChunk, RegisterInstruction},
+};
+
+use crate::{
+    object::{Downcast, NativeObject, ObjectType},
+    value::Value,
+};
+
+use super::VirtualMachine;
+
+impl VirtualMachine {
+    /// Runs a chunk of the experimental register machine.
+    ///
+    /// The register window is carved out of the top of the VM stack, so registers are marked
+    /// as roots by the collector just like stack slots are.
+    pub fn interpret_register(&mut self, chunk: RegisterChunk) -> InterpretResult {
+        let base = self.stack.len();
+        for _ in 0..chunk.register_count {
+            self.stack.push(Value::Nil)?;
+        }
+        self.offset = 0;
+        let result = self.run_register(&chunk, base);
+        self.stack.truncate(base);
+        result
+    }
+
+    fn run_register(&mut self, chunk: &RegisterChunk, base: usize) -> InterpretResult {
+        macro_rules! report {
+            ($code:expr, $message:expr, $label:expr) => {
+                return Err(InterpretError::Simple(
+                    ErrorItem::error()
+                        .with_code($code)
+                        .with_message($message)
+                        .with_labels(vec![Label::secondary(
+                            chunk.file_id,
+                            chunk.positions[self.offset].clone(),
+                        )
+                        .with_message($label)]),
+                ))
+            };
+
+            ($code:expr, $message:expr) => {
+                report!($code, $message, "error originated within this statement")
+            };
+        }
+
+        macro_rules! register {
+            ($register:expr) => {
+                self.stack[base + *$register as usize]
+            };
+        }
+
+        macro_rules! global_name {
+            ($index:expr) => {
+                match &chunk.constants[*$index as usize] {
+                    Constant::String(name) => name,
+                    _ => report!("E1006", "invalid name of global definition"),
+                }
+            };
+        }
+
+        // Collects garbage once the heap is over its limit, and fails if that isn't enough.
+        // Natives allocate on their own, so their results are checked as well.
+        macro_rules! within_limit {
+            ($value:expr) => {{
+                let value = $value;
+                if self.heap.is_exhausted() {
+                    self.collect_garbage(Some(&value));
+                    if self.heap.is_exhausted() {
+                        report!(
+                            "E1014",
+                            "out of memory",
+                            "allocation within this statement exceeds the heap limit"
+                        );
+                    }
+                }
+                value
+            }};
+        }
+
+        macro_rules! allocate {
+            ($reference:expr) => {
+                within_limit!(Value::Object($reference))
+            };
+        }
+
+        // In strict arithmetic mode, a NaN result is an error.
+        macro_rules! check_nan {
+            ($destination:expr) => {
+                if self.strict_arithmetic && register!($destination).is_nan() {
+                    report!(
+                        "E1021",
+                        "arithmetic produced NaN",
+                        "arithmetic operation within this statement"
+                    );
+                }
+            };
+        }
+
+        macro_rules! arithmetic {
+            ($destination:expr, $left:expr, $right:expr, $operation:ident) => {{
+                match register!($left).$operation(&register!($right)) {
+                    Some(result) => register!($destination) = result,
+                    None => report!(
+                        "E1003",
+                        "operands must be numbers",
+                        "arithmetic operation within this statement"
+                    ),
+                }
+            }};
+        }
+
+        loop {
+            if self.heap.should_collect() {
+                self.collect_garbage(None);
+            }
+
+            match &chunk.code[self.offset] {
+                // Loads and moves.
+                RegisterInstruction::LoadConstant(destination, index) => {
+                    register!(destination) = match &chunk.constants[*index as usize] {
+                        Constant::Int(int) => Value::Int(*int),
+                        Constant::Number(number) => Value::Number(*number),
+                        Constant::String(string) => allocate!(self.heap.manage_string(string)),
+                        Constant::Boolean(boolean) => Value::Boolean(*boolean),
+                        Constant::Nil => Value::Nil,
+                    };
+                }
+                RegisterInstruction::LoadNil(destination) => register!(destination) = Value::Nil,
+                RegisterInstruction::LoadTrue(destination) => {
+                    register!(destination) = Value::Boolean(true)
+                }
+                RegisterInstruction::LoadFalse(destination) => {
+                    register!(destination) = Value::Boolean(false)
+                }
+                RegisterInstruction::Move(destination, source) => {
+                    register!(destination) = register!(source).clone()
+                }
+
+                // Globals.
+                RegisterInstruction::DefineGlobal(index, source) => {
+                    let name = global_name!(index);
+                    self.globals.define(name, register!(source).clone());
+                }
+                RegisterInstruction::GetGlobal(destination, index) => {
+                    let name = global_name!(index);
+                    register!(destination) = match self.globals.resolve(name) {
+                        Some(slot) => self.globals.get(slot).clone(),
+                        None => report!("E1008", format!("undefined global {}", name)),
+                    };
+                }
+                RegisterInstruction::SetGlobal(index, source) => {
+                    let name = global_name!(index);
+                    match self.globals.resolve(name) {
+                        Some(slot) => self.globals.set(slot, register!(source).clone()),
+                        None => report!("E1008", format!("undefined global {}", name)),
+                    }
+                }
+
+                // Arithmetic instructions.
+                RegisterInstruction::Add(destination, left, right) => {
+                    let (left, right) = (&register!(left), &register!(right));
+                    match left.add(right) {
+                        Some(sum) => {
+                            register!(destination) = sum;
+                            check_nan!(destination);
+                        }
+                        None => {
+                            // Like the stack VM, strings take any other value unless strictly
+                            // compatible with Lox.
+                            let concatenable = match (left.as_str(), right.as_str()) {
+                                (Some(_), Some(_)) => true,
+                                (Some(_), None) | (None, Some(_)) => !self.strict_compat,
+                                (None, None) => false,
+                            };
+                            if !concatenable {
+                                report!(
+                                    "E1005",
+                                    "concatenation operands must be both numbers or both strings."
+                                );
+                            }
+                            let concat = format!("{}{}", left, right);
+                            register!(destination) = allocate!(self.heap.manage_string(&concat));
+                        }
+                    }
+                }
+                RegisterInstruction::Subtract(destination, left, right) => {
+                    arithmetic!(destination, left, right, subtract);
+                    check_nan!(destination);
+                }
+                RegisterInstruction::Multiply(destination, left, right) => {
+                    arithmetic!(destination, left, right, multiply);
+                    check_nan!(destination);
+                }
+                RegisterInstruction::Divide(destination, left, right) => {
+                    if self.strict_arithmetic && register!(right).is_zero() {
+                        report!(
+                            "E1020",
+                            "division by zero",
+                            "division within this statement"
+                        );
+                    }
+                    arithmetic!(destination, left, right, divide);
+                    check_nan!(destination);
+                }
+                RegisterInstruction::Negate(destination, operand) => {
+                    match register!(operand).negate() {
+                        Some(negated) => register!(destination) = negated,
+                        None => report!(
+                            "E1004",
+                            "operand must be number",
+                            "arithmetic negation within this statement"
+                        ),
+                    }
+                }
+
+                // Logic instructions.
+                RegisterInstruction::Not(destination, operand) => {
+                    register!(destination) = Value::Boolean(!register!(operand).as_bool())
+                }
+                RegisterInstruction::Equal(destination, left, right) => {
+                    register!(destination) = Value::Boolean(register!(left) == register!(right))
+                }
+                RegisterInstruction::Greater(destination, left, right) => {
+                    arithmetic!(destination, left, right, greater)
+                }
+                RegisterInstruction::Less(destination, left, right) => {
+                    arithmetic!(destination, left, right, less)
+                }
+
+                // Control flow. The offset is increased by 1 every loop.
+                RegisterInstruction::Jump(target) => {
+                    self.offset = *target as usize;
+                    continue;
+                }
+                RegisterInstruction::JumpFalse(condition, target) => {
+                    if !register!(condition).as_bool() {
+                        self.offset = *target as usize;
+                        continue;
+                    }
+                }
+                RegisterInstruction::JumpTrue(condition, target) => {
+                    if register!(condition).as_bool() {
+                        self.offset = *target as usize;
+                        continue;
+                    }
+                }
+
+                RegisterInstruction::Call(callee, argc) => {
+                    let argc = *argc as usize;
+                    let (arity, native) = match &register!(callee) {
+                        Value::Object(reference) => match reference.typ {
+                            ObjectType::Native => {
+                                let native: &NativeObject = reference.downcast().unwrap();
+                                if let Some(capability) = native
+                                    .requires
+                                    .filter(|capability| !self.capabilities.allows(*capability))
+                                {
+                                    report!(
+                                        "E1018",
+                                        format!(
+                                            "`{}` needs the {} capability, which is disabled",
+                                            native.name, capability
+                                        )
+                                    );
+                                }
+                                (native.arity, reference.clone())
+                            }
+                            _ => report!("E1015", "can only call functions"),
+                        },
+                        _ => report!("E1015", "can only call functions"),
+                    };
+                    if !arity.accepts(argc) {
+                        report!(
+                            "E1016",
+                            format!("expected {} arguments but got {}", arity, argc)
+                        );
+                    }
+                    let first = base + *callee as usize + 1;
+                    let arguments: Vec<Value> =
+                        self.stack.iter().skip(first).take(argc).cloned().collect();
+                    let result = match self.call_native(native, &arguments) {
+                        Ok(result) => result,
+                        Err(message) => {
+                            report!("E1017", message, "native call within this statement")
+                        }
+                    };
+                    register!(callee) = within_limit!(result);
+                }
+
+                // Miscellaneous.
+                RegisterInstruction::Print(source) => {
+                    if let Err(error) = writeln!(self.output, "{}", register!(source)) {
+                        report!("E1019", format!("failed to write output: {}", error));
+                    }
+                }
+                RegisterInstruction::Return => return Ok(()),
+            }
+            self.offset += 1;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use shared::{chunk::Chunk, error::InterpretResult};
+
+use crate::value::OwnedValue;
+
+use super::{Capabilities, VirtualMachine};
+
+/// A [`VirtualMachine`] which can be moved across threads.
+///
+/// Managed references are raw pointers into the heap owned by the VM itself. As long as no
+/// value escapes the machine, moving the whole machine to another thread moves every object
+/// along with it, which is why this wrapper only exposes the chunk-in, diagnostics-out API and
+/// globals copied in and out as [`OwnedValue`]s.
+pub struct SyncVirtualMachine(VirtualMachine);
+
+// SAFETY: what keeps `VirtualMachine` from being `Send` is shared ownership that isn't atomic:
+// the heap's raw pointers, the `Rc` of every `NativeFunction`, the `Rc<str>` of long strings
+// in `Characters::Shared`, and the `Box<dyn Write>` output along with the other boxed hooks.
+// All of them are created by `VirtualMachine::new` and never leave it, since this wrapper
+// neither hands out `Value`s nor accepts natives, streams or instruments. No other thread holds
+// a clone or a pointer, so the machine moves as a whole. Methods added here must keep it so.
+unsafe impl Send for SyncVirtualMachine {}
+
+impl Default for SyncVirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncVirtualMachine {
+    pub fn new() -> Self {
+        Self(VirtualMachine::new())
+    }
+
+    pub fn interpret(&mut self, chunk: Chunk) -> InterpretResult {
+        self.0.interpret(chunk)
+    }
+
+    pub fn clear_stack(&mut self) {
+        self.0.clear_stack();
+    }
+
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.0.set_memory_limit(limit);
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.0.set_args(args);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<OwnedValue> {
+        self.0.get_global(name)
+    }
+
+    pub fn set_global(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<OwnedValue>,
+    ) -> Result<(), String> {
+        self.0.set_global(name, value)
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.0.set_capabilities(capabilities);
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.0.set_trace(trace);
+    }
+
+    pub fn set_stack_size(&mut self, size: usize) {
+        self.0.set_stack_size(size);
+    }
+}
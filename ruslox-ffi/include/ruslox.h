@@ -0,0 +1,53 @@
+/* C interface to the Ruslox interpreter, implemented by the ruslox-ffi crate.
+ *
+ * Every function takes an interpreter created by ruslox_new. An interpreter must be used from
+ * one thread at a time and released with ruslox_free. Strings are NUL-terminated UTF-8.
+ *
+ * Should the interpreter panic, the call fails as any other would, and ruslox_last_error tells
+ * why. Nothing unwinds into the caller. */
+
+#ifndef RUSLOX_H
+#define RUSLOX_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RUSLOX_OK 0
+#define RUSLOX_ERROR 1
+
+typedef struct RusloxInterpreter RusloxInterpreter;
+
+/* Creates an interpreter. Globals live on from one evaluation to the next. */
+RusloxInterpreter *ruslox_new(void);
+
+/* Releases an interpreter. Passing NULL does nothing. */
+void ruslox_free(RusloxInterpreter *lox);
+
+/* Compiles and runs the source. Returns RUSLOX_ERROR if it fails to, with the diagnostics
+ * available from ruslox_last_error. */
+int ruslox_eval(RusloxInterpreter *lox, const char *source);
+
+/* Stores the global into value if it is a number. Returns RUSLOX_ERROR otherwise, leaving
+ * value untouched. */
+int ruslox_get_global_number(RusloxInterpreter *lox, const char *name, double *value);
+
+/* The global if it is a string, or NULL otherwise. Release it with ruslox_string_free.
+ *
+ * Lox strings may contain NUL characters, which can't appear within a C string. Each is
+ * replaced by the two characters \0, so the result can't tell them from a backslash followed
+ * by a zero in the Lox string. */
+char *ruslox_get_global_string(RusloxInterpreter *lox, const char *name);
+
+/* Releases a string returned by the interpreter. Passing NULL does nothing. */
+void ruslox_string_free(char *string);
+
+/* The error from the last call if it failed, or NULL. The string belongs to the interpreter
+ * and stays valid until the next call. */
+const char *ruslox_last_error(const RusloxInterpreter *lox);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUSLOX_H */
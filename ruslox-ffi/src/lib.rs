@@ -0,0 +1,292 @@
+//! A C ABI over [`ruslox::Ruslox`], for hosts written in other languages. The declarations are
+//! in `include/ruslox.h`.
+//!
+//! Every function takes the interpreter created by [`ruslox_new`]. An interpreter must be used
+//! from one thread at a time and released with [`ruslox_free`]. Strings passed in are
+//! NUL-terminated UTF-8.
+//!
+//! A panic inside the interpreter never unwinds into the host: the call fails instead, with the
+//! panic message as its error.
+
+use std::{
+    any::Any,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use ruslox::Value;
+
+pub const RUSLOX_OK: c_int = 0;
+pub const RUSLOX_ERROR: c_int = 1;
+
+pub struct RusloxInterpreter {
+    lox: ruslox::Ruslox,
+    // Message of the error from the last call which failed, until the next call.
+    last_error: Option<CString>,
+}
+
+impl RusloxInterpreter {
+    fn fail(&mut self, message: impl Into<String>) -> c_int {
+        self.last_error = Some(c_string(message.into()));
+        RUSLOX_ERROR
+    }
+
+    // Runs the body of a call, returning `failed` if it panics.
+    fn guard<T>(&mut self, failed: T, body: impl FnOnce(&mut Self) -> T) -> T {
+        self.last_error = None;
+        match panic::catch_unwind(AssertUnwindSafe(|| body(self))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.fail(format!(
+                    "the interpreter panicked: {}",
+                    panic_message(&*payload)
+                ));
+                failed
+            }
+        }
+    }
+}
+
+/// Creates an interpreter. Globals live on from one evaluation to the next.
+#[no_mangle]
+pub extern "C" fn ruslox_new() -> *mut RusloxInterpreter {
+    Box::into_raw(Box::new(RusloxInterpreter {
+        lox: ruslox::Ruslox::new(),
+        last_error: None,
+    }))
+}
+
+/// Releases an interpreter. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// `lox` must come from [`ruslox_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_free(lox: *mut RusloxInterpreter) {
+    if !lox.is_null() {
+        drop(Box::from_raw(lox));
+    }
+}
+
+/// Compiles and runs the source. Returns `RUSLOX_ERROR` if it fails to, with the diagnostics
+/// available from [`ruslox_last_error`].
+///
+/// # Safety
+///
+/// `lox` must be a live interpreter and `source` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_eval(lox: *mut RusloxInterpreter, source: *const c_char) -> c_int {
+    (*lox).guard(RUSLOX_ERROR, |lox| {
+        let source = match CStr::from_ptr(source).to_str() {
+            Ok(source) => source,
+            Err(_) => return lox.fail("source is not valid UTF-8"),
+        };
+        match lox.lox.eval(source) {
+            Ok(_) => RUSLOX_OK,
+            Err(error) => lox.fail(error.to_string()),
+        }
+    })
+}
+
+/// Stores the global `name` into `value` if it is a number. Returns `RUSLOX_ERROR` otherwise,
+/// leaving `value` untouched.
+///
+/// # Safety
+///
+/// `lox` must be a live interpreter, `name` a NUL-terminated string and `value` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_get_global_number(
+    lox: *mut RusloxInterpreter,
+    name: *const c_char,
+    value: *mut f64,
+) -> c_int {
+    (*lox).guard(RUSLOX_ERROR, |lox| match global(lox, name) {
+        Ok(Value::Int(int)) => {
+            *value = int as f64;
+            RUSLOX_OK
+        }
+        Ok(Value::Number(number)) => {
+            *value = number;
+            RUSLOX_OK
+        }
+        Ok(other) => lox.fail(format!("global is {}, not a number", other.repr())),
+        Err(message) => lox.fail(message),
+    })
+}
+
+/// The global `name` if it is a string, or NULL otherwise. The caller owns the string and
+/// releases it with [`ruslox_string_free`]. NUL characters come out as the two characters
+/// `\0`, as documented in `ruslox.h`.
+///
+/// # Safety
+///
+/// `lox` must be a live interpreter and `name` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_get_global_string(
+    lox: *mut RusloxInterpreter,
+    name: *const c_char,
+) -> *mut c_char {
+    (*lox).guard(ptr::null_mut(), |lox| match global(lox, name) {
+        Ok(Value::String(string)) => c_string(string).into_raw(),
+        Ok(other) => {
+            lox.fail(format!("global is {}, not a string", other.repr()));
+            ptr::null_mut()
+        }
+        Err(message) => {
+            lox.fail(message);
+            ptr::null_mut()
+        }
+    })
+}
+
+/// Releases a string returned by the interpreter. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// `string` must come from [`ruslox_get_global_string`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// The error from the last call if it failed, or NULL. The string belongs to the interpreter
+/// and stays valid until the next call.
+///
+/// # Safety
+///
+/// `lox` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn ruslox_last_error(lox: *const RusloxInterpreter) -> *const c_char {
+    match &(*lox).last_error {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+unsafe fn global(lox: &RusloxInterpreter, name: *const c_char) -> Result<Value, String> {
+    let name = CStr::from_ptr(name)
+        .to_str()
+        .map_err(|_| "name is not valid UTF-8".to_string())?;
+    lox.lox
+        .get_global(name)
+        .ok_or_else(|| format!("undefined global `{}`", name))
+}
+
+// The message `panic!` was given, which is either a `&str` or a `String`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown cause", String::as_str),
+    }
+}
+
+// Lox strings may hold NUL, which C strings can't. The rewrite is part of the C interface.
+fn c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "\\0")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Calls through the C interface the way a host does, releasing the interpreter afterwards.
+    fn with_interpreter(test: impl FnOnce(*mut RusloxInterpreter)) {
+        let lox = ruslox_new();
+        test(lox);
+        unsafe { ruslox_free(lox) };
+    }
+
+    fn c(string: &str) -> CString {
+        CString::new(string).unwrap()
+    }
+
+    unsafe fn last_error(lox: *mut RusloxInterpreter) -> Option<String> {
+        let error = ruslox_last_error(lox);
+        (!error.is_null()).then(|| CStr::from_ptr(error).to_string_lossy().into_owned())
+    }
+
+    unsafe fn global_string(lox: *mut RusloxInterpreter, name: &str) -> Option<String> {
+        let string = ruslox_get_global_string(lox, c(name).as_ptr());
+        if string.is_null() {
+            return None;
+        }
+        let copied = CStr::from_ptr(string).to_str().unwrap().to_string();
+        ruslox_string_free(string);
+        Some(copied)
+    }
+
+    #[test]
+    fn globals_are_read_after_evaluation() {
+        with_interpreter(|lox| unsafe {
+            let source = c("var n = 1.5; var i = 2; var s = \"ab\" + \"c\";");
+            assert_eq!(ruslox_eval(lox, source.as_ptr()), RUSLOX_OK);
+            assert_eq!(last_error(lox), None);
+
+            let mut value = 0.0;
+            assert_eq!(
+                ruslox_get_global_number(lox, c("n").as_ptr(), &mut value),
+                RUSLOX_OK
+            );
+            assert_eq!(value, 1.5);
+            assert_eq!(
+                ruslox_get_global_number(lox, c("i").as_ptr(), &mut value),
+                RUSLOX_OK
+            );
+            assert_eq!(value, 2.0);
+            assert_eq!(global_string(lox, "s").as_deref(), Some("abc"));
+        });
+    }
+
+    #[test]
+    fn failures_leave_a_message_until_the_next_call() {
+        with_interpreter(|lox| unsafe {
+            assert_eq!(ruslox_eval(lox, c("print nil + 1;").as_ptr()), RUSLOX_ERROR);
+            assert!(last_error(lox).is_some());
+
+            let mut value = 7.0;
+            let status = ruslox_get_global_number(lox, c("missing").as_ptr(), &mut value);
+            assert_eq!(status, RUSLOX_ERROR);
+            assert_eq!(value, 7.0);
+            assert_eq!(
+                last_error(lox).as_deref(),
+                Some("undefined global `missing`")
+            );
+
+            assert_eq!(ruslox_eval(lox, c("var s = 1;").as_ptr()), RUSLOX_OK);
+            assert_eq!(last_error(lox), None);
+            assert_eq!(global_string(lox, "s"), None);
+            assert_eq!(
+                last_error(lox).as_deref(),
+                Some("global is 1, not a string")
+            );
+        });
+    }
+
+    #[test]
+    fn nul_characters_come_out_escaped() {
+        with_interpreter(|lox| unsafe {
+            (*lox).lox.set_global("s", "a\0b").unwrap();
+            assert_eq!(global_string(lox, "s").as_deref(), Some("a\\0b"));
+        });
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        with_interpreter(|lox| unsafe {
+            (*lox)
+                .lox
+                .vm()
+                .define_native("boom", 0, |_, _| panic!("native went boom"));
+            assert_eq!(ruslox_eval(lox, c("boom();").as_ptr()), RUSLOX_ERROR);
+            assert_eq!(
+                last_error(lox).as_deref(),
+                Some("the interpreter panicked: native went boom")
+            );
+        });
+    }
+}
@@ -0,0 +1,62 @@
+//! Times each stage of the frontend on the programs under `benches/programs`. Results are
+//! grouped by stage and labelled by frontend, so a rewrite of the scanner or the parser can be
+//! compared stage by stage against the PEG one.
+
+use compiler::LintRules;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const PROGRAMS: [(&str, &str); 4] = [
+    ("loops", include_str!("programs/loops.lox")),
+    ("fibonacci", include_str!("programs/fibonacci.lox")),
+    ("strings", include_str!("programs/strings.lox")),
+    ("branches", include_str!("programs/branches.lox")),
+];
+
+// Too many constants to compile as one chunk, but fine to scan and parse.
+fn large_program() -> String {
+    PROGRAMS.map(|(_, source)| source).concat().repeat(25)
+}
+
+fn inputs() -> Vec<(String, String)> {
+    let mut inputs: Vec<_> = PROGRAMS
+        .iter()
+        .map(|(name, source)| (name.to_string(), source.to_string()))
+        .collect();
+    inputs.push(("large".into(), large_program()));
+    inputs
+}
+
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    for (name, source) in inputs() {
+        group.bench_with_input(BenchmarkId::new("peg", name), &source, |b, source| {
+            b.iter(|| compiler::scan(0, source).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in inputs() {
+        let scanned = compiler::scan(0, &source).unwrap();
+        group.bench_with_input(BenchmarkId::new("peg", name), &scanned, |b, scanned| {
+            b.iter(|| compiler::parse(0, scanned).unwrap().statements.len())
+        });
+    }
+    group.finish();
+}
+
+fn compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+    let rules = LintRules::none();
+    for (name, source) in PROGRAMS {
+        group.bench_with_input(BenchmarkId::new("peg", name), source, |b, source| {
+            b.iter(|| compiler::compile_with(0, source, &rules).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan, parse, compile);
+criterion_main!(benches);
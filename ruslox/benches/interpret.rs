@@ -0,0 +1,37 @@
+//! Times the VM running the hot loops under `benches/programs`. Compilation happens outside
+//! the measurement.
+
+use std::io;
+
+use compiler::{CompileOutput, LintRules};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use runtime::vm::VirtualMachine;
+
+const PROGRAMS: [(&str, &str); 4] = [
+    ("loops", include_str!("programs/loops.lox")),
+    ("fibonacci", include_str!("programs/fibonacci.lox")),
+    ("strings", include_str!("programs/strings.lox")),
+    ("branches", include_str!("programs/branches.lox")),
+];
+
+fn interpret(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpret");
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let CompileOutput { chunk, .. } =
+                        compiler::compile_with(0, source, &LintRules::none()).unwrap();
+                    let vm = VirtualMachine::builder().output(io::sink()).build();
+                    (vm, chunk)
+                },
+                |(mut vm, chunk)| vm.interpret(chunk).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpret);
+criterion_main!(benches);
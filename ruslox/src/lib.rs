@@ -0,0 +1,110 @@
+//! Embeds Ruslox into Rust applications, wiring the compiler and the VM together.
+//!
+//! ```
+//! let mut lox = ruslox::Ruslox::new();
+//! lox.eval("var answer = 40;").unwrap();
+//! assert_eq!(lox.eval("answer + 2;").unwrap(), ruslox::Value::Int(42));
+//! ```
+
+use compiler::{CompileOutput, LintRules};
+use runtime::vm::VirtualMachine;
+
+pub use runtime::value::OwnedValue as Value;
+pub use shared::{
+    error::{DiagnosticSink, ErrorItem, InterpretError, SourceFileManager, TerminalSink},
+    module::{FileModuleLoader, MemoryModuleLoader, ModuleLoader},
+};
+
+/// An interpreter whose globals live on from one evaluation to the next.
+pub struct Ruslox {
+    vm: VirtualMachine,
+    files: SourceFileManager<String, String>,
+    evaluations: usize,
+    modules: Box<dyn ModuleLoader>,
+}
+
+impl Default for Ruslox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ruslox {
+    pub fn new() -> Self {
+        Self::with_vm(VirtualMachine::new())
+    }
+
+    /// An interpreter running on a VM configured beforehand, such as one from
+    /// [`VirtualMachine::builder`].
+    pub fn with_vm(vm: VirtualMachine) -> Self {
+        Self {
+            vm,
+            files: SourceFileManager::new(),
+            evaluations: 0,
+            modules: Box::new(FileModuleLoader::new(".")),
+        }
+    }
+
+    /// Compiles and runs the source, returning the value of its final expression statement, or
+    /// nil if it doesn't end with one. Lints are not run.
+    pub fn eval(&mut self, source: impl AsRef<str>) -> Result<Value, InterpretError> {
+        self.evaluations += 1;
+        let name = format!("<eval {}>", self.evaluations);
+        self.eval_named(name, source.as_ref())
+    }
+
+    /// Sets where modules are loaded from. Modules are files under the working directory by
+    /// default.
+    pub fn set_module_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        self.modules = Box::new(loader);
+    }
+
+    /// Loads the module called `name` and evaluates it like [`Self::eval`], reporting errors
+    /// against the module name.
+    pub fn eval_module(&mut self, name: &str) -> Result<Value, InterpretError> {
+        let source = self.modules.load(name).map_err(|error| {
+            InterpretError::Simple(
+                ErrorItem::error()
+                    .with_message(format!("cannot load module `{}`: {}", name, error)),
+            )
+        })?;
+        self.eval_named(name.to_string(), &source)
+    }
+
+    fn eval_named(&mut self, name: String, source: &str) -> Result<Value, InterpretError> {
+        let file_id = self.files.add(name, source.to_string());
+
+        let CompileOutput { chunk, .. } =
+            compiler::compile_evaluation(file_id, source, &LintRules::none())?;
+        let result = self.vm.evaluate(chunk);
+        self.vm.clear_stack();
+        result
+    }
+
+    /// The value of a global, or `None` if no evaluation has defined it.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vm.get_global(name)
+    }
+
+    /// Defines a global for later evaluations to read. Fails for [`Value::Object`], which only
+    /// describes an object.
+    pub fn set_global(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<(), String> {
+        self.vm.set_global(name, value)
+    }
+
+    /// The sources evaluated so far, which errors refer to. Pass them to
+    /// [`InterpretError::emit`] to render an error, or to [`InterpretError::emit_to`] to hand
+    /// it to a [`DiagnosticSink`].
+    pub fn files(&self) -> &SourceFileManager<String, String> {
+        &self.files
+    }
+
+    /// The VM, for configuring it.
+    pub fn vm(&mut self) -> &mut VirtualMachine {
+        &mut self.vm
+    }
+}
@@ -0,0 +1,145 @@
+//! Runs scripts in-process under breakpoints, checking where the VM pauses, what it holds
+//! there and that resuming finishes the script.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    ops::Range,
+    rc::Rc,
+};
+
+use compiler::{LintRules, ReplSession};
+use runtime::{value::OwnedValue, vm::VirtualMachine};
+use shared::chunk::Chunk;
+
+const SCRIPT: &str = "var total = 0;
+{
+    var a = 1;
+    var b = 2;
+    print a + b;
+    total = a + b;
+}
+print total;
+";
+
+/// Where `print` writes during a test, so the output can be read back afterwards.
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Captured {
+    fn take(&self) -> String {
+        String::from_utf8(self.0.take()).unwrap()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn virtual_machine() -> (VirtualMachine, Captured) {
+    let output = Captured::default();
+    let vm = VirtualMachine::builder().output(output.clone()).build();
+    (vm, output)
+}
+
+fn compile(file_id: usize, source: &str) -> Chunk {
+    compiler::compile_with(file_id, source, &LintRules::none())
+        .unwrap()
+        .chunk
+}
+
+/// Byte range of the 1-based `line` of `source`, without its newline.
+fn line_span(source: &str, line: usize) -> Range<usize> {
+    let start: usize = source
+        .lines()
+        .take(line - 1)
+        .map(|line| line.len() + 1)
+        .sum();
+    let length = source.lines().nth(line - 1).unwrap().len();
+    start..start + length
+}
+
+#[test]
+fn pauses_before_the_statement_on_the_line() {
+    let (mut vm, output) = virtual_machine();
+    vm.set_breakpoint(0, line_span(SCRIPT, 5));
+    vm.interpret(compile(0, SCRIPT)).unwrap();
+
+    let paused = vm.paused().expect("the VM runs past the breakpoint");
+    assert_eq!(paused.file_id, 0);
+    assert_eq!(paused.span.start, SCRIPT.find("print a + b").unwrap());
+    assert_eq!(paused.stack, [OwnedValue::Int(1), OwnedValue::Int(2)]);
+    assert_eq!(paused.locals, paused.stack);
+    assert_eq!(output.take(), "");
+
+    vm.resume().unwrap();
+    assert_eq!(vm.paused(), None);
+    assert_eq!(output.take(), "3\n3\n");
+}
+
+#[test]
+fn pauses_once_per_run_of_a_statement() {
+    let source = "for (var i = 0; i < 3; i = i + 1) {\n    print i * 10;\n}\n";
+    let (mut vm, output) = virtual_machine();
+    vm.set_breakpoint(0, line_span(source, 2));
+    vm.interpret(compile(0, source)).unwrap();
+
+    // The statement spans several instructions, and resuming starts on the first of them.
+    let mut locals = Vec::new();
+    while let Some(paused) = vm.paused() {
+        locals.push(paused.locals);
+        vm.resume().unwrap();
+    }
+    assert_eq!(
+        locals,
+        [
+            [OwnedValue::Int(0)],
+            [OwnedValue::Int(1)],
+            [OwnedValue::Int(2)]
+        ]
+    );
+    assert_eq!(output.take(), "0\n10\n20\n");
+}
+
+#[test]
+fn cleared_breakpoints_no_longer_pause() {
+    let (mut vm, output) = virtual_machine();
+    vm.set_breakpoint(0, line_span(SCRIPT, 5));
+    assert!(vm.clear_breakpoint(0, line_span(SCRIPT, 5)));
+    assert!(!vm.clear_breakpoint(0, line_span(SCRIPT, 5)));
+    vm.set_breakpoint(1, line_span(SCRIPT, 5));
+    vm.interpret(compile(0, SCRIPT)).unwrap();
+    assert_eq!(vm.paused(), None);
+    assert_eq!(output.take(), "3\n3\n");
+}
+
+#[test]
+fn evaluating_a_paused_chunk_yields_nil_until_resumed() {
+    let mut session = ReplSession::new();
+    let (mut vm, _) = virtual_machine();
+    vm.set_breakpoint(0, 0..5);
+    let chunk = session.compile(0, "1 + 2").unwrap().chunk;
+    assert_eq!(vm.evaluate(chunk).unwrap(), OwnedValue::Nil);
+    assert!(vm.paused().is_some());
+    vm.resume().unwrap();
+    assert_eq!(vm.paused(), None);
+}
+
+#[test]
+fn interpreting_discards_the_paused_chunk() {
+    let (mut vm, output) = virtual_machine();
+    vm.set_breakpoint(0, line_span(SCRIPT, 5));
+    vm.interpret(compile(0, SCRIPT)).unwrap();
+    assert!(vm.paused().is_some());
+
+    vm.interpret(compile(1, "print \"other\";")).unwrap();
+    assert_eq!(vm.paused(), None);
+    vm.resume().unwrap();
+    assert_eq!(output.take(), "other\n");
+}
@@ -0,0 +1,63 @@
+//! Runs scripts in-process with capabilities taken away, checking that natives needing them
+//! fail with E1018 instead of reaching outside the VM.
+
+use compiler::LintRules;
+use runtime::{
+    value::Value,
+    vm::{Arity, Capabilities, Capability, VirtualMachine},
+};
+
+/// Runs the script in `vm`, returning the codes of the errors it fails with.
+fn run(vm: &mut VirtualMachine, source: &str) -> Vec<String> {
+    let output = compiler::compile_with(0, source, &LintRules::default()).unwrap();
+    match vm.interpret(output.chunk) {
+        Ok(()) => Vec::new(),
+        Err(error) => error
+            .diagnostics()
+            .iter()
+            .filter_map(|item| item.code.clone())
+            .collect(),
+    }
+}
+
+fn sandboxed() -> VirtualMachine {
+    VirtualMachine::builder()
+        .capabilities(Capabilities::none())
+        .build()
+}
+
+#[test]
+fn built_in_natives_need_their_capabilities() {
+    for call in ["clock();", "readLine();", "argc();", "arg(0);"] {
+        assert_eq!(run(&mut sandboxed(), call), ["E1018"], "{}", call);
+    }
+    assert_eq!(
+        run(&mut sandboxed(), "format(\"{0}\", 1);"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn host_natives_need_their_capabilities() {
+    let define = |vm: &mut VirtualMachine| {
+        vm.define_native_requiring(
+            "readFile",
+            Arity::Exactly(1),
+            Some(Capability::Filesystem),
+            |_, _| Ok(Value::Nil),
+        );
+    };
+    let mut vm = sandboxed();
+    define(&mut vm);
+    assert_eq!(run(&mut vm, "readFile(\"a.txt\");"), ["E1018"]);
+
+    let mut vm = VirtualMachine::new();
+    define(&mut vm);
+    assert_eq!(run(&mut vm, "readFile(\"a.txt\");"), Vec::<String>::new());
+
+    vm.set_capabilities(Capabilities {
+        filesystem: false,
+        ..Capabilities::all()
+    });
+    assert_eq!(run(&mut vm, "readFile(\"a.txt\");"), ["E1018"]);
+}
@@ -0,0 +1,242 @@
+//! Runs annotated Lox scripts in-process through `compiler::compile_with` and
+//! `VirtualMachine::interpret`, checking what they print and report.
+//!
+//! Two annotation styles are understood:
+//! - the one of this repository, as in `test/`: `// expect: <text>` for a printed line and
+//!   `// error: <code or message fragment>` for a diagnostic on the line of the comment;
+//! - the one of the craftinginterpreters suite: `// expect: <text>`, `// expect runtime error:
+//!   <message>`, and `// [line N] Error ...` or `// Error ...` for compile errors. Their
+//!   messages are worded for clox, so only the kind of failure is checked.
+//!
+//! Every script under `test/` must pass. Setting `LOX_TEST_SUITE` to the `test` directory of a
+//! craftinginterpreters checkout also measures conformance with the official suite, running
+//! the VM in strict compatibility. Ruslox implements only part of Lox, so that run reports how
+//! many scripts pass instead of failing.
+
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use compiler::{CompileOutput, LintRules};
+use runtime::vm::VirtualMachine;
+use shared::{
+    error::{ErrorItem, InterpretError, SourceFileManager},
+    line::LineIndex,
+};
+
+const EXPECT_MARKER: &str = "// expect: ";
+const ERROR_MARKER: &str = "// error: ";
+const RUNTIME_ERROR_MARKER: &str = "// expect runtime error: ";
+// Directories of the official suite which only apply to jlox.
+const JLOX_ONLY: [&str; 3] = ["benchmark", "expressions", "scanning"];
+
+#[test]
+fn repository_scripts() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test");
+    let scripts = discover(&root);
+    assert!(!scripts.is_empty(), "no scripts under {}", root.display());
+
+    let failures: Vec<_> = scripts
+        .iter()
+        .filter_map(|script| failure_report(script, Suite::Repository))
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn official_suite() {
+    let Some(root) = env::var_os("LOX_TEST_SUITE") else {
+        return;
+    };
+    let scripts = discover(Path::new(&root));
+    let failures: Vec<_> = scripts
+        .iter()
+        .filter_map(|script| failure_report(script, Suite::Official))
+        .collect();
+    for failure in &failures {
+        println!("{}", failure);
+    }
+    println!(
+        "{} of {} scripts of the official suite conform",
+        scripts.len() - failures.len(),
+        scripts.len()
+    );
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Suite {
+    Repository,
+    // Written against reference Lox, so Ruslox's extensions are turned off for it.
+    Official,
+}
+
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    // Line of the comment and the code or message fragment.
+    errors: Vec<(usize, String)>,
+    compile_error: bool,
+    runtime_error: bool,
+}
+
+/// Where `print` writes during a test, so the output can be read back afterwards.
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn discover(root: &Path) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                let name = path.file_name().unwrap().to_string_lossy();
+                if !JLOX_ONLY.contains(&name.as_ref()) {
+                    directories.push(path);
+                }
+            } else if path.extension().is_some_and(|extension| extension == "lox") {
+                scripts.push(path);
+            }
+        }
+    }
+    scripts.sort();
+    scripts
+}
+
+/// Runs one script, describing each way it fell short of its expectations, if any.
+fn failure_report(script: &Path, suite: Suite) -> Option<String> {
+    let failures = run_script(script, suite);
+    match failures.is_empty() {
+        true => None,
+        false => Some(format!(
+            "FAIL {}\n    {}",
+            script.display(),
+            failures.join("\n    ")
+        )),
+    }
+}
+
+fn run_script(script: &Path, suite: Suite) -> Vec<String> {
+    let source = match fs::read_to_string(script) {
+        Ok(source) => source,
+        Err(error) => return vec![format!("unreadable: {}", error)],
+    };
+    let expectations = expectations(&source);
+
+    let mut files = SourceFileManager::new();
+    let file_id = files.add(script.display().to_string(), source.as_str());
+    let output = Captured::default();
+    let mut vm = VirtualMachine::builder()
+        .output(output.clone())
+        .strict_compat(suite == Suite::Official)
+        .build();
+
+    let (compile_error, runtime_error) =
+        match compiler::compile_with(file_id, &source, &LintRules::none()) {
+            Ok(CompileOutput { chunk, .. }) => (None, vm.interpret(chunk).err()),
+            Err(error) => (Some(error), None),
+        };
+
+    let mut failures = Vec::new();
+    let printed = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    let printed: Vec<_> = printed.lines().collect();
+    for (index, expected) in expectations.output.iter().enumerate() {
+        match printed.get(index) {
+            Some(&actual) if actual == expected => {}
+            Some(actual) => failures.push(format!(
+                "output line {}: expected `{}`, got `{}`",
+                index + 1,
+                expected,
+                actual
+            )),
+            None => failures.push(format!(
+                "output line {}: expected `{}`, got nothing",
+                index + 1,
+                expected
+            )),
+        }
+    }
+    for unexpected in printed.iter().skip(expectations.output.len()) {
+        failures.push(format!("unexpected output `{}`", unexpected));
+    }
+
+    if expectations.compile_error && compile_error.is_none() {
+        failures.push("expected a compile error".into());
+    }
+    if expectations.runtime_error && runtime_error.is_none() {
+        failures.push("expected a runtime error".into());
+    }
+
+    let lines = LineIndex::new(&source);
+    let mut diagnostics: Vec<_> = compile_error
+        .iter()
+        .chain(&runtime_error)
+        .flat_map(InterpretError::diagnostics)
+        .collect();
+    for (line, text) in &expectations.errors {
+        match diagnostics
+            .iter()
+            .position(|diagnostic| matches(diagnostic, &lines, *line, text))
+        {
+            Some(index) => {
+                diagnostics.remove(index);
+            }
+            None => failures.push(format!("line {}: expected error `{}`", line, text)),
+        }
+    }
+    // Scripts of the official suite state the kind of failure, which was checked above.
+    if !(expectations.compile_error && compile_error.is_some()
+        || expectations.runtime_error && runtime_error.is_some())
+    {
+        for diagnostic in diagnostics {
+            failures.push(format!(
+                "unexpected error[{}]: {}",
+                diagnostic.code.as_deref().unwrap_or("?"),
+                diagnostic.message
+            ));
+        }
+    }
+    failures
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+    for (index, line) in source.lines().enumerate() {
+        if let Some((_, expected)) = line.split_once(EXPECT_MARKER) {
+            expectations.output.push(expected.trim_end().into());
+        } else if let Some((_, expected)) = line.split_once(ERROR_MARKER) {
+            expectations
+                .errors
+                .push((index + 1, expected.trim().into()));
+        } else if line.contains(RUNTIME_ERROR_MARKER) {
+            expectations.runtime_error = true;
+        } else if line.contains("// [line ") || line.contains("// Error") {
+            expectations.compile_error = true;
+        }
+    }
+    expectations
+}
+
+/// Whether the diagnostic has the code or a message containing `text`, and points at `line`.
+fn matches(diagnostic: &ErrorItem, lines: &LineIndex, line: usize, text: &str) -> bool {
+    let reported_line = diagnostic
+        .labels
+        .first()
+        .map(|label| lines.line_column(label.range.start).0);
+    reported_line == Some(line)
+        && (diagnostic.code.as_deref() == Some(text) || diagnostic.message.contains(text))
+}
@@ -0,0 +1,235 @@
+//! Drives the VM the way a host application does: defining natives, exchanging globals and
+//! configuring it, then running scripts in-process.
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Cursor, Write},
+    rc::Rc,
+    thread,
+};
+
+use compiler::LintRules;
+use runtime::{
+    value::{OwnedValue, Value},
+    vm::{SyncVirtualMachine, VirtualMachine},
+};
+
+/// Runs the script in `vm`, returning the codes of the errors it fails with.
+fn run(vm: &mut VirtualMachine, source: &str) -> Result<(), Vec<String>> {
+    let output = compiler::compile_with(0, source, &LintRules::none()).unwrap();
+    vm.interpret(output.chunk).map_err(|error| {
+        error
+            .diagnostics()
+            .iter()
+            .filter_map(|item| item.code.clone())
+            .collect()
+    })
+}
+
+fn double(_: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, String> {
+    match arguments[0].as_int() {
+        Some(int) => Ok(Value::Int(int * 2)),
+        None => Err("double takes a whole number".into()),
+    }
+}
+
+#[test]
+fn natives_are_called_with_their_arguments() {
+    let mut vm = VirtualMachine::new();
+    vm.define_native("double", 1, double);
+    vm.define_native("greet", 1, |vm, arguments| {
+        let greeting = format!("hello, {}", arguments[0].as_str().unwrap_or("stranger"));
+        Ok(vm.new_string(greeting))
+    });
+    run(&mut vm, "var d = double(21); var g = greet(\"host\");").unwrap();
+    assert_eq!(vm.get_global("d"), Some(OwnedValue::Int(42)));
+    assert_eq!(
+        vm.get_global("g"),
+        Some(OwnedValue::String("hello, host".into()))
+    );
+}
+
+#[test]
+fn natives_reject_other_numbers_of_arguments() {
+    let mut vm = VirtualMachine::new();
+    vm.define_native("double", 1, double);
+    assert_eq!(run(&mut vm, "double();"), Err(vec!["E1016".to_string()]));
+    assert_eq!(
+        run(&mut vm, "double(1, 2);"),
+        Err(vec!["E1016".to_string()])
+    );
+}
+
+#[test]
+fn errors_of_natives_are_reported() {
+    let mut vm = VirtualMachine::new();
+    vm.define_native("double", 1, double);
+    assert_eq!(
+        run(&mut vm, "double(\"x\");"),
+        Err(vec!["E1017".to_string()])
+    );
+}
+
+#[test]
+fn variadic_natives_take_any_number_beyond_the_minimum() {
+    let mut vm = VirtualMachine::new();
+    vm.define_variadic_native("sum", 1, |_, arguments| {
+        Ok(Value::Int(arguments.iter().filter_map(Value::as_int).sum()))
+    });
+    run(&mut vm, "var one = sum(1); var three = sum(1, 2, 3);").unwrap();
+    assert_eq!(vm.get_global("one"), Some(OwnedValue::Int(1)));
+    assert_eq!(vm.get_global("three"), Some(OwnedValue::Int(6)));
+    assert_eq!(run(&mut vm, "sum();"), Err(vec!["E1016".to_string()]));
+}
+
+#[test]
+fn globals_pass_between_host_and_script() {
+    let mut vm = VirtualMachine::new();
+    vm.set_global("count", 2).unwrap();
+    vm.set_global("name", "lox").unwrap();
+    vm.set_global("ratio", 0.5).unwrap();
+    vm.set_global("ready", true).unwrap();
+    vm.set_global("missing", OwnedValue::Nil).unwrap();
+    run(
+        &mut vm,
+        "var summary = name + \" \" + (count + ratio); ready = !ready and missing == nil;",
+    )
+    .unwrap();
+    assert_eq!(
+        vm.get_global("summary"),
+        Some(OwnedValue::String("lox 2.5".into()))
+    );
+    assert_eq!(vm.get_global("ready"), Some(OwnedValue::Boolean(false)));
+    assert_eq!(vm.get_global("undefined"), None);
+}
+
+#[test]
+fn globals_holding_objects_are_described_but_not_copied_in() {
+    let mut vm = VirtualMachine::new();
+    let native = vm.get_global("clock").unwrap();
+    assert!(matches!(native, OwnedValue::Object(_)), "{:?}", native);
+    assert!(vm.set_global("copy", native).is_err());
+    assert_eq!(vm.get_global("copy"), None);
+}
+
+/// A VM whose `open(n)` returns a foreign object holding `n`, counting the finalized ones.
+fn with_handles() -> (VirtualMachine, Rc<Cell<i64>>) {
+    let finalized = Rc::new(Cell::new(0));
+    let mut vm = VirtualMachine::new();
+    let counter = finalized.clone();
+    vm.define_native("open", 1, move |vm, arguments| {
+        let counter = counter.clone();
+        let handle = arguments[0].as_int().unwrap_or(0);
+        Ok(
+            vm.new_foreign_with_finalizer(handle, move |handle| {
+                counter.set(counter.get() + handle)
+            }),
+        )
+    });
+    vm.define_native("peek", 1, |_, arguments| {
+        match arguments[0].as_foreign::<i64>() {
+            Some(handle) => Ok(Value::Int(*handle)),
+            None => Err("peek takes a handle".into()),
+        }
+    });
+    (vm, finalized)
+}
+
+#[test]
+fn foreign_objects_hand_their_value_back_to_natives() {
+    let (mut vm, _) = with_handles();
+    run(&mut vm, "var peeked = peek(open(7));").unwrap();
+    assert_eq!(vm.get_global("peeked"), Some(OwnedValue::Int(7)));
+    assert_eq!(run(&mut vm, "peek(1);"), Err(vec!["E1017".to_string()]));
+}
+
+#[test]
+fn finalizers_run_when_foreign_objects_are_swept() {
+    let (mut vm, finalized) = with_handles();
+    run(&mut vm, "var kept = open(1); open(10); gc();").unwrap();
+    assert_eq!(finalized.get(), 10);
+    run(&mut vm, "kept = nil; gc();").unwrap();
+    assert_eq!(finalized.get(), 11);
+}
+
+#[test]
+fn finalizers_run_when_the_vm_is_dropped() {
+    let (mut vm, finalized) = with_handles();
+    run(&mut vm, "var kept = open(5);").unwrap();
+    assert_eq!(finalized.get(), 0);
+    drop(vm);
+    assert_eq!(finalized.get(), 5);
+}
+
+/// Where `print` writes during a test, so the output can be read back afterwards.
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn built_vms_read_and_write_the_streams_given() {
+    let output = Captured::default();
+    let mut vm = VirtualMachine::builder()
+        .input(Cursor::new("first\nsecond\n"))
+        .output(output.clone())
+        .build();
+    run(
+        &mut vm,
+        "print readLine(); print readLine(); print readLine();",
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output.0.take()).unwrap(),
+        "first\nsecond\nnil\n"
+    );
+}
+
+#[test]
+fn built_vms_with_the_same_seed_draw_the_same_numbers() {
+    let draws = |seed| {
+        let mut vm = VirtualMachine::builder().seed(seed).build();
+        run(&mut vm, "var a = randomInt(0, 1000000); var b = random();").unwrap();
+        (vm.get_global("a"), vm.get_global("b"))
+    };
+    assert_eq!(draws(42), draws(42));
+    assert_ne!(draws(42), draws(43));
+}
+
+#[test]
+fn built_vms_overflow_the_stack_size_given() {
+    let mut vm = VirtualMachine::builder().stack_size(2).build();
+    assert_eq!(
+        run(&mut vm, "{ var a = 1; var b = 2; var c = 3; }"),
+        Err(vec!["E1001".to_string()])
+    );
+    run(
+        &mut VirtualMachine::new(),
+        "{ var a = 1; var b = 2; var c = 3; }",
+    )
+    .unwrap();
+}
+
+#[test]
+fn sync_vms_run_on_other_threads() {
+    let mut vm = SyncVirtualMachine::new();
+    vm.set_global("base", 40).unwrap();
+    let chunk = compiler::compile_with(0, "var answer = base + 2;", &LintRules::none())
+        .unwrap()
+        .chunk;
+    let vm = thread::spawn(move || {
+        vm.interpret(chunk).unwrap();
+        vm
+    })
+    .join()
+    .unwrap();
+    assert_eq!(vm.get_global("answer"), Some(OwnedValue::Int(42)));
+}
@@ -0,0 +1,35 @@
+//! Runs scripts in-process under a small heap limit, checking that every way of allocating
+//! respects it, natives included.
+
+use compiler::LintRules;
+use runtime::vm::VirtualMachine;
+
+const LIMIT: usize = 64 * 1024;
+
+/// Runs the script under the limit, returning the codes of the errors it fails with.
+fn run_limited(source: &str) -> Vec<String> {
+    let output = compiler::compile_with(0, source, &LintRules::default()).unwrap();
+    let mut vm = VirtualMachine::new();
+    vm.set_memory_limit(Some(LIMIT));
+    match vm.interpret(output.chunk) {
+        Ok(()) => Vec::new(),
+        Err(error) => error
+            .diagnostics()
+            .iter()
+            .filter_map(|item| item.code.clone())
+            .collect(),
+    }
+}
+
+#[test]
+fn natives_growing_strings_run_out_of_memory() {
+    // The loop itself allocates nothing, not even the constant of the pattern.
+    let codes = run_limited("var f = \"{0}{0}\"; var s = \"x\"; while (true) s = format(f, s);");
+    assert_eq!(codes, ["E1014"]);
+}
+
+#[test]
+fn natives_leaving_garbage_are_collected() {
+    let codes = run_limited("for (var i = 0; i < 10000; i = i + 1) format(\"{0}\", i);");
+    assert!(codes.is_empty(), "{:?}", codes);
+}
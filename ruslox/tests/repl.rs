@@ -0,0 +1,50 @@
+//! Runs REPL entries in-process through `ReplSession::compile` and `VirtualMachine::evaluate`,
+//! the way the interactive prompt does, checking what each entry echoes.
+
+use compiler::{CompileOutput, ReplSession};
+use runtime::vm::VirtualMachine;
+
+/// Compiles and runs each entry in one session, returning what the prompt would show for it.
+fn echoes(entries: &[&str]) -> Vec<Option<String>> {
+    let mut session = ReplSession::new();
+    let mut vm = VirtualMachine::new();
+    entries
+        .iter()
+        .enumerate()
+        .map(|(file_id, entry)| {
+            let CompileOutput { chunk, .. } = session
+                .compile(file_id, entry)
+                .unwrap_or_else(|_| panic!("{:?} does not compile", entry));
+            let shown = match session.echoes() {
+                true => Some(vm.evaluate(chunk).unwrap().repr().to_string()),
+                false => {
+                    vm.interpret(chunk).unwrap();
+                    None
+                }
+            };
+            vm.clear_stack();
+            shown
+        })
+        .collect()
+}
+
+#[test]
+fn values_echo_as_literals() {
+    assert_eq!(
+        echoes(&["\"hi\"", "1 + 2", "nil", "\"a\" + \"b\";"]),
+        [
+            Some("\"hi\"".to_string()),
+            Some("3".to_string()),
+            Some("nil".to_string()),
+            Some("\"ab\"".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn statements_and_assignments_do_not_echo() {
+    assert_eq!(
+        echoes(&["var a = \"x\";", "a = \"y\"", "print a;", "a"]),
+        [None, None, None, Some("\"y\"".to_string())]
+    );
+}
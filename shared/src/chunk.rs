@@ -1,12 +1,25 @@
-use std::{fmt::Display, ops::Range};
+use std::{
+    fmt::{self, Display, Write},
+    ops::Range,
+};
 
-use crate::constant::Constant;
+use crate::{
+    constant::Constant,
+    error::{ErrorItem, InterpretError, InterpretResult, Label},
+    line::LineIndex,
+};
+
+mod serialize;
 
 #[rustfmt::skip]
 pub enum Instruction {
     // Instructions with operand.
     Constant(u8), DefineGlobal(u8), GetGlobal(u8), SetGlobal(u8),
     GetLocal(u8), SetLocal(u8), JumpFalse(u16), Jump(u16), Loop(u16),
+    Call(u8), PopN(u8),
+
+    // Superinstructions fused by the compiler.
+    AddConstant(u8), LessConstant(u8), GreaterConstant(u8), GetLocalAddConstant(u8, u8),
 
     // Literal instructions.
     Nil, True, False,
@@ -26,6 +39,14 @@ pub struct Chunk {
     pub code: Vec<Instruction>,
     pub positions: Vec<Range<usize>>,
     pub constants: Vec<Constant>,
+    pub lines: LineIndex,
+    /// What the code belongs to, such as `<script>`.
+    pub name: Option<String>,
+    /// [`Chunk::hash_source`] of the source the chunk was compiled from, if known.
+    pub source_hash: Option<u64>,
+    /// Format version of the bytecode, which is always [`Chunk::FORMAT_VERSION`] unless the
+    /// chunk was loaded from an older file.
+    pub version: u16,
 }
 
 macro_rules! register_backpatch {
@@ -33,20 +54,31 @@ macro_rules! register_backpatch {
         paste::paste! {
             $(
                 pub fn [<spare_ $instruction:lower>](&mut self, position: &Range<usize>) -> usize {
-                    let len = self.code.len();
-                    self.write(Instruction::$instruction(len as u16), position);
-                    len
+                    self.write(Instruction::$instruction(0), position);
+                    self.code.len() - 1
                 }
             )*
 
-            pub fn patch(&mut self, offset: usize) {
-                let len = self.code.len() as u16;
+            /// Points the jump at `offset` to the next instruction to be written.
+            pub fn patch(&mut self, offset: usize, position: &Range<usize>) -> InterpretResult {
+                let distance = match u16::try_from(self.code.len() - offset) {
+                    Ok(distance) => distance,
+                    Err(_) => {
+                        return Err(jump_error(
+                            self.file_id,
+                            position,
+                            "E0014",
+                            "too much code to jump over",
+                        ))
+                    }
+                };
                 match &mut self.code[offset] {
                     $(
-                        Instruction::$instruction(offset) => *offset = len - *offset,
+                        Instruction::$instruction(offset) => *offset = distance,
                     )*
                     _ => unreachable!("internal error when backpatch"),
                 }
+                Ok(())
             }
         }
     };
@@ -61,85 +93,224 @@ impl Chunk {
             code: Vec::new(),
             positions: Vec::new(),
             constants: Vec::with_capacity(u8::MAX as usize + 1),
+            lines: LineIndex::default(),
+            name: None,
+            source_hash: None,
+            version: Self::FORMAT_VERSION,
         }
     }
 
+    /// Records the source the chunk was compiled from: its lines, for positions, and its hash,
+    /// for telling whether the source changed since.
+    pub fn attach_source(&mut self, source: &str) {
+        self.lines = LineIndex::new(source);
+        self.source_hash = Some(Self::hash_source(source));
+    }
+
+    /// A hash of the source which stays the same across runs and builds of Ruslox, unlike the
+    /// hashers of the standard library. This is 64-bit FNV-1a.
+    pub fn hash_source(source: &str) -> u64 {
+        source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Whether the chunk was compiled from a different source than the one given.
+    pub fn is_stale(&self, source: &str) -> bool {
+        self.source_hash
+            .is_some_and(|hash| hash != Self::hash_source(source))
+    }
+
     pub fn write(&mut self, instruction: Instruction, position: &Range<usize>) {
         self.code.push(instruction);
         self.positions.push(position.clone());
     }
 
+    /// Writes a loop back to the instruction at `start`.
+    pub fn write_loop(&mut self, start: usize, position: &Range<usize>) -> InterpretResult {
+        match u16::try_from(self.code.len() - start) {
+            Ok(distance) => {
+                self.write(Instruction::Loop(distance), position);
+                Ok(())
+            }
+            Err(_) => Err(jump_error(
+                self.file_id,
+                position,
+                "E0015",
+                "loop body too large",
+            )),
+        }
+    }
+
     pub fn add_constant(&mut self, value: Constant) -> Option<u8> {
-        if self.constants.len() >= u8::MAX as usize + 1 {
+        if self.constants.len() > u8::MAX as usize {
             return None;
         }
         self.constants.push(value);
         Some((self.constants.len() - 1) as u8)
     }
 
+    /// Prints the disassembly of the whole chunk under a title.
     pub fn disassemble(&self, title: impl AsRef<str>) {
-        println!("== {} ==", title.as_ref());
+        print!("{}", self.disassembly(title.as_ref()));
+    }
+
+    /// Prints the disassembly of the instruction at `offset`.
+    pub fn disassemble_instruction(&self, offset: usize) {
+        let mut line = String::new();
+        self.write_instruction(offset, &mut line).unwrap();
+        print!("{}", line);
+    }
+
+    /// The disassembly of the whole chunk under a title, as [`Display`]ed by the returned value.
+    pub fn disassembly<'a>(&'a self, title: &'a str) -> Disassembly<'a> {
+        Disassembly { chunk: self, title }
+    }
+
+    /// Writes the disassembly of the whole chunk under a title, one line per instruction.
+    pub fn write_disassembly(&self, title: &str, out: &mut impl Write) -> fmt::Result {
+        writeln!(out, "== {} ==", title)?;
+        write!(out, "; ")?;
+        if let Some(name) = &self.name {
+            write!(out, "{}, ", name)?;
+        }
+        write!(out, "bytecode v{}", self.version)?;
+        if let Some(hash) = self.source_hash {
+            write!(out, ", source {:016x}", hash)?;
+        }
+        writeln!(out)?;
         for offset in 0..self.code.len() {
-            self.disassemble_instruction(offset);
+            self.write_instruction(offset, out)?;
         }
+        Ok(())
     }
 
-    pub fn disassemble_instruction(&self, offset: usize) {
-        print!("{:04} ", offset);
+    /// Writes the disassembly of the instruction at `offset` as one line.
+    pub fn write_instruction(&self, offset: usize, out: &mut impl Write) -> fmt::Result {
+        let (line, column) = self.lines.line_column(self.positions[offset].start);
+        write!(out, "{:04} {:>8} ", offset, format!("{}:{}", line, column))?;
 
         match &self.code[offset] {
             // Instructions with operand.
             Instruction::Constant(constant_index) => {
-                constant_instruction("CONST", constant_index, self)
+                constant_instruction(out, "CONST", constant_index, self)
+            }
+            Instruction::DefineGlobal(index) => {
+                constant_instruction(out, "DEFINEGLOBAL", index, self)
+            }
+            Instruction::GetGlobal(index) => constant_instruction(out, "GETGLOBAL", index, self),
+            Instruction::SetGlobal(index) => constant_instruction(out, "SETGLOBAL", index, self),
+            Instruction::GetLocal(index) => offset_instruction(out, "GETLOCAL", index),
+            Instruction::SetLocal(index) => offset_instruction(out, "SETLOCAL", index),
+            Instruction::JumpFalse(offset) => offset_instruction(out, "JMPFALSE", offset),
+            Instruction::Jump(offset) => offset_instruction(out, "JUMP", offset),
+            Instruction::Loop(offset) => offset_instruction(out, "LOOP", offset),
+            Instruction::Call(argc) => offset_instruction(out, "CALL", argc),
+            Instruction::PopN(count) => offset_instruction(out, "POPN", count),
+
+            // Superinstructions fused by the compiler.
+            Instruction::AddConstant(index) => constant_instruction(out, "ADDCONST", index, self),
+            Instruction::LessConstant(index) => constant_instruction(out, "LESSCONST", index, self),
+            Instruction::GreaterConstant(index) => {
+                constant_instruction(out, "GREATERCONST", index, self)
+            }
+            Instruction::GetLocalAddConstant(slot, index) => {
+                local_constant_instruction(out, "LOCALADDCONST", slot, index, self)
             }
-            Instruction::DefineGlobal(index) => constant_instruction("DEFINEGLOBAL", index, self),
-            Instruction::GetGlobal(index) => constant_instruction("GETGLOBAL", index, self),
-            Instruction::SetGlobal(index) => constant_instruction("SETGLOBAL", index, self),
-            Instruction::GetLocal(index) => offset_instruction("GETLOCAL", index),
-            Instruction::SetLocal(index) => offset_instruction("SETLOCAL", index),
-            Instruction::JumpFalse(offset) => offset_instruction("JMPFALSE", offset),
-            Instruction::Jump(offset) => offset_instruction("JUMP", offset),
-            Instruction::Loop(offset) => offset_instruction("LOOP", offset),
 
             // Literal instructions.
-            Instruction::Nil => simple_instruction("NIL"),
-            Instruction::True => simple_instruction("TRUE"),
-            Instruction::False => simple_instruction("FALSE"),
+            Instruction::Nil => simple_instruction(out, "NIL"),
+            Instruction::True => simple_instruction(out, "TRUE"),
+            Instruction::False => simple_instruction(out, "FALSE"),
 
             // Arithmetic instructions.
-            Instruction::Add => simple_instruction("ADD"),
-            Instruction::Subtract => simple_instruction("SUB"),
-            Instruction::Multiply => simple_instruction("MUL"),
-            Instruction::Divide => simple_instruction("DIV"),
-            Instruction::Negate => simple_instruction("NEG"),
+            Instruction::Add => simple_instruction(out, "ADD"),
+            Instruction::Subtract => simple_instruction(out, "SUB"),
+            Instruction::Multiply => simple_instruction(out, "MUL"),
+            Instruction::Divide => simple_instruction(out, "DIV"),
+            Instruction::Negate => simple_instruction(out, "NEG"),
 
             // Logic instructions.
-            Instruction::Not => simple_instruction("NOT"),
-            Instruction::Equal => simple_instruction("EQUAL"),
-            Instruction::Greater => simple_instruction("GREATER"),
-            Instruction::Less => simple_instruction("LESS"),
+            Instruction::Not => simple_instruction(out, "NOT"),
+            Instruction::Equal => simple_instruction(out, "EQUAL"),
+            Instruction::Greater => simple_instruction(out, "GREATER"),
+            Instruction::Less => simple_instruction(out, "LESS"),
 
             // Miscellaneous.
-            Instruction::Return => simple_instruction("RET"),
-            Instruction::Print => simple_instruction("PRINT"),
-            Instruction::Pop => simple_instruction("POP"),
+            Instruction::Return => simple_instruction(out, "RET"),
+            Instruction::Print => simple_instruction(out, "PRINT"),
+            Instruction::Pop => simple_instruction(out, "POP"),
         }
     }
 }
 
-fn simple_instruction(name: impl AsRef<str>) {
-    println!("{}", name.as_ref());
+pub(crate) fn jump_error(
+    file_id: usize,
+    position: &Range<usize>,
+    code: &str,
+    message: &str,
+) -> InterpretError {
+    InterpretError::Simple(
+        ErrorItem::error()
+            .with_code(code)
+            .with_message(message)
+            .with_labels(vec![Label::primary(file_id, position.clone())
+                .with_message("jump within this statement")]),
+    )
 }
 
-fn constant_instruction(name: impl AsRef<str>, constant_index: &u8, chunk: &Chunk) {
-    println!(
+/// The disassembly of a chunk, rendered by [`Display`].
+pub struct Disassembly<'a> {
+    chunk: &'a Chunk,
+    title: &'a str,
+}
+
+impl Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunk.write_disassembly(self.title, f)
+    }
+}
+
+fn simple_instruction(out: &mut impl Write, name: impl AsRef<str>) -> fmt::Result {
+    writeln!(out, "{}", name.as_ref())
+}
+
+fn constant_instruction(
+    out: &mut impl Write,
+    name: impl AsRef<str>,
+    constant_index: &u8,
+    chunk: &Chunk,
+) -> fmt::Result {
+    writeln!(
+        out,
         "{:<16} {:4} '{}'",
         name.as_ref(),
         constant_index,
         chunk.constants[*constant_index as usize]
-    );
+    )
+}
+
+fn local_constant_instruction(
+    out: &mut impl Write,
+    name: impl AsRef<str>,
+    slot: &u8,
+    constant_index: &u8,
+    chunk: &Chunk,
+) -> fmt::Result {
+    writeln!(
+        out,
+        "{:<16} {:4} {:4} '{}'",
+        name.as_ref(),
+        slot,
+        constant_index,
+        chunk.constants[*constant_index as usize]
+    )
 }
 
-fn offset_instruction<N: Display + Copy>(name: impl AsRef<str>, offset: &N) {
-    println!("{:<16} {:4}", name.as_ref(), offset);
+fn offset_instruction<N: Display + Copy>(
+    out: &mut impl Write,
+    name: impl AsRef<str>,
+    offset: &N,
+) -> fmt::Result {
+    writeln!(out, "{:<16} {:4}", name.as_ref(), offset)
 }
@@ -0,0 +1,453 @@
+//! The versioned binary `.loxc` format, so chunks can be compiled ahead of time.
+//!
+//! All integers are little-endian. The layout is:
+//!
+//! - magic `LOXC` and a `u16` format version;
+//! - since version 3, the chunk name and the source hash, each a presence byte followed by a
+//!   `u32` length and UTF-8 bytes, or by a `u64`, if present;
+//! - constant pool: `u32` count, then a tag byte per constant followed by an `f64` for
+//!   numbers, an `i64` for integers (since version 4), a `u32` length and UTF-8 bytes for
+//!   strings, a byte for booleans, or nothing for nil;
+//! - code: `u32` count, then an opcode byte per instruction followed by its operands;
+//! - position table: one `u32` start and `u32` end per instruction;
+//! - line table: `u32` count, then the `u32` byte offset where each source line starts.
+
+use std::ops::Range;
+
+use crate::{
+    constant::Constant,
+    error::{ErrorItem, InterpretError, InterpretResult},
+    line::LineIndex,
+};
+
+use super::{Chunk, Instruction};
+
+const MAGIC: &[u8; 4] = b"LOXC";
+// Versions before this one can still be loaded, without the metadata they lack.
+const OLDEST_VERSION: u16 = 2;
+
+const NUMBER_TAG: u8 = 0;
+const STRING_TAG: u8 = 1;
+const BOOLEAN_TAG: u8 = 2;
+const NIL_TAG: u8 = 3;
+const INT_TAG: u8 = 4;
+
+// Keeps encoding and decoding in sync. Opcodes must never be reused within a version.
+macro_rules! opcodes {
+    ($($opcode:literal => $name:ident $(($($operand:ident: $typ:ident),+))?),* $(,)?) => {
+        fn encode_instruction(instruction: &Instruction, bytes: &mut Vec<u8>) {
+            match instruction {
+                $(
+                    Instruction::$name $(($($operand),+))? => {
+                        bytes.push($opcode);
+                        $($(bytes.extend_from_slice(&$operand.to_le_bytes());)+)?
+                    }
+                )*
+            }
+        }
+
+        fn decode_instruction(reader: &mut Reader) -> InterpretResult<Instruction> {
+            Ok(match reader.u8()? {
+                $($opcode => Instruction::$name $(($(reader.$typ()?),+))?,)*
+                opcode => return Err(malformed(format!("unknown opcode {}", opcode))),
+            })
+        }
+    };
+}
+
+opcodes! {
+    0 => Constant(index: u8),
+    1 => DefineGlobal(index: u8),
+    2 => GetGlobal(index: u8),
+    3 => SetGlobal(index: u8),
+    4 => GetLocal(slot: u8),
+    5 => SetLocal(slot: u8),
+    6 => JumpFalse(offset: u16),
+    7 => Jump(offset: u16),
+    8 => Loop(offset: u16),
+    9 => Call(argc: u8),
+    10 => PopN(count: u8),
+    11 => AddConstant(index: u8),
+    12 => LessConstant(index: u8),
+    13 => GreaterConstant(index: u8),
+    14 => GetLocalAddConstant(slot: u8, index: u8),
+    15 => Nil,
+    16 => True,
+    17 => False,
+    18 => Add,
+    19 => Subtract,
+    20 => Multiply,
+    21 => Divide,
+    22 => Negate,
+    23 => Not,
+    24 => Equal,
+    25 => Greater,
+    26 => Less,
+    27 => Return,
+    28 => Print,
+    29 => Pop,
+}
+
+impl Chunk {
+    /// Version of the `.loxc` format written by [`Chunk::serialize`].
+    pub const FORMAT_VERSION: u16 = 4;
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&Self::FORMAT_VERSION.to_le_bytes());
+
+        match &self.name {
+            Some(name) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(name.as_bytes());
+            }
+            None => bytes.push(0),
+        }
+        match self.source_hash {
+            Some(hash) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+
+        bytes.extend_from_slice(&(self.constants.len() as u32).to_le_bytes());
+        for constant in &self.constants {
+            match constant {
+                Constant::Int(int) => {
+                    bytes.push(INT_TAG);
+                    bytes.extend_from_slice(&int.to_le_bytes());
+                }
+                Constant::Number(number) => {
+                    bytes.push(NUMBER_TAG);
+                    bytes.extend_from_slice(&number.to_le_bytes());
+                }
+                Constant::String(string) => {
+                    bytes.push(STRING_TAG);
+                    bytes.extend_from_slice(&(string.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(string.as_bytes());
+                }
+                Constant::Boolean(boolean) => {
+                    bytes.push(BOOLEAN_TAG);
+                    bytes.push(*boolean as u8);
+                }
+                Constant::Nil => bytes.push(NIL_TAG),
+            }
+        }
+
+        bytes.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+        for instruction in &self.code {
+            encode_instruction(instruction, &mut bytes);
+        }
+        for position in &self.positions {
+            bytes.extend_from_slice(&(position.start as u32).to_le_bytes());
+            bytes.extend_from_slice(&(position.end as u32).to_le_bytes());
+        }
+
+        let starts = self.lines.starts();
+        bytes.extend_from_slice(&(starts.len() as u32).to_le_bytes());
+        for start in starts {
+            bytes.extend_from_slice(&(*start as u32).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Whether the bytes start like a serialized chunk, so they can be told apart from source
+    /// code regardless of the file name.
+    pub fn is_serialized(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Loads a chunk written by [`Chunk::serialize`]. Positions refer to `file_id`, which
+    /// should hold the source the chunk was compiled from.
+    pub fn deserialize(file_id: usize, bytes: &[u8]) -> InterpretResult<Chunk> {
+        let mut reader = Reader { bytes, cursor: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(malformed("missing magic header"));
+        }
+        let version = reader.u16()?;
+        if !(OLDEST_VERSION..=Self::FORMAT_VERSION).contains(&version) {
+            return Err(malformed(format!("unsupported format version {}", version)));
+        }
+
+        let mut chunk = Chunk::new(file_id);
+        chunk.version = version;
+        if version >= 3 {
+            if reader.flag()? {
+                chunk.name = Some(reader.string()?);
+            }
+            if reader.flag()? {
+                chunk.source_hash = Some(reader.u64()?);
+            }
+        }
+        for _ in 0..reader.u32()? {
+            let constant = match reader.u8()? {
+                NUMBER_TAG => Constant::Number(reader.f64()?),
+                STRING_TAG => Constant::String(reader.string()?),
+                BOOLEAN_TAG => Constant::Boolean(reader.flag()?),
+                NIL_TAG => Constant::Nil,
+                INT_TAG if version >= 4 => Constant::Int(reader.i64()?),
+                tag => return Err(malformed(format!("unknown constant tag {}", tag))),
+            };
+            if chunk.add_constant(constant).is_none() {
+                return Err(malformed("too many constants"));
+            }
+        }
+
+        let count = reader.u32()? as usize;
+        for _ in 0..count {
+            let instruction = decode_instruction(&mut reader)?;
+            // The VM trusts constant indices, so they are checked once here.
+            if let Some(index) = constant_operand(&instruction) {
+                if index as usize >= chunk.constants.len() {
+                    return Err(malformed(format!("constant index {} out of range", index)));
+                }
+            }
+            chunk.code.push(instruction);
+        }
+        // Jumps are only bounds-checked by the VM when taken, so reject broken ones up front.
+        for (index, instruction) in chunk.code.iter().enumerate() {
+            let target = match *instruction {
+                Instruction::Jump(offset) | Instruction::JumpFalse(offset) if offset > 0 => {
+                    Some(index + offset as usize).filter(|&target| target < count)
+                }
+                Instruction::Loop(offset) if offset > 0 => index.checked_sub(offset as usize),
+                Instruction::Jump(_) | Instruction::JumpFalse(_) | Instruction::Loop(_) => None,
+                _ => continue,
+            };
+            if target.is_none() {
+                return Err(malformed(format!(
+                    "instruction {} jumps out of the code",
+                    index
+                )));
+            }
+        }
+        for _ in 0..count {
+            let position: Range<usize> = reader.u32()? as usize..reader.u32()? as usize;
+            chunk.positions.push(position);
+        }
+
+        let starts = (0..reader.u32()?)
+            .map(|_| reader.u32().map(|start| start as usize))
+            .collect::<InterpretResult<_>>()?;
+        chunk.lines = match LineIndex::from_starts(starts) {
+            Some(lines) => lines,
+            None => return Err(malformed("line table is not ascending from 0")),
+        };
+
+        if reader.cursor != bytes.len() {
+            return Err(malformed("trailing bytes after line table"));
+        }
+        Ok(chunk)
+    }
+}
+
+fn constant_operand(instruction: &Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::Constant(index)
+        | Instruction::DefineGlobal(index)
+        | Instruction::GetGlobal(index)
+        | Instruction::SetGlobal(index)
+        | Instruction::AddConstant(index)
+        | Instruction::LessConstant(index)
+        | Instruction::GreaterConstant(index)
+        | Instruction::GetLocalAddConstant(_, index) => Some(*index),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> InterpretResult<&'a [u8]> {
+        match self.bytes.get(self.cursor..self.cursor + length) {
+            Some(slice) => {
+                self.cursor += length;
+                Ok(slice)
+            }
+            None => Err(malformed("unexpected end of file")),
+        }
+    }
+
+    fn u8(&mut self) -> InterpretResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> InterpretResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> InterpretResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> InterpretResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn flag(&mut self) -> InterpretResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(malformed(format!("expected a 0 or 1 byte, not {}", byte))),
+        }
+    }
+
+    fn i64(&mut self) -> InterpretResult<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> InterpretResult<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> InterpretResult<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| malformed("string is not valid UTF-8"))
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> InterpretError {
+    InterpretError::Simple(
+        ErrorItem::error()
+            .with_code("E0013")
+            .with_message("malformed bytecode file")
+            .with_notes(vec![reason.into()]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_of(code: impl IntoIterator<Item = Instruction>) -> Chunk {
+        let mut chunk = Chunk::new(0);
+        for instruction in code {
+            chunk.write(instruction, &(0..0));
+        }
+        chunk
+    }
+
+    fn rejection(bytes: &[u8]) -> String {
+        match Chunk::deserialize(0, bytes) {
+            Ok(_) => panic!("malformed bytecode was accepted"),
+            Err(error) => error.diagnostics()[0].notes.join("\n"),
+        }
+    }
+
+    /// A chunk using every kind of constant and operand, with source information attached.
+    fn sample() -> Chunk {
+        let source = "var s = \"é\";\nprint s + 1.5;\n";
+        let mut chunk = Chunk::new(0);
+        for constant in [
+            Constant::String("s".into()),
+            Constant::String("é".into()),
+            Constant::Number(1.5),
+            Constant::Int(-7),
+            Constant::Boolean(true),
+            Constant::Nil,
+        ] {
+            chunk.add_constant(constant).unwrap();
+        }
+        let code = [
+            (Instruction::Constant(1), 8..12),
+            (Instruction::DefineGlobal(0), 0..13),
+            (Instruction::GetGlobal(0), 20..21),
+            (Instruction::AddConstant(2), 20..27),
+            (Instruction::JumpFalse(2), 14..28),
+            (Instruction::GetLocalAddConstant(0, 3), 14..28),
+            (Instruction::Print, 14..28),
+            (Instruction::Return, 0..0),
+        ];
+        for (instruction, position) in code {
+            chunk.write(instruction, &position);
+        }
+        chunk.name = Some("<script>".into());
+        chunk.attach_source(source);
+        chunk
+    }
+
+    #[test]
+    fn roundtrips() {
+        let chunk = sample();
+        let bytes = chunk.serialize();
+        let loaded = Chunk::deserialize(3, &bytes).unwrap();
+
+        assert_eq!(loaded.file_id, 3);
+        assert_eq!(loaded.name, chunk.name);
+        assert_eq!(loaded.source_hash, chunk.source_hash);
+        assert_eq!(loaded.version, Chunk::FORMAT_VERSION);
+        assert_eq!(loaded.positions, chunk.positions);
+        assert_eq!(loaded.lines.starts(), chunk.lines.starts());
+        // The disassembly shows every instruction with its operands and constants.
+        assert_eq!(
+            loaded.disassembly("sample").to_string(),
+            chunk.disassembly("sample").to_string()
+        );
+        assert_eq!(loaded.serialize(), bytes);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let bytes = sample().serialize();
+        for length in 0..bytes.len() {
+            assert!(
+                Chunk::deserialize(0, &bytes[..length]).is_err(),
+                "accepted the first {} of {} bytes",
+                length,
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = sample().serialize();
+        bytes[0] = b'X';
+        assert!(!Chunk::is_serialized(&bytes));
+        assert_eq!(rejection(&bytes), "missing magic header");
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let mut bytes = sample().serialize();
+        for version in [OLDEST_VERSION - 1, Chunk::FORMAT_VERSION + 1] {
+            bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(
+                rejection(&bytes),
+                format!("unsupported format version {}", version)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_jumps_out_of_code() {
+        for code in [
+            [Instruction::Jump(0), Instruction::Return],
+            [Instruction::Jump(2), Instruction::Return],
+            [Instruction::JumpFalse(0), Instruction::Return],
+            [Instruction::Return, Instruction::Loop(2)],
+            [Instruction::Return, Instruction::Loop(0)],
+        ] {
+            let bytes = chunk_of(code).serialize();
+            assert!(rejection(&bytes).contains("jumps out of the code"));
+        }
+    }
+
+    #[test]
+    fn accepts_jumps_within_code() {
+        let code = [
+            Instruction::JumpFalse(2),
+            Instruction::Loop(1),
+            Instruction::Jump(1),
+            Instruction::Return,
+        ];
+        assert!(Chunk::deserialize(0, &chunk_of(code).serialize()).is_ok());
+    }
+}
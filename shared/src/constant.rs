@@ -1,16 +1,26 @@
 use std::fmt::Display;
 
+use crate::number;
+
+/// A literal value stored in a chunk. The compiler loads `true`, `false` and `nil` with their
+/// own instructions, but any literal can be a constant, such as the result of folding.
 #[derive(Clone)]
 pub enum Constant {
+    Int(i64),
     Number(f64),
     String(String),
+    Boolean(bool),
+    Nil,
 }
 
 impl Display for Constant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Constant::Number(number) => write!(f, "{}", number),
+            Constant::Int(int) => write!(f, "{}", int),
+            Constant::Number(number) => write!(f, "{}", number::display(*number)),
             Constant::String(string) => write!(f, "{}", string),
+            Constant::Boolean(boolean) => write!(f, "{}", boolean),
+            Constant::Nil => write!(f, "nil"),
         }
     }
 }
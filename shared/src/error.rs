@@ -1,15 +1,68 @@
-use std::fmt::Display;
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    mem,
+    ops::Range,
+};
 
-use codespan_reporting::term::{
-    self,
-    termcolor::{ColorChoice, StandardStream},
-    Config,
+use codespan_reporting::{
+    diagnostic::{LabelStyle, Severity},
+    term::termcolor::ColorChoice,
 };
 
+use crate::line::LineIndex;
+
+pub mod explain;
+mod json;
+mod sink;
+
+pub use self::sink::{DiagnosticSink, TerminalSink};
+
 pub type SourceFileManager<N, S> = codespan_reporting::files::SimpleFiles<N, S>;
 pub type ErrorItem = codespan_reporting::diagnostic::Diagnostic<usize>;
 pub type Label = codespan_reporting::diagnostic::Label<usize>;
 
+/// How diagnostics are rendered when emitted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MessageFormat {
+    /// Snippets of the source, for people.
+    #[default]
+    Human,
+    /// One JSON object per line, for tools. See the `json` module for the schema.
+    Json,
+}
+
+/// Whether human-readable diagnostics are colored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorMode {
+    Always,
+    /// Colored only if `stderr` is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Never,
+}
+
+impl ColorMode {
+    fn choice(self) -> ColorChoice {
+        match self {
+            ColorMode::Always => ColorChoice::Always,
+            ColorMode::Auto if io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none() => {
+                // Still leaves `TERM=dumb` uncolored.
+                ColorChoice::Auto
+            }
+            ColorMode::Auto | ColorMode::Never => ColorChoice::Never,
+        }
+    }
+}
+
+/// Everything that decides how diagnostics look.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct EmitOptions {
+    pub format: MessageFormat,
+    pub color: ColorMode,
+}
+
 #[derive(Debug)]
 pub enum InterpretError {
     Simple(ErrorItem),
@@ -17,25 +70,204 @@ pub enum InterpretError {
 }
 
 impl InterpretError {
+    /// Every diagnostic carried by the error, in the order they were reported.
+    pub fn diagnostics(&self) -> &[ErrorItem] {
+        match self {
+            InterpretError::Simple(diagnostic) => std::slice::from_ref(diagnostic),
+            InterpretError::Compound(diagnostics) => diagnostics,
+        }
+    }
+
+    /// Code of the first diagnostic, such as `E0005`.
+    pub fn code(&self) -> Option<&str> {
+        self.diagnostics().first()?.code.as_deref()
+    }
+
+    /// Message of the first diagnostic.
+    pub fn message(&self) -> &str {
+        self.diagnostics()
+            .first()
+            .map_or("", |diagnostic| &diagnostic.message)
+    }
+
+    /// File id and byte range the first diagnostic points at: its primary label, or its first
+    /// label if it only has secondary ones.
+    pub fn primary_span(&self) -> Option<(usize, Range<usize>)> {
+        let labels = &self.diagnostics().first()?.labels;
+        labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or(labels.first())
+            .map(|label| (label.file_id, label.range.clone()))
+    }
+
+    /// Turns the source labels into `line:column` notes, for when the source the positions
+    /// refer to is unavailable.
+    pub fn without_labels(self, lines: &LineIndex) -> Self {
+        let strip = |mut diagnostic: ErrorItem| {
+            for label in mem::take(&mut diagnostic.labels) {
+                let (line, column) = lines.line_column(label.range.start);
+                diagnostic
+                    .notes
+                    .push(format!("{}:{}: {}", line, column, label.message));
+            }
+            diagnostic
+        };
+        match self {
+            InterpretError::Simple(diagnostic) => InterpretError::Simple(strip(diagnostic)),
+            InterpretError::Compound(diagnostics) => {
+                InterpretError::Compound(diagnostics.into_iter().map(strip).collect())
+            }
+        }
+    }
+
+    /// Keeps the first `limit` diagnostics and replaces the rest with one saying there were too
+    /// many. Errors within the limit are returned as they are.
+    pub fn truncated(self, limit: usize) -> Self {
+        let mut diagnostics = match self {
+            InterpretError::Compound(diagnostics) if diagnostics.len() > limit => diagnostics,
+            error => return error,
+        };
+        diagnostics.truncate(limit);
+        diagnostics.push(Self::too_many(limit));
+        InterpretError::Compound(diagnostics)
+    }
+
+    /// The diagnostic ending the errors of a compilation stopped at `limit` of them.
+    pub fn too_many(limit: usize) -> ErrorItem {
+        ErrorItem::error()
+            .with_message(format!(
+                "too many errors, stopped after the first {}",
+                limit
+            ))
+            .with_notes(vec![
+                "later errors are often caused by earlier ones, so fix those first".into(),
+            ])
+    }
+
     pub fn emit<N, S>(self, files: &SourceFileManager<N, S>)
     where
         N: Display + Clone,
         S: AsRef<str>,
     {
-        let stream = StandardStream::stderr(ColorChoice::Always);
-        let stream = &mut stream.lock();
-        let config = Config::default();
+        self.emit_as(files, EmitOptions::default());
+    }
 
-        match self {
-            InterpretError::Simple(diagnostic) => term::emit(stream, &config, files, &diagnostic)
-                .expect("internal diagnostic emission error"),
-            InterpretError::Compound(diagnostics) => {
-                for diagnostic in diagnostics {
-                    term::emit(stream, &config, files, &diagnostic)
-                        .expect("internal diagnostic emission error");
-                }
+    pub fn emit_as<N, S>(self, files: &SourceFileManager<N, S>, options: EmitOptions)
+    where
+        N: Display + Clone,
+        S: AsRef<str>,
+    {
+        self.emit_to(files, &mut TerminalSink::new(options));
+    }
+
+    /// Hands every diagnostic to the sink, in order.
+    pub fn emit_to<N, S>(
+        self,
+        files: &SourceFileManager<N, S>,
+        sink: &mut impl DiagnosticSink<N, S>,
+    ) {
+        let diagnostics = match self {
+            InterpretError::Simple(diagnostic) => vec![diagnostic],
+            InterpretError::Compound(diagnostics) => diagnostics,
+        };
+        for diagnostic in diagnostics {
+            sink.emit(diagnostic, files);
+        }
+    }
+}
+
+/// One `severity[code]: message` line per diagnostic, without source snippets.
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, diagnostic) in self.diagnostics().iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let severity = severity_name(diagnostic.severity);
+            match &diagnostic.code {
+                Some(code) => write!(f, "{}[{}]: {}", severity, code, diagnostic.message)?,
+                None => write!(f, "{}: {}", severity, diagnostic.message)?,
             }
         }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+/// Non-fatal diagnostics collected alongside a successful result.
+#[derive(Debug, Default)]
+pub struct Warnings(Vec<ErrorItem>);
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn push(&mut self, warning: ErrorItem) {
+        self.0.push(warning);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Turns every warning into an error, for when warnings are denied.
+    pub fn into_errors(self) -> InterpretError {
+        InterpretError::Compound(
+            self.0
+                .into_iter()
+                .map(|mut warning| {
+                    warning.severity = Severity::Error;
+                    warning
+                        .notes
+                        .push("warnings are denied, so this is an error".into());
+                    warning
+                })
+                .collect(),
+        )
+    }
+
+    pub fn emit<N, S>(self, files: &SourceFileManager<N, S>)
+    where
+        N: Display + Clone,
+        S: AsRef<str>,
+    {
+        self.emit_as(files, EmitOptions::default());
+    }
+
+    pub fn emit_as<N, S>(self, files: &SourceFileManager<N, S>, options: EmitOptions)
+    where
+        N: Display + Clone,
+        S: AsRef<str>,
+    {
+        self.emit_to(files, &mut TerminalSink::new(options));
+    }
+
+    /// Hands every warning to the sink, in order.
+    pub fn emit_to<N, S>(
+        self,
+        files: &SourceFileManager<N, S>,
+        sink: &mut impl DiagnosticSink<N, S>,
+    ) {
+        for warning in self.0 {
+            sink.emit(warning, files);
+        }
     }
 }
 
@@ -0,0 +1,466 @@
+//! Long-form explanations of error and warning codes, printed by `ruslox explain <code>`.
+
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E0001",
+        r#"Too many constants in one chunk.
+
+Every string literal, number literal and mention of a global name is stored in the constant
+pool of its chunk, and instructions refer to them by a one-byte index. A chunk can hold at
+most 256 constants, so a script with more of them than that can't be compiled.
+
+Keeping values in local variables helps, since locals are addressed by slot instead of by
+name. Otherwise, split the script."#,
+    ),
+    (
+        "E0002",
+        r#"Unexpected character.
+
+The scanner found a character that can't start any Lox token.
+
+    var price = $5; // error: `$` is not part of Lox
+
+Lox only knows ASCII letters, digits, `_`, the operators `+ - * / ! = < >`, the punctuation
+`( ) { } , . ;` and double-quoted strings. Every such character in the file is reported at
+once."#,
+    ),
+    (
+        "E0003",
+        r#"Uninterpretable number literal.
+
+The literal follows the syntax of numbers, but can't be stored as a 64-bit float."#,
+    ),
+    (
+        "E0004",
+        r#"Unterminated string.
+
+A string literal was opened with `"` but the file ended before the closing quote.
+
+    print "hello; // error: the string never ends
+
+Lox strings may span lines, so the missing quote may be far from where the error points. Lox
+has no escape sequences: a string can't contain a double quote."#,
+    ),
+    (
+        "E0005",
+        r#"Unrecognized statement.
+
+The parser could not make a statement out of the tokens starting here. It skips ahead to the
+next `;`, brace or keyword and carries on, so one mistake is reported once.
+
+    + 1;          // error: no expression starts with `+`
+    else print 1; // error: `else` without an `if`
+
+Check the statement for a typo or a missing operand."#,
+    ),
+    (
+        "E0006",
+        r#"Missing specific token.
+
+The statement was recognized, but a token it requires, such as `;` or `)`, is missing after
+the highlighted token.
+
+    print 1 + 2   // error: expected ; after this
+    if (a print a; // error: expected ) after this
+
+The note tells which token was expected."#,
+    ),
+    (
+        "E0007",
+        r#"Missing variable name.
+
+`var` has to be followed by the name of the variable being declared.
+
+    var = 1; // error
+
+Names start with a letter or `_` and go on with letters, digits or `_`."#,
+    ),
+    (
+        "E0008",
+        r#"Invalid assignment target.
+
+Only variables can be assigned to. The left side of `=` is some other expression here.
+
+    a + b = c; // error
+    1 = 2;     // error
+
+Make sure `=` wasn't meant to be the comparison `==`."#,
+    ),
+    (
+        "E0009",
+        r#"Too many arguments in one call.
+
+A call may pass at most 255 arguments, since the count is encoded in one byte."#,
+    ),
+    (
+        "E0010",
+        r#"Too many registers in one chunk.
+
+Only the experimental register backend reports this. Each chunk can address 256 registers,
+and deeply nested expressions may need more temporaries than that. Simplify the expression
+or use the default stack backend."#,
+    ),
+    (
+        "E0011",
+        r#"Variable already declared in this scope.
+
+A block may declare a name only once.
+
+    {
+        var a = 1;
+        var a = 2; // error
+    }
+
+Assign to the existing variable instead, or declare the new one in a nested block. Globals
+may be redeclared, which keeps the REPL convenient."#,
+    ),
+    (
+        "E0012",
+        r#"Can't read local variable in its own initializer.
+
+A local variable is not usable until its initializer has been evaluated.
+
+    var a = 1;
+    {
+        var a = a + 1; // error: refers to the inner `a`
+    }
+
+Like `clox`, Ruslox doesn't let the initializer see the outer variable through the new one.
+Pick a different name for the inner variable."#,
+    ),
+    (
+        "E0013",
+        r#"Malformed bytecode file.
+
+A `.loxc` file could not be loaded: it is truncated, has the wrong magic number, was written
+by an incompatible version of Ruslox, or refers to constants it doesn't contain. Compile the
+script again with `ruslox compile`."#,
+    ),
+    (
+        "E0014",
+        r#"Too much code to jump over.
+
+The branch of an `if`, or the body of a loop, compiled to more bytecode than a jump can skip
+(65535 bytes). Move part of the code into a separate statement or file."#,
+    ),
+    (
+        "E0015",
+        r#"Loop body too large.
+
+A loop jumps back to its start with a 16-bit offset, so its body and condition must compile to
+less than 65536 bytes. Split the loop body up."#,
+    ),
+    (
+        "E0016",
+        r#"Missing expression.
+
+An expression was expected here, but none was found.
+
+    var a = ; // error
+    print;    // error
+
+Fill in the missing value or operand."#,
+    ),
+    (
+        "E0017",
+        r#"Unmatched closing brace.
+
+A `}` appeared with no `{` to close.
+
+    print 1;
+    } // error
+
+Remove the brace, or look above for a block which was closed too early."#,
+    ),
+    (
+        "E0018",
+        r#"Internal frontend error.
+
+The scanner or the parser gave up on the source without reporting why. Their grammars are
+meant to accept any input and report a specific error for whatever is wrong, so this is a
+bug in Ruslox rather than in the script. Please report it along with the source, or the
+smallest part of it that still fails."#,
+    ),
+    (
+        "E0019",
+        r#"Variable is never declared.
+
+In strict mode, a global variable was read or assigned, but no `var` statement in the program
+declares it, and it isn't a native either.
+
+    var total = 0;
+    print totl; // error
+
+Without `--strict`, this is only found when the code runs, as `E1008`. Declare the variable, or
+fix the spelling of its name."#,
+    ),
+    (
+        "W0001",
+        r#"Unused local variable.
+
+A local variable was declared but never read. Assigning to it doesn't count as a use.
+
+    {
+        var unused = 1; // warning
+    }
+
+Remove the variable, or start its name with `_` to say it is unused on purpose. The lint is
+named `unused-variable`."#,
+    ),
+    (
+        "W0002",
+        r#"Statement has no effect.
+
+The statement computes a value and throws it away, without calling or assigning anything.
+
+    a == 1; // warning: probably meant `a = 1;`
+
+The lint is named `no-effect`."#,
+    ),
+    (
+        "W0003",
+        r#"Local variable shadows another variable.
+
+A local variable has the same name as a variable of an enclosing scope, which becomes
+unreachable until the block ends.
+
+    var count = 0;
+    {
+        var count = 1; // warning
+    }
+
+The lint is named `shadowing`. It is off by default, enable it with `-W shadowing`."#,
+    ),
+    (
+        "W0004",
+        r#"Comparison to a boolean literal.
+
+    if (done == true) print "done"; // warning
+
+Such comparisons are usually redundant. Note that they aren't quite the same as the operand
+itself: `1 == true` is false, while `1` is truthy. The lint is named `bool-comparison`."#,
+    ),
+    (
+        "W0005",
+        r#"Empty block.
+
+    while (waiting()) {} // warning
+
+An empty block does nothing, which is often a leftover. The lint is named `empty-block`."#,
+    ),
+    (
+        "W0006",
+        r#"Bytecode is older than its source.
+
+A `.loxc` file was run, but the `.lox` file beside it has changed since it was compiled, so
+the bytecode may not do what the source says. Diagnostics fall back to line and column
+numbers, since positions in the changed source would be wrong. Compile the script again:
+
+    ruslox compile script.lox
+
+This is not a lint, and can't be allowed."#,
+    ),
+    (
+        "E1001",
+        r#"Stack overflow.
+
+The VM stack holds at most 256 values, unless `--stack-size` sets another limit. Expressions
+or calls nested too deeply can exceed it."#,
+    ),
+    (
+        "E1002",
+        r#"Stack underflow.
+
+An instruction popped a value from an empty stack. Valid programs never do this: it points to
+a compiler bug or a corrupted `.loxc` file."#,
+    ),
+    (
+        "E1003",
+        r#"Operands must be numbers.
+
+Arithmetic and comparison operators other than `+`, `==` and `!=` only work on numbers.
+
+    print "a" < "b"; // error
+    print nil * 2;   // error"#,
+    ),
+    (
+        "E1004",
+        r#"Operand must be a number.
+
+Unary `-` only negates numbers.
+
+    print -"five"; // error"#,
+    ),
+    (
+        "E1005",
+        r#"Concatenation operands must be both numbers or both strings.
+
+`+` adds two numbers or concatenates strings. A string can be concatenated with any other
+value, which is converted the way `print` shows it, so only operands like these fail:
+
+    print nil + true; // error
+
+With `--strict-compat`, Ruslox follows reference Lox, which doesn't mix strings with other
+values either:
+
+    print "total: " + 3; // error with --strict-compat"#,
+    ),
+    (
+        "E1006",
+        r#"Invalid name of global definition.
+
+An instruction on globals refers to a constant which isn't a string. Valid programs never do
+this: it points to a compiler bug or a corrupted `.loxc` file."#,
+    ),
+    (
+        "E1007",
+        r#"Defining global with empty stack.
+
+A global definition found no value on the stack. It points to a compiler bug or a corrupted
+`.loxc` file."#,
+    ),
+    (
+        "E1008",
+        r#"Undefined global.
+
+A global variable was read or assigned before any `var` statement defined it.
+
+    print total; // error
+    var total = 0;
+
+Unlike locals, globals are resolved when the code runs, so this is a runtime error. Define the
+variable before the code that uses it runs."#,
+    ),
+    (
+        "E1009",
+        r#"Get local with empty stack slot.
+
+An instruction read a local slot beyond the top of the stack. It points to a compiler bug or a
+corrupted `.loxc` file."#,
+    ),
+    (
+        "E1010",
+        r#"Set local with empty stack slot.
+
+An instruction wrote a local slot beyond the top of the stack. It points to a compiler bug or a
+corrupted `.loxc` file."#,
+    ),
+    (
+        "E1011",
+        r#"Jumping out of code.
+
+A jump instruction targets an offset past the end of the chunk. It points to a compiler bug or
+a corrupted `.loxc` file."#,
+    ),
+    (
+        "E1012",
+        r#"Jump condition required but stack is empty.
+
+A conditional jump found no condition on the stack. It points to a compiler bug or a corrupted
+`.loxc` file."#,
+    ),
+    (
+        "E1013",
+        r#"Loop back out of code.
+
+A loop instruction jumps back before the start of the chunk. It points to a compiler bug or a
+corrupted `.loxc` file."#,
+    ),
+    (
+        "E1014",
+        r#"Out of memory.
+
+An allocation would exceed the heap limit even after collecting garbage. Values still reachable
+from variables can't be collected; drop references to large strings when they are no longer
+needed."#,
+    ),
+    (
+        "E1015",
+        r#"Can only call functions.
+
+Only functions can be called.
+
+    var a = 1;
+    a(); // error
+
+The natives `gc`, `memory`, `objects`, `argc`, `arg`, `readLine` and `clock`, along with
+those an embedding application defines, are the only functions for now."#,
+    ),
+    (
+        "E1016",
+        r#"Wrong number of arguments.
+
+A function was called with a different number of arguments than it declares.
+
+    memory(1); // error: `memory` takes no arguments"#,
+    ),
+    (
+        "E1017",
+        r#"Native function failed.
+
+A function implemented by the VM reported an error. The message tells what went wrong."#,
+    ),
+    (
+        "E1018",
+        r#"Native function needs a disabled capability.
+
+The application running the script has disabled what the native function needs to reach
+outside the VM: the filesystem, the process, the network or the wall clock. Scripts run
+this way can only compute.
+
+    print clock(); // error when the wall clock is disabled
+
+`argc`, `arg` and `readLine` need the process capability, and `clock` the wall-clock one."#,
+    ),
+    (
+        "E1019",
+        r#"Failed to write output.
+
+`print` could not write to standard output, or to the writer the embedding application
+gave the VM. This happens when the output is a pipe whose reader has exited, as with
+
+    ruslox script.lox | head -1
+
+or when the disk holding a redirected output is full."#,
+    ),
+    (
+        "E1020",
+        r#"Division by zero.
+
+A number was divided by zero while strict arithmetic was on, as with `--strict-arithmetic`.
+
+    var rate = total / count; // error when count is 0
+
+Without strict arithmetic, the division yields an infinity, or NaN for `0 / 0`, and the
+script carries on with it. Check the divisor before dividing."#,
+    ),
+    (
+        "E1021",
+        r#"Arithmetic produced NaN.
+
+An arithmetic operation produced NaN ("not a number") while strict arithmetic was on, as with
+`--strict-arithmetic`. With division by zero already reported as E1020, this happens when
+infinities meet:
+
+    var huge = 1;
+    while (huge * 10 > huge) huge = huge * 10; // overflows to infinity
+    print huge - huge; // error
+
+Without strict arithmetic, NaN propagates silently through later arithmetic, and compares
+unequal to everything, itself included."#,
+    ),
+];
+
+/// The explanation of an error or warning code, such as `E0005`.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Every code with an explanation, in ascending order within compile errors, warnings and
+/// runtime errors.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
@@ -0,0 +1,137 @@
+//! Diagnostics as JSON objects, one per line, for editors and CI to consume.
+//!
+//! Each object has `severity`, `code`, `message`, `notes` and `labels`. A label carries its
+//! `style` (`primary` or `secondary`), `file`, byte `start` and `end`, the 1-based `line` and
+//! `column` of its start (or `null` if the file can't resolve them) and its `message`.
+
+use std::fmt::{Display, Write};
+
+use codespan_reporting::{diagnostic::LabelStyle, files::Files};
+
+use super::{severity_name, ErrorItem, SourceFileManager};
+
+pub(super) fn diagnostic<N, S>(diagnostic: &ErrorItem, files: &SourceFileManager<N, S>) -> String
+where
+    N: Display + Clone,
+    S: AsRef<str>,
+{
+    let severity = severity_name(diagnostic.severity);
+    let labels: Vec<_> = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let style = match label.style {
+                LabelStyle::Primary => "primary",
+                LabelStyle::Secondary => "secondary",
+            };
+            let file = files
+                .name(label.file_id)
+                .map_or_else(|_| "null".into(), |name| string(&name.to_string()));
+            let (line, column) = match files.location(label.file_id, label.range.start) {
+                Ok(location) => (
+                    location.line_number.to_string(),
+                    location.column_number.to_string(),
+                ),
+                Err(_) => ("null".into(), "null".into()),
+            };
+            format!(
+                r#"{{"style":"{}","file":{},"start":{},"end":{},"line":{},"column":{},"message":{}}}"#,
+                style,
+                file,
+                label.range.start,
+                label.range.end,
+                line,
+                column,
+                string(&label.message)
+            )
+        })
+        .collect();
+    let notes: Vec<_> = diagnostic.notes.iter().map(|note| string(note)).collect();
+    let code = diagnostic
+        .code
+        .as_ref()
+        .map_or_else(|| "null".into(), |code| string(code));
+
+    format!(
+        r#"{{"severity":"{}","code":{},"message":{},"labels":[{}],"notes":[{}]}}"#,
+        severity,
+        code,
+        string(&diagnostic.message),
+        labels.join(","),
+        notes.join(",")
+    )
+}
+
+/// Quotes and escapes a JSON string.
+fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if character.is_control() => {
+                write!(quoted, "\\u{:04x}", character as u32).expect("writing to a string")
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Label;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(string(r"a\b"), r#""a\\b""#);
+        assert_eq!(string("one\ntwo\r\tthree"), r#""one\ntwo\r\tthree""#);
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        assert_eq!(string("\u{0}\u{1b}[1m"), r#""\u0000\u001b[1m""#);
+        assert_eq!(string("\u{7f}\u{85}"), r#""\u007f\u0085""#);
+    }
+
+    #[test]
+    fn keeps_non_ascii_characters() {
+        assert_eq!(string("é 日本 🦀"), "\"é 日本 🦀\"");
+    }
+
+    #[test]
+    fn resolves_lines_and_columns() {
+        let mut files = SourceFileManager::new();
+        let source = "var a;\nprint \"é\" + b;\n";
+        let file_id = files.add("main.lox", source);
+        let start = source.find('b').unwrap();
+        let item = ErrorItem::error()
+            .with_code("E1008")
+            .with_message("undefined global")
+            .with_labels(vec![
+                Label::primary(file_id, start..start + 1).with_message("read here"),
+                Label::secondary(file_id + 1, 0..1),
+            ]);
+        let json = diagnostic(&item, &files);
+        // Columns count characters, not bytes.
+        assert!(json.contains(&format!(
+            r#"{{"style":"primary","file":"main.lox","start":{},"end":{},"line":2,"column":13,"message":"read here"}}"#,
+            start,
+            start + 1
+        )));
+        // Labels in files that aren't loaded have no location.
+        assert!(json.contains(
+            r#"{"style":"secondary","file":null,"start":0,"end":1,"line":null,"column":null,"message":""}"#
+        ));
+        assert!(
+            json.starts_with(r#"{"severity":"error","code":"E1008","message":"undefined global""#)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Where emitted diagnostics go. Hosts can route them to their own UI, logging or language
+//! server by implementing [`DiagnosticSink`]; [`TerminalSink`] renders them to `stderr`.
+
+use std::fmt::Display;
+
+use codespan_reporting::term::{self, termcolor::StandardStream, Config};
+
+use super::{json, EmitOptions, ErrorItem, MessageFormat, SourceFileManager};
+
+pub trait DiagnosticSink<N, S> {
+    /// Receives one diagnostic, along with the files its labels point into.
+    fn emit(&mut self, diagnostic: ErrorItem, files: &SourceFileManager<N, S>);
+}
+
+impl<N, S, F> DiagnosticSink<N, S> for F
+where
+    F: FnMut(ErrorItem, &SourceFileManager<N, S>),
+{
+    fn emit(&mut self, diagnostic: ErrorItem, files: &SourceFileManager<N, S>) {
+        self(diagnostic, files)
+    }
+}
+
+/// Collects the diagnostics, for hosts which handle them later.
+impl<N, S> DiagnosticSink<N, S> for Vec<ErrorItem> {
+    fn emit(&mut self, diagnostic: ErrorItem, _: &SourceFileManager<N, S>) {
+        self.push(diagnostic);
+    }
+}
+
+/// Renders diagnostics to `stderr` as the options say: source snippets through codespan, or
+/// JSON lines. This is what `emit` and `emit_as` use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerminalSink {
+    options: EmitOptions,
+}
+
+impl TerminalSink {
+    pub fn new(options: EmitOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl<N, S> DiagnosticSink<N, S> for TerminalSink
+where
+    N: Display + Clone,
+    S: AsRef<str>,
+{
+    fn emit(&mut self, diagnostic: ErrorItem, files: &SourceFileManager<N, S>) {
+        if self.options.format == MessageFormat::Json {
+            eprintln!("{}", json::diagnostic(&diagnostic, files));
+            return;
+        }
+
+        let stream = StandardStream::stderr(self.options.color.choice());
+        term::emit(&mut stream.lock(), &Config::default(), files, &diagnostic)
+            .expect("internal diagnostic emission error");
+    }
+}
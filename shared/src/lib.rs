@@ -1,4 +1,9 @@
 pub mod chunk;
 pub mod constant;
 pub mod error;
+pub mod line;
+pub mod module;
+pub mod number;
+#[cfg(feature = "register")]
+pub mod register;
 pub mod stack;
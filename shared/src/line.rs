@@ -0,0 +1,39 @@
+//! Line and column lookup for the byte ranges that positions are recorded as.
+
+/// Maps byte offsets of one source to 1-based line and column numbers. Columns count bytes.
+#[derive(Clone)]
+pub struct LineIndex {
+    // Byte offset where each line starts. The first line always starts at 0.
+    starts: Vec<usize>,
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self { starts: vec![0] }
+    }
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(offset, _)| offset + 1));
+        Self { starts }
+    }
+
+    pub fn from_starts(starts: Vec<usize>) -> Option<Self> {
+        let sorted = starts.windows(2).all(|pair| pair[0] < pair[1]);
+        match starts.first() {
+            Some(0) if sorted => Some(Self { starts }),
+            _ => None,
+        }
+    }
+
+    pub fn starts(&self) -> &[usize] {
+        &self.starts
+    }
+
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        (line, offset - self.starts[line - 1] + 1)
+    }
+}
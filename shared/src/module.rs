@@ -0,0 +1,126 @@
+//! Where the source of a module comes from, given its name. Hosts decide how names resolve, so
+//! modules can live on disk, in memory, in an archive or behind a virtual filesystem alike.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+pub trait ModuleLoader {
+    /// The source of the module called `name`.
+    fn load(&self, name: &str) -> io::Result<String>;
+}
+
+impl<F> ModuleLoader for F
+where
+    F: Fn(&str) -> io::Result<String>,
+{
+    fn load(&self, name: &str) -> io::Result<String> {
+        self(name)
+    }
+}
+
+/// Loads module `name` from `name.lox` under a root directory. Names may contain `/` to reach
+/// into subdirectories, but can't leave the root.
+pub struct FileModuleLoader {
+    root: PathBuf,
+}
+
+impl FileModuleLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ModuleLoader for FileModuleLoader {
+    fn load(&self, name: &str) -> io::Result<String> {
+        let inside_root = Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if name.is_empty() || !inside_root {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "name leaves the module root",
+            ));
+        }
+        // Appended rather than set, so a dotted name like `utils.v2` keeps its dot.
+        std::fs::read_to_string(self.root.join(format!("{}.lox", name)))
+    }
+}
+
+/// Serves modules registered up front, such as ones embedded into the host binary.
+#[derive(Default)]
+pub struct MemoryModuleLoader {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.modules.insert(name.into(), source.into());
+    }
+}
+
+impl ModuleLoader for MemoryModuleLoader {
+    fn load(&self, name: &str) -> io::Result<String> {
+        self.modules
+            .get(name)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not registered"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    /// A loader over a fresh directory holding the modules given by name.
+    fn loader(test: &str, modules: &[(&str, &str)]) -> FileModuleLoader {
+        let root = env::temp_dir().join(format!("ruslox-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, source) in modules {
+            let path = root.join(format!("{}.lox", name));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        FileModuleLoader::new(root)
+    }
+
+    #[test]
+    fn names_map_to_files_under_the_root() {
+        let loader = loader(
+            "names",
+            &[("utils", "1;"), ("utils.v2", "2;"), ("nested/deep", "3;")],
+        );
+        assert_eq!(loader.load("utils").unwrap(), "1;");
+        assert_eq!(loader.load("utils.v2").unwrap(), "2;");
+        assert_eq!(loader.load("nested/deep").unwrap(), "3;");
+        assert_eq!(
+            loader.load("missing").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_dir_all(&loader.root).unwrap();
+    }
+
+    #[test]
+    fn names_cannot_leave_the_root() {
+        let loader = loader("escape", &[("inside", "1;")]);
+        for name in [
+            "../inside",
+            "nested/../../inside",
+            "/inside",
+            "./inside",
+            "",
+        ] {
+            let error = loader.load(name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+        fs::remove_dir_all(&loader.root).unwrap();
+    }
+}
@@ -0,0 +1,27 @@
+//! How numbers are printed, shared by `print` and the disassembler so the two always agree.
+
+use std::fmt::{self, Display};
+
+/// A number formatted the way Lox prints it:
+/// - whole numbers have no fractional part, so `3.0` prints as `3`, and `-0` keeps its sign;
+/// - magnitudes from `1e-7` up to `1e21` are written out in full, and others in scientific
+///   notation, as `1e21` or `2.5e-8`;
+/// - infinities print as `inf` and `-inf`, and NaN as `nan` whatever its sign.
+pub fn display(number: f64) -> impl Display {
+    Number(number)
+}
+
+struct Number(f64);
+
+impl Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = self.0;
+        if number.is_nan() {
+            write!(f, "nan")
+        } else if number.is_infinite() || number == 0.0 || (1e-7..1e21).contains(&number.abs()) {
+            write!(f, "{}", number)
+        } else {
+            write!(f, "{:e}", number)
+        }
+    }
+}
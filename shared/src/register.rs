@@ -0,0 +1,111 @@
+//! Bytecode of the experimental register machine.
+//!
+//! Every operand names a register in the current window, except constant indices and jump
+//! targets. Jump targets are absolute instruction indices, which keeps backpatching trivial.
+
+use std::ops::Range;
+
+use crate::{chunk::jump_error, constant::Constant, error::InterpretResult};
+
+pub type Register = u8;
+
+#[rustfmt::skip]
+pub enum RegisterInstruction {
+    // Loads and moves.
+    LoadConstant(Register, u8), LoadNil(Register), LoadTrue(Register), LoadFalse(Register),
+    Move(Register, Register),
+
+    // Globals, addressed by the constant index of their names.
+    DefineGlobal(u8, Register), GetGlobal(Register, u8), SetGlobal(u8, Register),
+
+    // Arithmetic instructions, destination first.
+    Add(Register, Register, Register), Subtract(Register, Register, Register),
+    Multiply(Register, Register, Register), Divide(Register, Register, Register),
+    Negate(Register, Register),
+
+    // Logic instructions, destination first.
+    Not(Register, Register), Equal(Register, Register, Register),
+    Greater(Register, Register, Register), Less(Register, Register, Register),
+
+    // Control flow.
+    Jump(u16), JumpFalse(Register, u16), JumpTrue(Register, u16),
+
+    // Callee in the register, arguments in the registers following it, result in the callee's.
+    Call(Register, u8),
+
+    // Miscellaneous.
+    Print(Register), Return,
+}
+
+pub struct RegisterChunk {
+    pub file_id: usize,
+    pub code: Vec<RegisterInstruction>,
+    pub positions: Vec<Range<usize>>,
+    pub constants: Vec<Constant>,
+    pub register_count: usize,
+}
+
+impl RegisterChunk {
+    pub fn new(file_id: usize) -> Self {
+        Self {
+            file_id,
+            code: Vec::new(),
+            positions: Vec::new(),
+            constants: Vec::with_capacity(u8::MAX as usize + 1),
+            register_count: 0,
+        }
+    }
+
+    pub fn write(&mut self, instruction: RegisterInstruction, position: &Range<usize>) -> usize {
+        self.code.push(instruction);
+        self.positions.push(position.clone());
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Constant) -> Option<u8> {
+        if self.constants.len() > u8::MAX as usize {
+            return None;
+        }
+        self.constants.push(value);
+        Some((self.constants.len() - 1) as u8)
+    }
+
+    /// Points the jump at `offset` to the next instruction to be written.
+    pub fn patch(&mut self, offset: usize, position: &Range<usize>) -> InterpretResult {
+        let target = match u16::try_from(self.code.len()) {
+            Ok(target) => target,
+            Err(_) => {
+                return Err(jump_error(
+                    self.file_id,
+                    position,
+                    "E0014",
+                    "too much code to jump over",
+                ))
+            }
+        };
+        match &mut self.code[offset] {
+            RegisterInstruction::Jump(destination)
+            | RegisterInstruction::JumpFalse(_, destination)
+            | RegisterInstruction::JumpTrue(_, destination) => *destination = target,
+            _ => unreachable!("internal error when backpatch"),
+        }
+        Ok(())
+    }
+
+    /// Writes a loop back to the instruction at `start`. Targets are absolute, so it's the
+    /// start of the loop rather than its body which must fit.
+    pub fn write_loop(&mut self, start: usize, position: &Range<usize>) -> InterpretResult {
+        match u16::try_from(start) {
+            Ok(target) => {
+                self.write(RegisterInstruction::Jump(target), position);
+                Ok(())
+            }
+            Err(_) => Err(jump_error(
+                self.file_id,
+                position,
+                "E0014",
+                "too much code to jump over",
+            )),
+        }
+    }
+}
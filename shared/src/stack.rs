@@ -1,64 +1,88 @@
 use std::{
-    mem::{self, ManuallyDrop},
+    fmt::{self, Debug},
     ops::{Index, IndexMut},
-    ptr,
+    slice,
 };
 
 use crate::error::{ErrorItem, InterpretError, InterpretResult};
 
-const DEFAULT_STACK_CAPACITY: usize = u8::MAX as usize + 1;
+pub const DEFAULT_STACK_CAPACITY: usize = u8::MAX as usize + 1;
 
+/// A stack reporting overflow and underflow as diagnostics. Values live on the heap, with room
+/// for `N` of them reserved up front; `N` is also the limit until [`Stack::set_limit`] changes it.
 pub struct Stack<T, const N: usize = DEFAULT_STACK_CAPACITY> {
-    data: [ManuallyDrop<T>; N],
-    top: usize,
+    data: Vec<T>,
+    // Pushing beyond this many values overflows.
+    limit: usize,
+}
+
+impl<T, const N: usize> Default for Stack<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T, const N: usize> Stack<T, N> {
     pub fn new() -> Self {
         Self {
-            data: unsafe { mem::zeroed() },
-            top: 0,
+            data: Vec::with_capacity(N),
+            limit: N,
         }
     }
 
+    /// Makes the stack overflow once it holds `limit` values. Values already above the limit
+    /// stay until they are popped.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
     pub fn push(&mut self, value: T) -> InterpretResult {
-        if self.top >= N {
+        if self.data.len() >= self.limit {
             return Err(InterpretError::Simple(
                 ErrorItem::error()
                     .with_code("E1001")
                     .with_message("stack overflow"),
             ));
         }
-        unsafe {
-            ptr::write(&mut self.data[self.top], ManuallyDrop::new(value));
-        }
-        self.top += 1;
+        self.data.push(value);
         Ok(())
     }
 
     pub fn pop(&mut self) -> InterpretResult<T> {
-        if self.is_empty() {
-            return Err(InterpretError::Simple(
+        self.data.pop().ok_or_else(|| {
+            InterpretError::Simple(
                 ErrorItem::error()
                     .with_code("E1002")
                     .with_message("stack underflow"),
-            ));
-        }
-        self.top -= 1;
-        let slot = unsafe { ptr::read(&mut self.data[self.top]) };
-        Ok(ManuallyDrop::into_inner(slot))
+            )
+        })
     }
 
     pub fn peek(&self) -> Option<&T> {
-        if self.top > 0 {
-            Some(&self.data[self.top - 1])
-        } else {
-            None
-        }
+        self.data.last()
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.data.last_mut()
+    }
+
+    /// Values from the bottom of the stack to the top.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Pops every value above the first `len`, dropping them. Does nothing if the stack holds
+    /// no more than `len` values.
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
     }
 
     pub fn len(&self) -> usize {
-        self.top
+        self.data.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -66,15 +90,23 @@ impl<T, const N: usize> Stack<T, N> {
     }
 
     pub fn clear(&mut self) {
-        while !self.is_empty() {
-            mem::drop(self.pop().unwrap());
-        }
+        self.data.clear();
     }
 }
 
-impl<T, const N: usize> Drop for Stack<T, N> {
-    fn drop(&mut self) {
-        self.clear();
+/// Lists the values from the bottom of the stack to the top.
+impl<T: Debug, const N: usize> Debug for Stack<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a Stack<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
@@ -82,7 +114,7 @@ impl<T, const N: usize> Index<usize> for Stack<T, N> {
     type Output = T;
 
     fn index(&self, index: usize) -> &Self::Output {
-        if index >= self.top {
+        if index >= self.len() {
             panic!("Index {} out of stack size {}", index, self.len());
         }
         &self.data[index]
@@ -91,7 +123,7 @@ impl<T, const N: usize> Index<usize> for Stack<T, N> {
 
 impl<T, const N: usize> IndexMut<usize> for Stack<T, N> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        if index >= self.top {
+        if index >= self.len() {
             panic!("Index {} out of stack size {}", index, self.len());
         }
         &mut self.data[index]